@@ -209,7 +209,9 @@ pub fn evaluate(input: &str) -> Result<i64, &'static str> {
     let new_input = preprocess(input, &mut buffer)?;
     use nom::Finish;
     match parse_equation(new_input).finish() {
-        Ok((_remaining, value)) => {
+        // anything the parser stopped at means the expression isn't finished
+        Ok((remaining, _)) if !remaining.trim().is_empty() => Err("Unexpected input"),
+        Ok((_, value)) => {
             Ok(value)
         },
         Err(_) => Err("Failed to evaluate expression"),
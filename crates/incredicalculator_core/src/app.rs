@@ -1,8 +1,31 @@
+use crate::keymap::Keymaps;
+use crate::legend::KeyLegend;
+use crate::overlay::Overlays;
 use crate::platform::IcPlatform;
-use crate::input::{IcKey, KeyState};
+use crate::prefs::Prefs;
+use crate::state::{StateReader, StateWriter};
+use crate::theme::Palette;
+use crate::input::{IcKey, KeyEventKind, KeyState};
+use glam::IVec2;
+use rgb::RGB8;
+
+// Part of the screen an app may draw in, the rest belongs to the shell.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Viewport {
+    pub pos: IVec2,
+    pub size: IVec2,
+}
 
 pub struct InputContext<'a> {
-    pub key_states: &'a [KeyState; IcKey::COUNT]
+    pub key_states: &'a [KeyState; IcKey::COUNT],
+    // set by one-shot or locked modifiers, on top of the key being held
+    pub shift_latched: bool,
+    pub super_latched: bool,
+    pub viewport: Viewport,
+    pub prefs: &'a Prefs,
+    pub keymaps: &'a Keymaps,
+    pub palette: &'a Palette,
+    pub overlays: &'a Overlays,
 }
 
 impl<'a> InputContext<'a> {
@@ -10,16 +33,103 @@ impl<'a> InputContext<'a> {
         self.key_states[key as usize].is_down
     }
     pub fn is_shifted(&self) -> bool {
-        self.key_states[IcKey::Shift as usize].is_down
+        self.key_states[IcKey::Shift as usize].is_down || self.shift_latched
     }
     pub fn is_super(&self) -> bool {
-        self.key_states[IcKey::Super as usize].is_down
+        self.key_states[IcKey::Super as usize].is_down || self.super_latched
+    }
+}
+
+// What the launcher shows for an app. The icon is a few characters drawn in
+// the middle of the tile.
+#[derive(Clone, Copy)]
+pub struct AppInfo {
+    pub name: &'static str,
+    pub icon: &'static str,
+    pub color: RGB8,
+}
+
+// When an app wants update() called again. Input always causes an update on
+// top of this, so apps that only change on key presses can stay Idle.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FrameRequest {
+    Continuous,
+    AfterMs(u32),
+    Idle,
+}
+
+impl FrameRequest {
+    // the request that wakes up first
+    pub fn sooner(self, other: FrameRequest) -> FrameRequest {
+        match (self, other) {
+            (FrameRequest::Continuous, _) | (_, FrameRequest::Continuous) => {
+                FrameRequest::Continuous
+            }
+            (FrameRequest::AfterMs(a), FrameRequest::AfterMs(b)) => FrameRequest::AfterMs(a.min(b)),
+            (FrameRequest::AfterMs(a), FrameRequest::Idle)
+            | (FrameRequest::Idle, FrameRequest::AfterMs(a)) => FrameRequest::AfterMs(a),
+            (FrameRequest::Idle, FrameRequest::Idle) => FrameRequest::Idle,
+        }
     }
 }
 
 pub trait IcApp {
     fn on_enter(&mut self);
+    // another app or the launcher took over the screen
+    fn on_exit(&mut self) {}
+    // the device is going to sleep / woke up while this app was active
+    fn on_suspend(&mut self) {}
+    fn on_resume(&mut self) {}
+    // called every frame for apps that are not on screen, for things like
+    // timers that have to keep running. now_ms is platform.millis()
+    fn background_tick(&mut self, _now_ms: u64) {}
     fn on_key(&mut self, key: IcKey, ctx: &InputContext);
+    // Every key event goes through here. The default passes plain presses on
+    // to on_key, apps that want repeats, long presses or double taps override it.
+    fn on_key_event(&mut self, key: IcKey, kind: KeyEventKind, ctx: &InputContext) {
+        if kind == KeyEventKind::Press {
+            self.on_key(key, ctx);
+        }
+    }
+    // Answer to a dialog opened with ctx.overlays.ask(). choice is the index
+    // of the chosen button, None if the dialog was cancelled.
+    fn on_dialog_result(&mut self, _id: u32, _choice: Option<usize>, _ctx: &InputContext) {}
     fn update(&mut self, platform: &mut dyn IcPlatform, ctx: &InputContext);
+    // Labels for what each key does right now on each modifier layer. The
+    // shell adds its own shortcuts on top.
+    fn key_legend(&self, _legend: &mut KeyLegend) {}
+    // short state shown in the status bar next to the app name, eg. "HEX"
+    fn mode_text(&self) -> &str {
+        ""
+    }
+    // Called once when the app is registered. Keep the returned ids to read
+    // the values later through InputContext::prefs.
+    fn register_prefs(&mut self, _prefs: &mut Prefs) {}
+    // after the saved values are loaded and whenever one of them changes
+    fn on_prefs_changed(&mut self, _prefs: &Prefs) {}
+    // Called once when the app is registered, for key layouts the user can
+    // change in the settings app.
+    fn register_keymaps(&mut self, _keymaps: &mut Keymaps) {}
+    // after the saved overrides are loaded and whenever a binding changes,
+    // rebuild the action tables with Keymaps::table()
+    fn on_keymaps_changed(&mut self, _keymaps: &Keymaps) {}
+    // Working state that should survive a power cycle, like unfinished input.
+    // The shell stores it a few seconds after the last key and before the
+    // device sleeps, and hands it back on boot. Bump state_version whenever
+    // the layout written by save_state changes.
+    fn state_version(&self) -> u16 {
+        1
+    }
+    fn save_state(&self, _w: &mut StateWriter) {}
+    // version is what state_version returned when the data was written, so
+    // old layouts can be migrated. None means the data was refused, the app
+    // should then be left as it was.
+    fn restore_state(&mut self, _version: u16, _r: &mut StateReader) -> Option<()> {
+        None
+    }
+    // asked after every update
+    fn frame_request(&self) -> FrameRequest {
+        FrameRequest::Idle
+    }
 }
 
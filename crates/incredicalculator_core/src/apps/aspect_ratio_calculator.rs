@@ -1,80 +1,34 @@
-use crate::input::{IcKey, KeyState};
+use crate::input::IcKey;
+use crate::keymap::{Binding, KeyTable, KeymapDef, KeymapId, Keymaps};
+use crate::legend::{KeyLayer, KeyLegend};
+use crate::ui::{FieldStyle, FocusManager, NavDir, NumField};
 use glam::IVec2;
 use num_traits::{abs, clamp_max};
 use num_traits::float::FloatCore;
-use rgb::{RGB8, Rgb};
+use rgb::RGB8;
+
+use crate::state::{StateReader, StateWriter};
+use alloc::format;
+use alloc::string::String;
+use crate::theme::Palette;
 
 use crate::{
     app::IcApp,
-    platform::{self, IcPlatform},
-    text::{draw_text, draw_text_f},
+    text::draw_text,
 };
 
-struct NumInputBox {
-    value: i32,
-    pos: IVec2,
-    size: IVec2,
-}
-
-impl NumInputBox {
-    fn new(pos: IVec2, size: IVec2) -> NumInputBox {
-        NumInputBox {
-            value: 0,
-            pos: pos,
-            size: size,
-        }
-    }
-    fn append_digit(&mut self, digit: u32) {
-        self.value = self.value * 10 + clamp_max(digit as i32, 9);
-    }
-    fn backspace(&mut self) {
-        self.value /= 10;
-    }
-    fn clear(&mut self) {
-        self.value = 0;
-    }
-    fn draw(&mut self, platform: &mut dyn crate::platform::IcPlatform) {
-        platform.draw_rectangle(
-            self.pos,
-            self.pos + self.size,
-            RGB8::new(0xAD, 0x4B, 0x27),
-            2,
-            Some(RGB8::new(0xff, 0xff, 0xff)),
-        );
-        let margin = 4;
-        draw_text_f(
-            platform,
-            format_args!("{}", self.value),
-            (self.pos.x + margin) as f32,
-            (self.pos.y + margin) as f32,
-            4.0,
-            RGB8::new(0, 0, 0),
-        );
-    }
-    fn draw_highlight(&mut self, platform: &mut dyn crate::platform::IcPlatform) {
-        platform.draw_rectangle(
-            self.pos,
-            self.pos + self.size,
-            RGB8::new(0, 0, 0xff),
-            3,
-            None,
-        );
-    }
-}
-
-enum FocusUi {
-    Width1,
-    Height1,
-    Width2,
-    Height2,
-}
+const WIDTH1: usize = 0;
+const HEIGHT1: usize = 1;
+const WIDTH2: usize = 2;
+const HEIGHT2: usize = 3;
+const FIELD_COUNT: usize = 4;
 
 pub struct AspectRatioCalculator {
-    focused_ui: FocusUi,
-    input_box_width1: NumInputBox,
-    input_box_height1: NumInputBox,
-    input_box_width2: NumInputBox,
-    input_box_height2: NumInputBox,
+    focus: FocusManager<FIELD_COUNT>,
+    fields: [NumField; FIELD_COUNT],
+    palette: Palette,
+    keys: KeyTable<KeyAction>,
+    keymap_id: Option<KeymapId>,
 }
 
 #[derive(Clone, Copy, PartialEq, Eq)]
@@ -89,124 +43,121 @@ enum KeyAction {
     Clear,
 }
 
+impl KeyAction {
+    // for the key legend
+    fn label(&self) -> String {
+        match *self {
+            KeyAction::InsertDigit(d) => format!("{}", d),
+            KeyAction::MoveLeft => String::from("<"),
+            KeyAction::MoveRight => String::from(">"),
+            KeyAction::MoveUp => String::from("^"),
+            KeyAction::MoveDown => String::from("v"),
+            KeyAction::Backspace => String::from("Bk"),
+            KeyAction::Enter => String::from("="),
+            KeyAction::Clear => String::from("Clr"),
+        }
+    }
+}
+
+const ASPECT_ACTIONS: &[(&str, KeyAction)] = &[
+    ("0", KeyAction::InsertDigit(0)),
+    ("1", KeyAction::InsertDigit(1)),
+    ("2", KeyAction::InsertDigit(2)),
+    ("3", KeyAction::InsertDigit(3)),
+    ("4", KeyAction::InsertDigit(4)),
+    ("5", KeyAction::InsertDigit(5)),
+    ("6", KeyAction::InsertDigit(6)),
+    ("7", KeyAction::InsertDigit(7)),
+    ("8", KeyAction::InsertDigit(8)),
+    ("9", KeyAction::InsertDigit(9)),
+    ("left", KeyAction::MoveLeft),
+    ("right", KeyAction::MoveRight),
+    ("up", KeyAction::MoveUp),
+    ("down", KeyAction::MoveDown),
+    ("bksp", KeyAction::Backspace),
+    ("enter", KeyAction::Enter),
+    ("clear", KeyAction::Clear),
+];
+
+const ASPECT_KEYS: &[Binding] = &[
+    (IcKey::Num0, KeyLayer::Base, "0"),
+    (IcKey::Num1, KeyLayer::Base, "1"),
+    (IcKey::Num2, KeyLayer::Base, "2"),
+    (IcKey::Num3, KeyLayer::Base, "3"),
+    (IcKey::Num4, KeyLayer::Base, "4"),
+    (IcKey::Num5, KeyLayer::Base, "5"),
+    (IcKey::Num6, KeyLayer::Base, "6"),
+    (IcKey::Num7, KeyLayer::Base, "7"),
+    (IcKey::Num8, KeyLayer::Base, "8"),
+    (IcKey::Num9, KeyLayer::Base, "9"),
+    (IcKey::Func1, KeyLayer::Base, "bksp"),
+    (IcKey::Func6, KeyLayer::Base, "enter"),
+    (IcKey::Num1, KeyLayer::Super, "down"),
+    (IcKey::Num2, KeyLayer::Super, "down"),
+    (IcKey::Num4, KeyLayer::Super, "left"),
+    (IcKey::Num6, KeyLayer::Super, "right"),
+    (IcKey::Num7, KeyLayer::Super, "up"),
+    (IcKey::Num8, KeyLayer::Super, "up"),
+    (IcKey::Num9, KeyLayer::Super, "clear"),
+];
+
 impl AspectRatioCalculator {
     pub fn new() -> AspectRatioCalculator {
+        let palette = Palette::from_index(0);
+        let style = palette.field_style(FieldStyle {
+            border_width: 2,
+            highlight: Some(palette.accent),
+            ..FieldStyle::default()
+        });
+        let size = IVec2::new(129, 33);
+        let mut focus = FocusManager::new([
+            IVec2::new(0, 0),
+            IVec2::new(0, 1),
+            IVec2::new(1, 0),
+            IVec2::new(1, 1),
+        ]);
+        focus.wrap_rows = true;
         AspectRatioCalculator {
-            focused_ui: FocusUi::Width1,
-            input_box_width1: NumInputBox::new(IVec2::new(17, 39), IVec2::new(129, 33)),
-            input_box_width2: NumInputBox::new(IVec2::new(174, 39), IVec2::new(129, 33)),
-            input_box_height1: NumInputBox::new(IVec2::new(17, 107), IVec2::new(129, 33)),
-            input_box_height2: NumInputBox::new(IVec2::new(174, 107), IVec2::new(129, 33)),
+            focus,
+            fields: [
+                NumField::new(IVec2::new(17, 39), size, style),
+                NumField::new(IVec2::new(17, 107), size, style),
+                NumField::new(IVec2::new(174, 39), size, style),
+                NumField::new(IVec2::new(174, 107), size, style),
+            ],
+            palette,
+            keys: KeyTable::new(ASPECT_ACTIONS, ASPECT_KEYS),
+            keymap_id: None,
         }
     }
 
     fn get_action(&self, key: IcKey, is_shifted: bool, is_super: bool) -> Option<KeyAction> {
-        if is_shifted {
-            match key {
-                IcKey::Num0 => None,
-                IcKey::Num1 => None,
-                IcKey::Num2 => None,
-                IcKey::Num3 => None,
-                IcKey::Num4 => None,
-                IcKey::Num5 => None,
-                IcKey::Num6 => None,
-                IcKey::Num7 => None,
-                IcKey::Num8 => None,
-                IcKey::Num9 => None,
-                IcKey::Func1 => None,
-                IcKey::Func2 => None,
-                IcKey::Func3 => None,
-                IcKey::Func4 => None,
-                IcKey::Func5 => None,
-                IcKey::Func6 => None,
-                IcKey::Shift => None,
-                IcKey::Super => None,
-                IcKey::_Max => None,
-            }
-        } else if is_super {
-            match key {
-                IcKey::Num0 => None,
-                IcKey::Num1 => Some(KeyAction::MoveDown),
-                IcKey::Num2 => Some(KeyAction::MoveDown),
-                IcKey::Num3 => None,
-                IcKey::Num4 => Some(KeyAction::MoveLeft),
-                IcKey::Num5 => None,
-                IcKey::Num6 => Some(KeyAction::MoveRight),
-                IcKey::Num7 => Some(KeyAction::MoveUp),
-                IcKey::Num8 => Some(KeyAction::MoveUp),
-                IcKey::Num9 => Some(KeyAction::Clear),
-                IcKey::Func1 => None,
-                IcKey::Func2 => None,
-                IcKey::Func3 => None,
-                IcKey::Func4 => None,
-                IcKey::Func5 => None,
-                IcKey::Func6 => None,
-                IcKey::Shift => None,
-                IcKey::Super => None,
-                IcKey::_Max => None,
-            }
-        } else {
-            match key {
-                IcKey::Num0 => Some(KeyAction::InsertDigit(0)),
-                IcKey::Num1 => Some(KeyAction::InsertDigit(1)),
-                IcKey::Num2 => Some(KeyAction::InsertDigit(2)),
-                IcKey::Num3 => Some(KeyAction::InsertDigit(3)),
-                IcKey::Num4 => Some(KeyAction::InsertDigit(4)),
-                IcKey::Num5 => Some(KeyAction::InsertDigit(5)),
-                IcKey::Num6 => Some(KeyAction::InsertDigit(6)),
-                IcKey::Num7 => Some(KeyAction::InsertDigit(7)),
-                IcKey::Num8 => Some(KeyAction::InsertDigit(8)),
-                IcKey::Num9 => Some(KeyAction::InsertDigit(9)),
-                IcKey::Func1 => Some(KeyAction::Backspace),
-                IcKey::Func2 => None,
-                IcKey::Func3 => None,
-                IcKey::Func4 => None,
-                IcKey::Func5 => None,
-                IcKey::Func6 => Some(KeyAction::Enter),
-                IcKey::Shift => None,
-                IcKey::Super => None,
-                IcKey::_Max => None,
-            }
-        }
+        self.keys.get(key, is_shifted, is_super)
     }
 
-    fn get_focused_input_box(&mut self) -> &mut NumInputBox {
-        match self.focused_ui {
-            FocusUi::Width1 => &mut self.input_box_width1,
-            FocusUi::Height1 => &mut self.input_box_height1,
-            FocusUi::Width2 => &mut self.input_box_width2,
-            FocusUi::Height2 => &mut self.input_box_height2,
-        }
+    fn get_focused_input_box(&mut self) -> &mut NumField {
+        &mut self.fields[self.focus.focused()]
     }
 
     fn has_any_zeroes(&self) -> bool {
-        self.input_box_width1.value <= 0
-            || self.input_box_height1.value <= 0
-            || self.input_box_width2.value <= 0
-            || self.input_box_height2.value <= 0
+        self.fields.iter().any(|f| f.value <= 0)
     }
 
     fn update_math(&mut self) {
         // w1/h1 side is called ratio and w2/h2 side is called result
-        let w1 = self.input_box_width1.value as f32;
-        let h1 = self.input_box_height1.value as f32;
-        let w2 = self.input_box_width2.value as f32;
-        let h2 = self.input_box_height2.value as f32;
+        let w1 = self.fields[WIDTH1].value as f32;
+        let h1 = self.fields[HEIGHT1].value as f32;
+        let w2 = self.fields[WIDTH2].value as f32;
+        let h2 = self.fields[HEIGHT2].value as f32;
         if w1 == 0.0 || h1 == 0.0 {
             return;
         }
-        match self.focused_ui {
-            FocusUi::Width1 => {
-                self.input_box_width2.value = ((w1 / h1) * h2).round() as i32;
-            }
-            FocusUi::Height1 => {
-                self.input_box_height2.value = ((h1 / w1) * w2).round() as i32;
-            }
-            FocusUi::Width2 => {
-                self.input_box_height2.value = ((h1 / w1) * w2).round() as i32;
+        match self.focus.focused() {
+            WIDTH1 | HEIGHT2 => {
+                self.fields[WIDTH2].value = ((w1 / h1) * h2).round() as i32;
             }
-            FocusUi::Height2 => {
-                self.input_box_width2.value = ((w1 / h1) * h2).round() as i32;
+            _ => {
+                self.fields[HEIGHT2].value = ((h1 / w1) * w2).round() as i32;
             }
         }
     }
@@ -218,7 +169,7 @@ impl AspectRatioCalculator {
         bottom_right: IVec2,
     ) {
         // first the decorations around the rectangle is drawn and then the acutal screen rectangle you want
-        let base_col = RGB8::new(0, 0, 0);
+        let base_col = self.palette.text;
         let bezel_w = 3;
         platform.draw_rectangle(
             top_left - IVec2::new(bezel_w, bezel_w),
@@ -308,7 +259,7 @@ impl AspectRatioCalculator {
         let height: i32 = 60;
         let top_y = 159;
         let width_to_height =
-            self.input_box_width1.value as f32 / self.input_box_height1.value as f32;
+            self.fields[WIDTH1].value as f32 / self.fields[HEIGHT1].value as f32;
         let width = clamp_max((height as f32 * width_to_height) as i32, 320);
         let center_x = 320 / 2;
         let top_left = IVec2::new(center_x - width / 2, top_y);
@@ -322,6 +273,44 @@ impl IcApp for AspectRatioCalculator {
         ()
     }
 
+    fn key_legend(&self, legend: &mut KeyLegend) {
+        legend.fill(|key, layer| {
+            let (shifted, sup) = layer.modifiers();
+            self.get_action(key, shifted, sup).map(|a| a.label())
+        });
+    }
+
+    fn register_keymaps(&mut self, keymaps: &mut Keymaps) {
+        let def = KeymapDef::new("aspect", "Aspect ratio", ASPECT_ACTIONS, ASPECT_KEYS);
+        self.keymap_id = Some(keymaps.register(def));
+    }
+
+    fn on_keymaps_changed(&mut self, keymaps: &Keymaps) {
+        if let Some(id) = self.keymap_id {
+            self.keys = keymaps.table(id, ASPECT_ACTIONS);
+        }
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        for field in self.fields.iter() {
+            w.i32(field.value);
+        }
+    }
+
+    fn restore_state(&mut self, version: u16, r: &mut StateReader) -> Option<()> {
+        if version != self.state_version() {
+            return None;
+        }
+        let mut values = [0; FIELD_COUNT];
+        for v in values.iter_mut() {
+            *v = r.i32()?;
+        }
+        for (field, v) in self.fields.iter_mut().zip(values) {
+            field.value = v;
+        }
+        Some(())
+    }
+
     fn on_key(&mut self, key: crate::input::IcKey, ctx: &crate::app::InputContext) {
         let action = self.get_action(key, ctx.is_shifted(), ctx.is_super());
         match action {
@@ -330,36 +319,16 @@ impl IcApp for AspectRatioCalculator {
                 self.update_math();
             }
             Some(KeyAction::MoveUp) => {
-                self.focused_ui = match self.focused_ui {
-                    FocusUi::Width1 => FocusUi::Width1,
-                    FocusUi::Height1 => FocusUi::Width1,
-                    FocusUi::Width2 => FocusUi::Width2,
-                    FocusUi::Height2 => FocusUi::Width2,
-                }
+                self.focus.navigate(NavDir::Up);
             }
             Some(KeyAction::MoveDown) => {
-                self.focused_ui = match self.focused_ui {
-                    FocusUi::Width1 => FocusUi::Height1,
-                    FocusUi::Height1 => FocusUi::Height1,
-                    FocusUi::Width2 => FocusUi::Height2,
-                    FocusUi::Height2 => FocusUi::Height2,
-                }
+                self.focus.navigate(NavDir::Down);
             }
             Some(KeyAction::MoveLeft) => {
-                self.focused_ui = match self.focused_ui {
-                    FocusUi::Width1 => FocusUi::Width1,
-                    FocusUi::Height1 => FocusUi::Width2,
-                    FocusUi::Width2 => FocusUi::Width1,
-                    FocusUi::Height2 => FocusUi::Height1,
-                }
+                self.focus.navigate(NavDir::Left);
             }
             Some(KeyAction::MoveRight) => {
-                self.focused_ui = match self.focused_ui {
-                    FocusUi::Width1 => FocusUi::Width2,
-                    FocusUi::Height1 => FocusUi::Height2,
-                    FocusUi::Width2 => FocusUi::Height1,
-                    FocusUi::Height2 => FocusUi::Height2,
-                }
+                self.focus.navigate(NavDir::Right);
             }
             Some(KeyAction::Backspace) => {
                 self.get_focused_input_box().backspace();
@@ -369,14 +338,7 @@ impl IcApp for AspectRatioCalculator {
                 self.get_focused_input_box().clear();
                 self.update_math();
             }
-            Some(KeyAction::Enter) => {
-                self.focused_ui = match self.focused_ui {
-                    FocusUi::Width1 => FocusUi::Height1,
-                    FocusUi::Height1 => FocusUi::Width2,
-                    FocusUi::Width2 => FocusUi::Height2,
-                    FocusUi::Height2 => FocusUi::Width1,
-                }
-            }
+            Some(KeyAction::Enter) => self.focus.next(),
             None => (),
         }
     }
@@ -386,42 +348,46 @@ impl IcApp for AspectRatioCalculator {
         platform: &mut dyn crate::platform::IcPlatform,
         ctx: &crate::app::InputContext,
     ) {
-        platform.clear(RGB8::new(0xff, 0xb3, 0x3f));
+        if self.palette != *ctx.palette {
+            self.palette = *ctx.palette;
+            for field in self.fields.iter_mut() {
+                field.style = self.palette.field_style(field.style);
+            }
+        }
+        platform.clear(self.palette.background);
         draw_text(
             platform,
             "ASPECT RATIO CALCULATOR",
             10.0,
-            10.0,
+            ctx.viewport.pos.y as f32 + 3.0,
             2.0,
-            RGB8::new(0, 0, 0),
+            self.palette.text,
         );
-        self.input_box_width1.draw(platform);
-        self.input_box_height1.draw(platform);
-        self.input_box_width2.draw(platform);
-        self.input_box_height2.draw(platform);
-        self.get_focused_input_box().draw_highlight(platform);
+        for (id, field) in self.fields.iter().enumerate() {
+            field.draw(platform, self.focus.is_focused(id));
+        }
         platform.draw_line(
             IVec2::new(152, 83),
             IVec2::new(152 + 17, 83),
-            RGB8::new(0, 0, 0),
+            self.palette.text,
             3,
         );
         platform.draw_line(
             IVec2::new(152, 93),
             IVec2::new(152 + 17, 93),
-            RGB8::new(0, 0, 0),
+            self.palette.text,
             3,
         );
         platform.draw_line(
             IVec2::new(17, 89),
             IVec2::new(17 + 129, 89),
-            RGB8::new(0, 0, 0),
+            self.palette.text,
             3,
         );
         platform.draw_line(
             IVec2::new(175, 89),
             IVec2::new(175 + 129, 89),
-            RGB8::new(0, 0, 0),
+            self.palette.text,
             3,
         );
         self.draw_ratio_visualizer(platform);
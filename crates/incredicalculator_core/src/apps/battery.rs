@@ -0,0 +1,114 @@
+use crate::app::{FrameRequest, IcApp, InputContext};
+use crate::input::IcKey;
+use crate::platform::IcPlatform;
+use crate::font::Align;
+use crate::text::{draw_text, draw_text_f};
+use glam::IVec2;
+
+// the gauge only updates every few seconds
+const REFRESH_MS: u32 = 2000;
+const ROW_H: f32 = 24.0;
+const VALUE_X: f32 = 130.0;
+
+// Everything the platform knows about the battery.
+pub struct BatteryApp {}
+
+impl Default for BatteryApp {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BatteryApp {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl IcApp for BatteryApp {
+    fn on_enter(&mut self) {}
+
+    fn on_key(&mut self, _key: IcKey, _ctx: &InputContext) {}
+
+    fn update(&mut self, platform: &mut dyn IcPlatform, ctx: &InputContext) {
+        let palette = ctx.palette;
+        platform.clear(palette.background);
+        let soc = platform.get_battery_soc();
+        let charging = platform.is_charging();
+        let voltage = platform.battery_voltage();
+        let rate = platform.battery_charge_rate();
+        let top = ctx.viewport.pos.y as f32 + 10.0;
+
+        // big percentage next to a bar
+        if soc < 0 {
+            draw_text(platform, "--%", 16.0, top, 5.0, palette.text);
+        } else {
+            draw_text_f(platform, format_args!("{}%", soc), 16.0, top, 5.0, palette.text);
+        }
+        let bar_pos = IVec2::new(180, top as i32 + 4);
+        let bar_size = IVec2::new(120, 32);
+        let fill_w = soc.clamp(0, 100) * (bar_size.x - 4) / 100;
+        let fill = if charging { palette.accent } else { palette.text };
+        platform.draw_rectangle(bar_pos, bar_pos + bar_size, palette.dim, 2, None);
+        platform.draw_rectangle(
+            bar_pos + IVec2::new(2, 2),
+            bar_pos + IVec2::new(2 + fill_w, bar_size.y - 2),
+            fill,
+            0,
+            Some(fill),
+        );
+
+        let labels = ["Status", "Voltage", "Rate", if charging { "Full in" } else { "Time left" }];
+        let row_y = |i: usize| top + 56.0 + i as f32 * ROW_H;
+        for (i, label) in labels.iter().enumerate() {
+            draw_text(platform, label, 16.0, row_y(i), 2.0, palette.dim);
+        }
+        let status = match (charging, rate) {
+            (true, _) => "Charging",
+            (false, Some(r)) if r < 0.0 => "Discharging",
+            (false, Some(_)) => "Idle",
+            (false, None) => "On battery",
+        };
+        draw_text(platform, status, VALUE_X, row_y(0), 2.0, palette.text);
+        match voltage {
+            Some(v) => draw_text_f(platform, format_args!("{:.2} V", v), VALUE_X, row_y(1), 2.0, palette.text),
+            None => draw_text(platform, "n/a", VALUE_X, row_y(1), 2.0, palette.text),
+        }
+        match rate {
+            Some(r) => draw_text_f(platform, format_args!("{:+.1} %/h", r), VALUE_X, row_y(2), 2.0, palette.text),
+            None => draw_text(platform, "n/a", VALUE_X, row_y(2), 2.0, palette.text),
+        }
+
+        // straight line estimate from the current rate
+        let hours = match rate {
+            Some(r) if charging && r > 0.0 => Some((100 - soc.clamp(0, 100)) as f32 / r),
+            Some(r) if !charging && r < 0.0 && soc >= 0 => Some(soc as f32 / -r),
+            _ => None,
+        };
+        match hours {
+            Some(h) => {
+                let minutes = (h * 60.0) as u32;
+                draw_text_f(
+                    platform,
+                    format_args!("{}h {:02}m", minutes / 60, minutes % 60),
+                    VALUE_X,
+                    row_y(3),
+                    2.0,
+                    palette.text,
+                );
+            }
+            None => draw_text(platform, "-", VALUE_X, row_y(3), 2.0, palette.text),
+        }
+
+        let hint_pos = IVec2::new(
+            ctx.viewport.size.x / 2,
+            ctx.viewport.pos.y + ctx.viewport.size.y - 20,
+        );
+        let hint = "Thresholds are in Settings";
+        platform.draw_string_aligned(hint, hint_pos, 2, Align::Center, palette.dim);
+    }
+
+    fn frame_request(&self) -> FrameRequest {
+        FrameRequest::AfterMs(REFRESH_MS)
+    }
+}
@@ -1,17 +1,27 @@
 use crate::app::IcApp;
 use crate::app::InputContext;
-use crate::input::{IcKey, KeyState};
+use crate::app::Viewport;
+use crate::font::{self, Align, RESULT_SIZE};
+use crate::input::{IcKey, KeyEventKind, KeyState};
+use crate::keymap::{Binding, KeyTable, KeymapId, Keymaps, KeymapDef};
+use crate::legend::{KeyLayer, KeyLegend};
+use crate::math_render::{MathStyle, draw_expression};
 use crate::platform;
 use crate::platform::IcPlatform;
 use crate::platform::debug_log;
-use crate::text::{draw_text, draw_text_f, text_to_pos};
+use crate::prefs::{PrefDef, PrefId, Prefs};
+use crate::state::{StateReader, StateWriter};
+use crate::text::{draw_text, draw_text_f};
+use crate::theme::Palette;
+use crate::ui::{Dialog, FieldStyle, FocusManager, LineBuffer, Menu, NavDir, TextField};
 use alloc::boxed::Box;
 use alloc::string::ToString;
+use alloc::vec::Vec;
 use alloc::{format, string::String};
 use core::str::FromStr;
 use core::{num::ParseIntError, result};
 use glam::IVec2;
-use rgb::*;
+use rgb::RGB8;
 
 #[derive(Clone, Copy)]
 struct EqEntry {
@@ -63,121 +73,284 @@ enum KeyAction {
     Home,
     End,
     Mode,
+    TogglePrettyPrint,
+    // the result of the last equation
+    InsertAnswer,
 }
 
-#[derive(Clone, Copy, PartialEq, Eq)]
-enum FocusUi {
-    Equation,
-    Widget,
+impl KeyAction {
+    // for the key legend
+    fn label(&self) -> String {
+        let text = |chars: &[u8]| chars.iter().map(|c| *c as char).collect();
+        match *self {
+            KeyAction::InsertChar(c) => text(&[c]),
+            KeyAction::InsertChar2(c1, c2) => text(&[c1, c2]),
+            KeyAction::InsertChar3(c1, c2, c3) => text(&[c1, c2, c3]),
+            KeyAction::InsertChar4(c1, c2, c3, c4) => text(&[c1, c2, c3, c4]),
+            KeyAction::InsertChar5(c1, c2, c3, c4, c5) => text(&[c1, c2, c3, c4, c5]),
+            KeyAction::MoveLeft => String::from("<"),
+            KeyAction::MoveRight => String::from(">"),
+            KeyAction::MoveUp => String::from("^"),
+            KeyAction::MoveDown => String::from("v"),
+            KeyAction::Backspace => String::from("Bk"),
+            KeyAction::Delete => String::from("Del"),
+            KeyAction::Enter => String::from("="),
+            KeyAction::Clear => String::from("Clr"),
+            KeyAction::Home => String::from("Hm"),
+            KeyAction::End => String::from("End"),
+            KeyAction::Mode => String::from("Mode"),
+            KeyAction::TogglePrettyPrint => String::from("Pty"),
+            KeyAction::InsertAnswer => String::from("Ans"),
+        }
+    }
 }
 
-enum NavDir {
-    Up,
-    Down,
-    Left,
-    Right,
-}
+// Everything the engines can put on a key, by the name keymaps use. Inserts
+// are named after what they type.
+const CALC_ACTIONS: &[(&str, KeyAction)] = &[
+    ("0", KeyAction::InsertChar(b'0')),
+    ("1", KeyAction::InsertChar(b'1')),
+    ("2", KeyAction::InsertChar(b'2')),
+    ("3", KeyAction::InsertChar(b'3')),
+    ("4", KeyAction::InsertChar(b'4')),
+    ("5", KeyAction::InsertChar(b'5')),
+    ("6", KeyAction::InsertChar(b'6')),
+    ("7", KeyAction::InsertChar(b'7')),
+    ("8", KeyAction::InsertChar(b'8')),
+    ("9", KeyAction::InsertChar(b'9')),
+    ("A", KeyAction::InsertChar(b'A')),
+    ("B", KeyAction::InsertChar(b'B')),
+    ("C", KeyAction::InsertChar(b'C')),
+    ("D", KeyAction::InsertChar(b'D')),
+    ("E", KeyAction::InsertChar(b'E')),
+    ("F", KeyAction::InsertChar(b'F')),
+    (".", KeyAction::InsertChar(b'.')),
+    ("(", KeyAction::InsertChar(b'(')),
+    (")", KeyAction::InsertChar(b')')),
+    ("+", KeyAction::InsertChar(b'+')),
+    ("-", KeyAction::InsertChar(b'-')),
+    ("*", KeyAction::InsertChar(b'*')),
+    ("/", KeyAction::InsertChar(b'/')),
+    ("^", KeyAction::InsertChar(b'^')),
+    ("%", KeyAction::InsertChar(b'%')),
+    ("&", KeyAction::InsertChar(b'&')),
+    ("|", KeyAction::InsertChar(b'|')),
+    ("~", KeyAction::InsertChar(b'~')),
+    ("<<", KeyAction::InsertChar2(b'<', b'<')),
+    (">>", KeyAction::InsertChar2(b'>', b'>')),
+    ("0x", KeyAction::InsertChar2(b'0', b'x')),
+    ("sin(", KeyAction::InsertChar4(b's', b'i', b'n', b'(')),
+    ("cos(", KeyAction::InsertChar4(b'c', b'o', b's', b'(')),
+    ("tan(", KeyAction::InsertChar4(b't', b'a', b'n', b'(')),
+    ("asin(", KeyAction::InsertChar5(b'a', b's', b'i', b'n', b'(')),
+    ("acos(", KeyAction::InsertChar5(b'a', b'c', b'o', b's', b'(')),
+    ("atan(", KeyAction::InsertChar5(b'a', b't', b'a', b'n', b'(')),
+    ("sqrt(", KeyAction::InsertChar5(b's', b'q', b'r', b't', b'(')),
+    ("ans", KeyAction::InsertAnswer),
+    ("left", KeyAction::MoveLeft),
+    ("right", KeyAction::MoveRight),
+    ("up", KeyAction::MoveUp),
+    ("down", KeyAction::MoveDown),
+    ("bksp", KeyAction::Backspace),
+    ("del", KeyAction::Delete),
+    ("enter", KeyAction::Enter),
+    ("clear", KeyAction::Clear),
+    ("home", KeyAction::Home),
+    ("end", KeyAction::End),
+    ("mode", KeyAction::Mode),
+    ("pretty", KeyAction::TogglePrettyPrint),
+];
 
-#[derive(Clone, Copy, PartialEq, Eq)]
-enum EngineMode {
-    Programmer,
-    Scientific,
-}
+const SCIENTIFIC_KEYS: &[Binding] = &[
+    (IcKey::Num0, KeyLayer::Base, "0"),
+    (IcKey::Num1, KeyLayer::Base, "1"),
+    (IcKey::Num2, KeyLayer::Base, "2"),
+    (IcKey::Num3, KeyLayer::Base, "3"),
+    (IcKey::Num4, KeyLayer::Base, "4"),
+    (IcKey::Num5, KeyLayer::Base, "5"),
+    (IcKey::Num6, KeyLayer::Base, "6"),
+    (IcKey::Num7, KeyLayer::Base, "7"),
+    (IcKey::Num8, KeyLayer::Base, "8"),
+    (IcKey::Num9, KeyLayer::Base, "9"),
+    (IcKey::Func1, KeyLayer::Base, "bksp"),
+    (IcKey::Func2, KeyLayer::Base, "/"),
+    (IcKey::Func3, KeyLayer::Base, "*"),
+    (IcKey::Func4, KeyLayer::Base, "-"),
+    (IcKey::Func5, KeyLayer::Base, "+"),
+    (IcKey::Func6, KeyLayer::Base, "enter"),
+    (IcKey::Num0, KeyLayer::Shift, "sin("),
+    (IcKey::Num1, KeyLayer::Shift, "cos("),
+    (IcKey::Num2, KeyLayer::Shift, "tan("),
+    (IcKey::Num3, KeyLayer::Shift, "asin("),
+    (IcKey::Num4, KeyLayer::Shift, "acos("),
+    (IcKey::Num5, KeyLayer::Shift, "atan("),
+    (IcKey::Num6, KeyLayer::Shift, "."),
+    (IcKey::Num7, KeyLayer::Shift, "("),
+    (IcKey::Num8, KeyLayer::Shift, ")"),
+    (IcKey::Num9, KeyLayer::Shift, "sqrt("),
+    (IcKey::Func6, KeyLayer::Shift, "^"),
+    (IcKey::Num0, KeyLayer::Super, "."),
+    (IcKey::Num1, KeyLayer::Super, "end"),
+    (IcKey::Num2, KeyLayer::Super, "down"),
+    (IcKey::Num3, KeyLayer::Super, "pretty"),
+    (IcKey::Num4, KeyLayer::Super, "left"),
+    (IcKey::Num5, KeyLayer::Super, "mode"),
+    (IcKey::Num6, KeyLayer::Super, "right"),
+    (IcKey::Num7, KeyLayer::Super, "home"),
+    (IcKey::Num8, KeyLayer::Super, "up"),
+    (IcKey::Num9, KeyLayer::Super, "clear"),
+];
 
-const EQ_HISTORY_MAX: usize = 4;
-const WIDTH: u32 = 320;
-const HEIGHT: u32 = 240;
+const PROGRAMMER_KEYS: &[Binding] = &[
+    (IcKey::Num0, KeyLayer::Base, "0"),
+    (IcKey::Num1, KeyLayer::Base, "1"),
+    (IcKey::Num2, KeyLayer::Base, "2"),
+    (IcKey::Num3, KeyLayer::Base, "3"),
+    (IcKey::Num4, KeyLayer::Base, "4"),
+    (IcKey::Num5, KeyLayer::Base, "5"),
+    (IcKey::Num6, KeyLayer::Base, "6"),
+    (IcKey::Num7, KeyLayer::Base, "7"),
+    (IcKey::Num8, KeyLayer::Base, "8"),
+    (IcKey::Num9, KeyLayer::Base, "9"),
+    (IcKey::Func1, KeyLayer::Base, "bksp"),
+    (IcKey::Func2, KeyLayer::Base, "/"),
+    (IcKey::Func3, KeyLayer::Base, "*"),
+    (IcKey::Func4, KeyLayer::Base, "-"),
+    (IcKey::Func5, KeyLayer::Base, "+"),
+    (IcKey::Func6, KeyLayer::Base, "enter"),
+    (IcKey::Num0, KeyLayer::Shift, "A"),
+    (IcKey::Num1, KeyLayer::Shift, "B"),
+    (IcKey::Num2, KeyLayer::Shift, "C"),
+    (IcKey::Num3, KeyLayer::Shift, "D"),
+    (IcKey::Num4, KeyLayer::Shift, "E"),
+    (IcKey::Num5, KeyLayer::Shift, "F"),
+    (IcKey::Num6, KeyLayer::Shift, "~"),
+    (IcKey::Num7, KeyLayer::Shift, "("),
+    (IcKey::Num8, KeyLayer::Shift, ")"),
+    (IcKey::Num9, KeyLayer::Shift, "0x"),
+    (IcKey::Func1, KeyLayer::Shift, "&"),
+    (IcKey::Func2, KeyLayer::Shift, "|"),
+    (IcKey::Func3, KeyLayer::Shift, "%"),
+    (IcKey::Func4, KeyLayer::Shift, "<<"),
+    (IcKey::Func5, KeyLayer::Shift, ">>"),
+    (IcKey::Func6, KeyLayer::Shift, "^"),
+    (IcKey::Num0, KeyLayer::Super, "."),
+    (IcKey::Num1, KeyLayer::Super, "end"),
+    (IcKey::Num2, KeyLayer::Super, "down"),
+    (IcKey::Num3, KeyLayer::Super, "pretty"),
+    (IcKey::Num4, KeyLayer::Super, "left"),
+    (IcKey::Num5, KeyLayer::Super, "mode"),
+    (IcKey::Num6, KeyLayer::Super, "right"),
+    (IcKey::Num7, KeyLayer::Super, "home"),
+    (IcKey::Num8, KeyLayer::Super, "up"),
+    (IcKey::Num9, KeyLayer::Super, "clear"),
+];
 
-struct LineBuffer<const N: usize> {
-    pub data: [u8; N],
-    pub len: usize,
-    pub cursor: usize,
-}
+const FOCUS_EQUATION: usize = 0;
+const FOCUS_WIDGET: usize = 1;
 
-impl<const N: usize> LineBuffer<N> {
-    pub fn default() -> Self {
-        Self {
-            data: [0; N],
-            len: 0,
-            cursor: 0,
-        }
-    }
+// ids for ctx.overlays.ask()
+const DIALOG_CLEAR_HISTORY: u32 = 0;
 
-    pub fn insert_char(&mut self, char_code: u8) {
-        if self.cursor < EqEntry::EQUATION_MAX_SIZE {
-            for i in (self.cursor..=(self.len)).rev() {
-                if i == EqEntry::EQUATION_MAX_SIZE - 1 {
-                    break;
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum ResultState {
+    Empty,
+    Valid,
+    // still being typed, eg. an open paren or a trailing operator
+    Incomplete,
+    Invalid,
+}
+
+// Mistakes that typing more can't fix: a closing paren with nothing open,
+// an empty pair of parens or an operator where an operand has to go.
+fn has_syntax_error(equation: &str) -> bool {
+    const OPERATORS: &[u8] = b"+-*/^%&|<>=!~,";
+    // operators written with two characters, the second one doesn't start a
+    // new operand
+    const PAIRS: &[&[u8]] = &[b"**", b"<<", b">>", b"<=", b">=", b"==", b"!=", b"<>", b"&&", b"||"];
+    let mut depth: i32 = 0;
+    // the next thing has to be a number, name or opening paren
+    let mut want_operand = true;
+    let mut prev = 0u8;
+    for c in equation.bytes().filter(|c| !c.is_ascii_whitespace()) {
+        match c {
+            b'(' => want_operand = true,
+            b')' => {
+                if depth == 0 || want_operand {
+                    return true;
                 }
-                self.data[i + 1] = self.data[i];
             }
-            self.data[self.cursor] = char_code;
-            self.cursor += 1;
-            self.len += 1;
-            if self.len > EqEntry::EQUATION_MAX_SIZE {
-                self.len = EqEntry::EQUATION_MAX_SIZE;
+            b'-' | b'+' | b'~' | b'!' if want_operand && !PAIRS.contains(&&[prev, c][..]) => (),
+            _ if OPERATORS.contains(&c) => {
+                if want_operand && !PAIRS.contains(&&[prev, c][..]) {
+                    return true;
+                }
+                want_operand = true;
             }
+            _ => want_operand = false,
         }
-    }
-
-    pub fn move_cursor(&mut self, right: bool) {
-        if right {
-            if self.cursor < self.len {
-                self.cursor += 1;
-            }
-        } else {
-            if self.cursor > 0 {
-                self.cursor -= 1;
-            }
+        match c {
+            b'(' => depth += 1,
+            b')' => depth -= 1,
+            _ => (),
         }
+        prev = c;
     }
+    false
+}
 
-    pub fn move_cursor_home(&mut self) {
-        self.cursor = 0;
-    }
-
-    pub fn move_cursor_end(&mut self) {
-        self.cursor = self.len;
+// Only asked about equations that failed to evaluate, so it just has to tell
+// a half typed equation apart from a wrong one.
+fn is_incomplete(equation: &str) -> bool {
+    let trimmed = equation.trim_end();
+    if has_syntax_error(trimmed) {
+        return false;
     }
-
-    pub fn backspace(&mut self) {
-        if self.cursor > 0 {
-            for i in self.cursor..self.len {
-                self.data[i - 1] = self.data[i];
-            }
-            self.cursor -= 1;
-            self.len -= 1;
-            self.data[self.len] = 0;
+    let mut depth: i32 = 0;
+    for c in trimmed.bytes() {
+        match c {
+            b'(' => depth += 1,
+            b')' => depth -= 1,
+            _ => (),
         }
     }
-
-    pub fn backspace_del(&mut self) {
-        // delete is the same thing as pressing right and then backspace
-        self.move_cursor(true);
-        self.backspace();
+    if depth > 0 {
+        return true;
     }
-
-    pub fn as_str(&self) -> &str {
-        core::str::from_utf8(&self.data[..self.len]).unwrap_or("Invalid UTF-8")
+    if trimmed.ends_with("0x") || trimmed.ends_with("0b") {
+        return true;
     }
-
-    pub fn set_content(&mut self, content: &[u8]) {
-        let copy_len = content.len().min(N);
-        self.data[..copy_len].copy_from_slice(&content[..copy_len]);
-        self.len = copy_len;
-        self.cursor = copy_len;
+    match trimmed.bytes().last() {
+        Some(c) => b"+-*/^%&|<>=!~,.".contains(&c),
+        None => true,
     }
+}
 
-    pub fn clear(&mut self) {
-        self.data = [0; N];
-        self.len = 0;
-        self.cursor = 0;
-    }
+const EQ_HISTORY_MAX: usize = 4;
+const WIDTH: u32 = 320;
+const HEIGHT: u32 = 240;
+// for the engines' line along the bottom of the screen
+const FOOTER_SIZE: u32 = 2;
+// history answers, a size down from the result
+const ANSWER_SIZE: u32 = 4;
+
+fn draw_footer(platform: &mut dyn IcPlatform, text: &str, color: RGB8) {
+    let y = HEIGHT as i32 - platform.measure_string(text, FOOTER_SIZE).y;
+    platform.draw_string(text, IVec2::new(2, y), FOOTER_SIZE, color);
 }
 
 trait CalcEngine {
-    fn evaluate(&self, equation: &str) -> String;
-    fn draw_widgets(&self, platform: &mut dyn IcPlatform, result_str: &str, is_focused: bool);
+    // shown in the mode picker
+    fn name(&self) -> &'static str;
+    // Err holds the message to show instead of a value
+    fn evaluate(&self, equation: &str) -> Result<String, String>;
+    fn draw_widgets(
+        &self,
+        platform: &mut dyn IcPlatform,
+        result_str: &str,
+        is_focused: bool,
+        palette: &Palette,
+    );
     // true means this CalcEngine consumed the input
     fn on_widget_key(
         &mut self,
@@ -186,8 +359,16 @@ trait CalcEngine {
         current_result: &str,
     ) -> bool;
     fn has_widget(&self) -> bool;
-    fn get_action(&self, key: IcKey, is_shifted: bool, is_super: bool) -> Option<KeyAction>;
-    fn get_color(&self) -> RGB8;
+    // the default layout and the name overrides of it are saved under
+    fn keymap(&self) -> (&'static str, &'static [Binding]);
+    // whether equations in this engine's syntax can be drawn in 2D
+    fn pretty_print(&self) -> bool {
+        false
+    }
+    // short state for the status bar, like the angle unit or word size
+    fn mode_text(&self) -> &'static str {
+        ""
+    }
 }
 
 pub struct ScientificEngine {}
@@ -199,26 +380,29 @@ impl ScientificEngine {
 }
 
 impl CalcEngine for ScientificEngine {
-    fn evaluate(&self, equation: &str) -> String {
+    fn name(&self) -> &'static str {
+        "Scientific"
+    }
+
+    fn mode_text(&self) -> &'static str {
+        "RAD"
+    }
+
+    fn evaluate(&self, equation: &str) -> Result<String, String> {
         match exp_rs::interp(equation, None) {
-            Ok(v) => format!("{}", v),
-            Err(msg) => msg.to_string(),
+            Ok(v) => Ok(format!("{}", v)),
+            Err(msg) => Err(msg.to_string()),
         }
     }
 
-    fn draw_widgets(&self, platform: &mut dyn IcPlatform, _result_str: &str, is_focused: bool) {
-        draw_text(
-            platform,
-            "Scientific",
-            2.0,
-            222.0,
-            2.0,
-            Rgb {
-                r: 0x44,
-                g: 0x44,
-                b: 0x44,
-            },
-        );
+    fn draw_widgets(
+        &self,
+        platform: &mut dyn IcPlatform,
+        _result_str: &str,
+        is_focused: bool,
+        palette: &Palette,
+    ) {
+        draw_footer(platform, "Scientific", palette.dim);
     }
 
     fn on_widget_key(
@@ -234,78 +418,12 @@ impl CalcEngine for ScientificEngine {
         false
     }
 
-    fn get_action(&self, key: IcKey, is_shifted: bool, is_super: bool) -> Option<KeyAction> {
-        if is_shifted {
-            match key {
-                IcKey::Num0 => Some(KeyAction::InsertChar4(b's', b'i', b'n', b'(')),
-                IcKey::Num1 => Some(KeyAction::InsertChar4(b'c', b'o', b's', b'(')),
-                IcKey::Num2 => Some(KeyAction::InsertChar4(b't', b'a', b'n', b'(')),
-                IcKey::Num3 => Some(KeyAction::InsertChar5(b'a', b's', b'i', b'n', b'(')),
-                IcKey::Num4 => Some(KeyAction::InsertChar5(b'a', b'c', b'o', b's', b'(')),
-                IcKey::Num5 => Some(KeyAction::InsertChar5(b'a', b't', b'a', b'n', b'(')),
-                IcKey::Num6 => Some(KeyAction::InsertChar(b'.')),
-                IcKey::Num7 => Some(KeyAction::InsertChar(b'(')),
-                IcKey::Num8 => Some(KeyAction::InsertChar(b')')),
-                IcKey::Num9 => Some(KeyAction::InsertChar5(b's', b'q', b'r', b't', b'(')),
-                IcKey::Func1 => None,
-                IcKey::Func2 => None,
-                IcKey::Func3 => None,
-                IcKey::Func4 => None,
-                IcKey::Func5 => None,
-                IcKey::Func6 => Some(KeyAction::InsertChar(b'^')),
-                IcKey::Shift => None,
-                IcKey::Super => None,
-                IcKey::_Max => None,
-            }
-        } else if is_super {
-            match key {
-                IcKey::Num0 => Some(KeyAction::InsertChar(b'.')),
-                IcKey::Num1 => Some(KeyAction::End),
-                IcKey::Num2 => Some(KeyAction::MoveDown),
-                IcKey::Num3 => None,
-                IcKey::Num4 => Some(KeyAction::MoveLeft),
-                IcKey::Num5 => Some(KeyAction::Mode),
-                IcKey::Num6 => Some(KeyAction::MoveRight),
-                IcKey::Num7 => Some(KeyAction::Home),
-                IcKey::Num8 => Some(KeyAction::MoveUp),
-                IcKey::Num9 => Some(KeyAction::Clear),
-                IcKey::Func1 => None,
-                IcKey::Func2 => None,
-                IcKey::Func3 => None,
-                IcKey::Func4 => None,
-                IcKey::Func5 => None,
-                IcKey::Func6 => None,
-                IcKey::Shift => None,
-                IcKey::Super => None,
-                IcKey::_Max => None,
-            }
-        } else {
-            match key {
-                IcKey::Num0 => Some(KeyAction::InsertChar(b'0')),
-                IcKey::Num1 => Some(KeyAction::InsertChar(b'1')),
-                IcKey::Num2 => Some(KeyAction::InsertChar(b'2')),
-                IcKey::Num3 => Some(KeyAction::InsertChar(b'3')),
-                IcKey::Num4 => Some(KeyAction::InsertChar(b'4')),
-                IcKey::Num5 => Some(KeyAction::InsertChar(b'5')),
-                IcKey::Num6 => Some(KeyAction::InsertChar(b'6')),
-                IcKey::Num7 => Some(KeyAction::InsertChar(b'7')),
-                IcKey::Num8 => Some(KeyAction::InsertChar(b'8')),
-                IcKey::Num9 => Some(KeyAction::InsertChar(b'9')),
-                IcKey::Func1 => Some(KeyAction::Backspace),
-                IcKey::Func2 => Some(KeyAction::InsertChar(b'/')),
-                IcKey::Func3 => Some(KeyAction::InsertChar(b'*')),
-                IcKey::Func4 => Some(KeyAction::InsertChar(b'-')),
-                IcKey::Func5 => Some(KeyAction::InsertChar(b'+')),
-                IcKey::Func6 => Some(KeyAction::Enter),
-                IcKey::Shift => None,
-                IcKey::Super => None,
-                IcKey::_Max => None,
-            }
-        }
+    fn keymap(&self) -> (&'static str, &'static [Binding]) {
+        ("calc.scientific", SCIENTIFIC_KEYS)
     }
     
-    fn get_color(&self) -> RGB8 {
-        RGB8::new(0x20, 0, 0)
+    fn pretty_print(&self) -> bool {
+        true
     }
 }
 
@@ -363,8 +481,19 @@ impl ProgrammerEngine {
 }
 
 impl CalcEngine for ProgrammerEngine {
-    fn evaluate(&self, equation: &str) -> String {
-        bitwise_expr::evaluate_str(equation)
+    fn name(&self) -> &'static str {
+        "Programmer"
+    }
+
+    fn mode_text(&self) -> &'static str {
+        "64-bit"
+    }
+
+    fn evaluate(&self, equation: &str) -> Result<String, String> {
+        match bitwise_expr::evaluate(equation) {
+            Ok(v) => Ok(format!("{}", v)),
+            Err(msg) => Err(String::from(msg)),
+        }
     }
     fn has_widget(&self) -> bool {
         true
@@ -424,8 +553,13 @@ impl CalcEngine for ProgrammerEngine {
         true
     }
 
-    fn draw_widgets(&self, platform: &mut dyn IcPlatform, result_str: &str, is_focused: bool) {
-        let margin = 2;
+    fn draw_widgets(
+        &self,
+        platform: &mut dyn IcPlatform,
+        result_str: &str,
+        is_focused: bool,
+        palette: &Palette,
+    ) {
         let (result_as_int, result_is_int) = match result_str.parse::<i32>() {
             Ok(s) => (s, true),
             Err(_) => (0, false),
@@ -438,18 +572,7 @@ impl CalcEngine for ProgrammerEngine {
                     Err(_) => (String::new(), true),
                 };
             if !hex_err {
-                draw_text(
-                    platform,
-                    &result_as_hex,
-                    margin as f32,
-                    222.0,
-                    2.0,
-                    Rgb {
-                        r: 0x00,
-                        g: 0xff,
-                        b: 0xff,
-                    },
-                );
+                draw_footer(platform, &result_as_hex, palette.accent);
             }
             // draw bin form of ans
             let bin_widget_bit1_x: i32 = 310;
@@ -465,18 +588,10 @@ impl CalcEngine for ProgrammerEngine {
                     bin_widget_bit1_y - bin_widget_element_margin - bin_widget_element_w
                 };
                 let bit_val: bool = (result_as_int >> i) & 1 != 0;
-                let color: Rgb<u8> = if is_focused && i == self.binary_selection_idx as i32 {
-                    Rgb {
-                        r: 0x00,
-                        g: 0xff,
-                        b: 0x55,
-                    }
+                let color = if is_focused && i == self.binary_selection_idx as i32 {
+                    palette.text
                 } else {
-                    Rgb {
-                        r: 0xff,
-                        g: 0xff,
-                        b: 0x00,
-                    }
+                    palette.accent
                 };
                 if bit_val {
                     platform.draw_line(
@@ -502,153 +617,168 @@ impl CalcEngine for ProgrammerEngine {
                 }
             }
         } else {
-            draw_text(
-                platform,
-                "Programmer",
-                margin as f32,
-                222.0,
-                2.0,
-                Rgb {
-                    r: 0x44,
-                    g: 0x44,
-                    b: 0x44,
-                },
-            );
+            draw_footer(platform, "Programmer", palette.dim);
         }
     }
 
-    fn get_action(&self, key: IcKey, is_shifted: bool, is_super: bool) -> Option<KeyAction> {
-        if is_shifted {
-            match key {
-                IcKey::Num0 => Some(KeyAction::InsertChar(b'A')),
-                IcKey::Num1 => Some(KeyAction::InsertChar(b'B')),
-                IcKey::Num2 => Some(KeyAction::InsertChar(b'C')),
-                IcKey::Num3 => Some(KeyAction::InsertChar(b'D')),
-                IcKey::Num4 => Some(KeyAction::InsertChar(b'E')),
-                IcKey::Num5 => Some(KeyAction::InsertChar(b'F')),
-                IcKey::Num6 => Some(KeyAction::InsertChar(b'~')),
-                IcKey::Num7 => Some(KeyAction::InsertChar(b'(')),
-                IcKey::Num8 => Some(KeyAction::InsertChar(b')')),
-                IcKey::Num9 => Some(KeyAction::InsertChar2(b'0', b'x')),
-                IcKey::Func1 => Some(KeyAction::InsertChar(b'&')),
-                IcKey::Func2 => Some(KeyAction::InsertChar(b'|')),
-                IcKey::Func3 => Some(KeyAction::InsertChar(b'%')),
-                IcKey::Func4 => Some(KeyAction::InsertChar2(b'<', b'<')),
-                IcKey::Func5 => Some(KeyAction::InsertChar2(b'>', b'>')),
-                IcKey::Func6 => Some(KeyAction::InsertChar(b'^')),
-                IcKey::Shift => None,
-                IcKey::Super => None,
-                IcKey::_Max => None,
-            }
-        } else if is_super {
-            match key {
-                IcKey::Num0 => Some(KeyAction::InsertChar(b'.')),
-                IcKey::Num1 => Some(KeyAction::End),
-                IcKey::Num2 => Some(KeyAction::MoveDown),
-                IcKey::Num3 => None,
-                IcKey::Num4 => Some(KeyAction::MoveLeft),
-                IcKey::Num5 => Some(KeyAction::Mode),
-                IcKey::Num6 => Some(KeyAction::MoveRight),
-                IcKey::Num7 => Some(KeyAction::Home),
-                IcKey::Num8 => Some(KeyAction::MoveUp),
-                IcKey::Num9 => Some(KeyAction::Clear),
-                IcKey::Func1 => None,
-                IcKey::Func2 => None,
-                IcKey::Func3 => None,
-                IcKey::Func4 => None,
-                IcKey::Func5 => None,
-                IcKey::Func6 => None,
-                IcKey::Shift => None,
-                IcKey::Super => None,
-                IcKey::_Max => None,
-            }
-        } else {
-            match key {
-                IcKey::Num0 => Some(KeyAction::InsertChar(b'0')),
-                IcKey::Num1 => Some(KeyAction::InsertChar(b'1')),
-                IcKey::Num2 => Some(KeyAction::InsertChar(b'2')),
-                IcKey::Num3 => Some(KeyAction::InsertChar(b'3')),
-                IcKey::Num4 => Some(KeyAction::InsertChar(b'4')),
-                IcKey::Num5 => Some(KeyAction::InsertChar(b'5')),
-                IcKey::Num6 => Some(KeyAction::InsertChar(b'6')),
-                IcKey::Num7 => Some(KeyAction::InsertChar(b'7')),
-                IcKey::Num8 => Some(KeyAction::InsertChar(b'8')),
-                IcKey::Num9 => Some(KeyAction::InsertChar(b'9')),
-                IcKey::Func1 => Some(KeyAction::Backspace),
-                IcKey::Func2 => Some(KeyAction::InsertChar(b'/')),
-                IcKey::Func3 => Some(KeyAction::InsertChar(b'*')),
-                IcKey::Func4 => Some(KeyAction::InsertChar(b'-')),
-                IcKey::Func5 => Some(KeyAction::InsertChar(b'+')),
-                IcKey::Func6 => Some(KeyAction::Enter),
-                IcKey::Shift => None,
-                IcKey::Super => None,
-                IcKey::_Max => None,
-            }
-        }
-    }
-
-    fn get_color(&self) -> RGB8 {
-        RGB8::new(0, 0x20, 0x20)
+    fn keymap(&self) -> (&'static str, &'static [Binding]) {
+        ("calc.programmer", PROGRAMMER_KEYS)
     }
 }
 
 pub struct Calculator {
-    current_eq: LineBuffer<{ EqEntry::EQUATION_MAX_SIZE }>,
+    editor: TextField<{ EqEntry::EQUATION_MAX_SIZE }>,
     eq_history: [EqEntry; EQ_HISTORY_MAX],
     eq_history_len: usize,
     eq_history_write_idx: usize,
     current_result: [u8; EqEntry::EQUATION_MAX_SIZE],
     current_result_len: usize,
+    // current_result keeps the last valid value while the state is not Valid
+    result_state: ResultState,
+    current_error: [u8; EqEntry::EQUATION_MAX_SIZE],
+    current_error_len: usize,
     history_selection: Option<HistorySelection>, // none means youre editing the current equation
-    focused_ui: FocusUi,
-    engine: Box<dyn CalcEngine>,
-    engine_mode: EngineMode,
+    focus: FocusManager<2>,
+    // engines are kept alive while inactive so their widget state survives a switch
+    engines: Vec<Box<dyn CalcEngine>>,
+    engine_idx: usize,
+    // per engine, its layout with the user's overrides
+    key_tables: Vec<KeyTable<KeyAction>>,
+    keymap_ids: Vec<KeymapId>,
+    mode_menu: Option<Menu>,
+    pretty_print: bool,
+    // the shell's palette as of the last update
+    palette: Palette,
+    prefs: Option<CalcPrefs>,
+    // last applied "calc.default_mode", so only changes to it switch engines
+    default_mode: Option<i32>,
+}
+
+struct CalcPrefs {
+    default_mode: PrefId,
+    pretty_print: PrefId,
 }
 
 impl Calculator {
     pub fn new() -> Calculator {
-        Calculator {
-            current_eq: LineBuffer::default(),
+        let mut calc = Calculator {
+            editor: TextField::new(
+                IVec2::new(2, 154),
+                IVec2::new(WIDTH as i32 - 4, 30),
+                FieldStyle {
+                    fill: None,
+                    fill_focused: None,
+                    padding: 0,
+                    shrink_after: 12,
+                    ..FieldStyle::default()
+                },
+            ),
             eq_history: [EqEntry::default(); EQ_HISTORY_MAX],
             eq_history_len: 0,
             eq_history_write_idx: 0,
             current_result: [0; EqEntry::EQUATION_MAX_SIZE],
             current_result_len: 0,
+            result_state: ResultState::Empty,
+            current_error: [0; EqEntry::EQUATION_MAX_SIZE],
+            current_error_len: 0,
             history_selection: None,
-            focused_ui: FocusUi::Equation,
-            engine: Box::new(ProgrammerEngine::default()),
-            engine_mode: EngineMode::Programmer,
+            focus: FocusManager::new([IVec2::new(0, 0), IVec2::new(0, 1)]),
+            engines: Vec::new(),
+            engine_idx: 0,
+            key_tables: Vec::new(),
+            keymap_ids: Vec::new(),
+            mode_menu: None,
+            pretty_print: false,
+            palette: Palette::from_index(0),
+            prefs: None,
+            default_mode: None,
+        };
+        calc.editor.style = calc.palette.field_style(calc.editor.style);
+        calc.register_engine(Box::new(ProgrammerEngine::default()));
+        calc.register_engine(Box::new(ScientificEngine::default()));
+        calc
+    }
+
+    fn register_engine(&mut self, engine: Box<dyn CalcEngine>) {
+        self.key_tables.push(KeyTable::new(CALC_ACTIONS, engine.keymap().1));
+        self.engines.push(engine);
+    }
+
+    fn get_action(&self, key: IcKey, is_shifted: bool, is_super: bool) -> Option<KeyAction> {
+        self.key_tables[self.engine_idx].get(key, is_shifted, is_super)
+    }
+
+    fn engine(&self) -> &dyn CalcEngine {
+        self.engines[self.engine_idx].as_ref()
+    }
+
+    fn open_mode_menu(&mut self) {
+        let names: Vec<&'static str> = self.engines.iter().map(|e| e.name()).collect();
+        let mut menu = Menu::new("Mode", &names, self.palette.menu_style());
+        menu.select(self.engine_idx);
+        self.mode_menu = Some(menu);
+    }
+
+    fn set_engine(&mut self, idx: usize) {
+        if idx < self.engines.len() && idx != self.engine_idx {
+            self.engine_idx = idx;
+            self.focus.set_focus(FOCUS_EQUATION);
+            self.update_realtime_result();
+        }
+    }
+
+    // keys go through the active engine's action map so the menu follows its layout
+    fn mode_menu_key(&mut self, action: KeyAction) {
+        let Some(menu) = self.mode_menu.as_mut() else {
+            return;
+        };
+        match action {
+            KeyAction::MoveUp => {
+                menu.navigate(NavDir::Up);
+            }
+            KeyAction::MoveDown => {
+                menu.navigate(NavDir::Down);
+            }
+            KeyAction::Enter => {
+                let idx = menu.selected();
+                self.mode_menu = None;
+                self.set_engine(idx);
+            }
+            KeyAction::Backspace | KeyAction::Clear | KeyAction::Mode => self.mode_menu = None,
+            _ => (),
         }
     }
 
     fn ui_nav(&mut self, dir: NavDir) {
-        match self.focused_ui {
-            FocusUi::Equation => match dir {
-                NavDir::Up => self.history_nav(true),
-                NavDir::Down => {
-                    if self.history_selection.is_none() {
-                        if self.engine.has_widget() {
-                            self.focused_ui = FocusUi::Widget;
-                        }
-                    } else {
-                        self.history_nav(false);
+        if !self.focus.is_focused(FOCUS_EQUATION) {
+            return;
+        }
+        match dir {
+            NavDir::Up => self.history_nav(true),
+            NavDir::Down => {
+                if self.history_selection.is_none() {
+                    if self.engine().has_widget() && self.widget_usable() {
+                        self.focus.set_focus(FOCUS_WIDGET);
                     }
+                } else {
+                    self.history_nav(false);
                 }
-                NavDir::Left => self.current_eq.move_cursor(false),
-                NavDir::Right => self.current_eq.move_cursor(true),
-            },
-            FocusUi::Widget => {}
+            }
+            NavDir::Left => self.editor.buffer.move_cursor(false),
+            NavDir::Right => self.editor.buffer.move_cursor(true),
         }
     }
 
     fn run_equation(&mut self) {
-        if self.current_eq.len == 0 {
+        if self.editor.buffer.len == 0 {
             return;
         }
-        let answer_str = self.engine.evaluate(self.current_eq.as_str());
+        let answer_str = match self.engine().evaluate(self.editor.buffer.as_str()) {
+            Ok(s) | Err(s) => s,
+        };
         let mut new_hist_entry = EqEntry {
-            equation: self.current_eq.data,
-            equation_len: self.current_eq.len,
+            equation: self.editor.buffer.data,
+            equation_len: self.editor.buffer.len,
             result: [0; EqEntry::EQUATION_MAX_SIZE],
             result_len: 0,
         };
@@ -658,26 +788,50 @@ impl Calculator {
             &answer_str,
         );
         self.history_append(&new_hist_entry);
-        self.current_eq.clear();
+        self.editor.buffer.clear();
         self.current_result_len = 0;
+        self.result_state = ResultState::Empty;
+    }
+
+    // The widgets work on the current result, which is stale while the
+    // equation is half typed or wrong.
+    fn widget_usable(&self) -> bool {
+        matches!(self.result_state, ResultState::Empty | ResultState::Valid)
     }
 
     fn update_realtime_result(&mut self) {
-        let eq_str = self.current_eq.as_str();
+        let eq_str = self.editor.buffer.as_str();
         if eq_str.is_empty() {
             self.current_result_len = 0;
+            self.result_state = ResultState::Empty;
             return;
         }
-        let answer_str = self.engine.evaluate(eq_str);
-        Self::copy_str_to_buffer(
-            &mut self.current_result,
-            &mut self.current_result_len,
-            &answer_str,
-        );
+        match self.engine().evaluate(eq_str) {
+            Ok(answer_str) => {
+                Self::copy_str_to_buffer(
+                    &mut self.current_result,
+                    &mut self.current_result_len,
+                    &answer_str,
+                );
+                self.result_state = ResultState::Valid;
+            }
+            Err(_) if is_incomplete(eq_str) => self.result_state = ResultState::Incomplete,
+            Err(msg) => {
+                Self::copy_str_to_buffer(
+                    &mut self.current_error,
+                    &mut self.current_error_len,
+                    &msg,
+                );
+                self.result_state = ResultState::Invalid;
+            }
+        }
     }
 
     fn copy_str_to_buffer(buffer: &mut [u8], len: &mut usize, s: &str) {
-        let bytes = s.as_bytes();
+        Self::copy_bytes(buffer, len, s.as_bytes());
+    }
+
+    fn copy_bytes(buffer: &mut [u8], len: &mut usize, bytes: &[u8]) {
         let copy_len = bytes.len().min(buffer.len());
         buffer[..copy_len].copy_from_slice(&bytes[..copy_len]);
         *len = copy_len;
@@ -743,22 +897,33 @@ impl Calculator {
         }
     }
 
+    fn insert_answer(&mut self) {
+        if self.eq_history_len == 0 {
+            return;
+        }
+        let most_recent = (self.eq_history_write_idx + EQ_HISTORY_MAX - 1) % EQ_HISTORY_MAX;
+        let entry = self.eq_history[most_recent];
+        for c in &entry.result[..entry.result_len] {
+            self.editor.buffer.insert_char(*c);
+        }
+    }
+
     fn copy_from_history(&mut self) {
         if let Some(hs) = self.history_selection {
             let idx = self.get_physical_idx(hs.idx);
             let entry = self.eq_history[idx];
             match hs.part {
                 EqEntryPart::Equation => {
-                    self.current_eq.data = entry.equation;
-                    self.current_eq.len = entry.equation_len;
+                    self.editor.buffer.data = entry.equation;
+                    self.editor.buffer.len = entry.equation_len;
                 }
                 EqEntryPart::Result => {
-                    self.current_eq.data = entry.result;
-                    self.current_eq.len = entry.result_len;
+                    self.editor.buffer.data = entry.result;
+                    self.editor.buffer.len = entry.result_len;
                 }
             }
             self.history_selection = None;
-            self.current_eq.cursor = self.current_eq.len;
+            self.editor.buffer.cursor = self.editor.buffer.len;
         }
     }
 
@@ -798,7 +963,14 @@ impl Calculator {
         }
     }
 
-    fn draw_history(&self, platform: &mut dyn IcPlatform) {
+    fn clear_history(&mut self) {
+        self.eq_history = [EqEntry::default(); EQ_HISTORY_MAX];
+        self.eq_history_len = 0;
+        self.eq_history_write_idx = 0;
+        self.history_selection = None;
+    }
+
+    fn draw_history(&self, platform: &mut dyn IcPlatform, viewport: &Viewport) {
         // draw_text_f(
         //     platform,
         //     format_args!("{}, b{}", self.focused_ui as u8, self.binary_selection_idx),
@@ -812,10 +984,17 @@ impl Calculator {
         //     },
         // );
         let mut draw_row: u32 = 0;
-        let row_height: u32 = 40;
         let margin: u32 = 2;
         let font_size: f32 = 2.0;
-        let max_entries_to_disp: u32 = 3;
+        let line_height: u32 = 20;
+        let pretty = self.pretty_print && self.engine().pretty_print();
+        // fractions and exponents need taller rows, so fewer of them fit
+        let eq_height: u32 = if pretty { 40 } else { 20 };
+        let row_height: u32 = eq_height + line_height;
+        // rows stack upwards from just above the editor, as many as fit
+        // below the top of the viewport
+        let history_bottom = self.editor.pos.y as u32 - 4;
+        let max_entries_to_disp = (history_bottom - viewport.pos.y as u32) / row_height;
         let num_entries_to_disp = core::cmp::min(self.eq_history_len as u32, max_entries_to_disp);
         for i in 0..num_entries_to_disp {
             let most_recent_phys_idx =
@@ -824,23 +1003,22 @@ impl Calculator {
             let entry = &self.eq_history[phys_idx];
             let eq_disp = core::str::from_utf8(&entry.equation[..entry.equation_len])
                 .unwrap_or("Invalid UTF-8");
-            let base_y: u32 = 108;
+            let base_y: u32 = history_bottom - row_height;
             let y = base_y + margin - draw_row * row_height;
-            let line_height: u32 = 20;
-            let y2 = base_y + line_height + margin - draw_row * row_height;
+            let y2 = base_y + eq_height + margin - draw_row * row_height;
             if let Some(selection) = self.history_selection {
-                let y_pos = match selection.part {
-                    EqEntryPart::Equation => y,
-                    EqEntryPart::Result => y2,
+                let (y_pos, part_height) = match selection.part {
+                    EqEntryPart::Equation => (y, eq_height),
+                    EqEntryPart::Result => (y2, line_height),
                 };
                 let logical_idx = self.eq_history_len - 1 - i as usize;
                 if logical_idx == selection.idx {
                     platform.draw_rectangle(
                         IVec2::new(0, y_pos as i32 - margin as i32),
-                        IVec2::new(WIDTH as i32, y_pos as i32 + line_height as i32 - 5),
-                        Rgb::new(0, 0, 0),
+                        IVec2::new(WIDTH as i32, y_pos as i32 + part_height as i32 - 5),
+                        self.palette.selection,
                         0,
-                        Some(Rgb::new(0, 0, 255)),
+                        Some(self.palette.selection),
                     );
                     draw_text(
                         platform,
@@ -848,26 +1026,26 @@ impl Calculator {
                         (WIDTH - margin - 9) as f32,
                         y_pos as f32,
                         font_size,
-                        Rgb {
-                            r: 0xff,
-                            g: 0,
-                            b: 0,
-                        },
+                        self.palette.accent,
                     );
                 }
             }
-            draw_text(
-                platform,
-                eq_disp,
-                margin as f32,
-                y as f32,
-                font_size,
-                Rgb {
-                    r: 0x99,
-                    g: 0x99,
-                    b: 0x99,
-                },
-            );
+            let eq_color = self.palette.dim;
+            if pretty {
+                draw_expression(
+                    platform,
+                    eq_disp,
+                    IVec2::new(margin as i32, y as i32 - 1),
+                    IVec2::new((WIDTH - margin - 14) as i32, eq_height as i32 - 4),
+                    MathStyle {
+                        scale: font_size,
+                        min_scale: 1.0,
+                        color: eq_color,
+                    },
+                );
+            } else {
+                draw_text(platform, eq_disp, margin as f32, y as f32, font_size, eq_color);
+            }
             let ans_disp =
                 core::str::from_utf8(&entry.result[..entry.result_len]).unwrap_or("Invalid UTF-8");
             draw_text(
@@ -876,33 +1054,25 @@ impl Calculator {
                 margin as f32,
                 y2 as f32,
                 font_size,
-                Rgb {
-                    r: 0x99,
-                    g: 0x99,
-                    b: 0x99,
-                },
+                self.palette.dim,
             );
-            draw_text(
-                platform,
-                ans_disp,
-                margin as f32 + 11.0,
-                y2 as f32,
-                font_size,
-                Rgb {
-                    r: 0xff,
-                    g: 0xff,
-                    b: 0x00,
-                },
+            // right aligned, clear of the selection marker
+            let ans_right = (WIDTH - margin - 14) as i32;
+            let ans_room = ans_right - margin as i32 - 11;
+            let ans_font = font::for_size(ANSWER_SIZE);
+            platform.draw_string_aligned(
+                &ans_font.truncate(ans_disp, ans_room as u32),
+                // baseline level with the bottom of the "="
+                IVec2::new(ans_right, y2 as i32 + 14 - ans_font.baseline() as i32),
+                ANSWER_SIZE,
+                Align::Right,
+                self.palette.accent,
             );
 
             platform.draw_line(
                 IVec2::new(margin as i32, y2 as i32 + 16),
                 IVec2::new((WIDTH - margin) as i32, y2 as i32 + 16),
-                Rgb {
-                    r: 0x80,
-                    g: 0x80,
-                    b: 0x80,
-                },
+                self.palette.dim,
                 2,
             );
             draw_row += 1;
@@ -911,95 +1081,74 @@ impl Calculator {
 
     fn draw_editor(&self, platform: &mut dyn IcPlatform) {
         let margin: u32 = 2;
-        let equation_disp = core::str::from_utf8(&self.current_eq.data[..self.current_eq.len])
-            .unwrap_or("Invalid UTF-8");
-        let eq_scale = match self.current_eq.len {
-            x if x > 12 => 2.0,
-            _ => 4.0,
-        };
         let eq_y: f32 = 154.0;
-        draw_text(
-            platform,
-            &equation_disp,
-            margin as f32,
-            eq_y,
-            eq_scale,
-            Rgb {
-                r: 0xff,
-                g: 0xff,
-                b: 0xff,
-            },
-        );
-        if self.focused_ui == FocusUi::Equation && self.history_selection.is_none() {
-            let mut cursor_x_pos = text_to_pos(
-                &equation_disp,
-                margin as f32,
-                eq_scale,
-                self.current_eq.cursor,
-            );
-            cursor_x_pos -= 2.0;
-            if cursor_x_pos < 2.0 {
-                cursor_x_pos = 2.0;
-            }
-            platform.draw_line(
-                IVec2::new(cursor_x_pos as i32 - 3, eq_y as i32 - 5),
-                IVec2::new(cursor_x_pos as i32 - 3, eq_y as i32 + 30),
-                Rgb::new(0xff, 0xff, 0x44),
-                2,
-            );
-        }
+        let editing = self.focus.is_focused(FOCUS_EQUATION) && self.history_selection.is_none();
+        self.editor.draw(platform, editing);
 
         // draw result -------------
 
-        let result_disp = core::str::from_utf8(&self.current_result[..self.current_result_len])
-            .unwrap_or("Invalid UTF-8");
-        let ans_scale = match self.current_result_len {
-            x if x > 12 => 2.0,
-            _ => 4.0,
+        let (result_buf, result_len, result_color) = match self.result_state {
+            ResultState::Empty | ResultState::Valid => (
+                &self.current_result,
+                self.current_result_len,
+                self.palette.text,
+            ),
+            ResultState::Incomplete => (
+                &self.current_result,
+                self.current_result_len,
+                self.palette.dim,
+            ),
+            ResultState::Invalid => (
+                &self.current_error,
+                self.current_error_len,
+                self.palette.error,
+            ),
         };
-        draw_text(
-            platform,
-            "=",
-            margin as f32,
-            eq_y + 31.0,
-            ans_scale,
-            Rgb {
-                r: 0xff,
-                g: 0xff,
-                b: 0xff,
-            },
-        );
-        draw_text(
-            platform,
-            &result_disp,
-            (margin + 24) as f32,
-            eq_y + 31.0,
-            ans_scale,
-            Rgb {
-                r: 0xff,
-                g: 0xff,
-                b: 0xff,
-            },
+        let result_disp =
+            core::str::from_utf8(&result_buf[..result_len]).unwrap_or("Invalid UTF-8");
+        // Right aligned like on a real calculator. Results too wide for the
+        // big font drop a size, and get cut off if that is still too wide.
+        let right = (WIDTH - margin) as i32;
+        let room = right - margin as i32 - platform.measure_string("= ", RESULT_SIZE).x;
+        let size = if platform.measure_string(result_disp, RESULT_SIZE).x <= room {
+            RESULT_SIZE
+        } else {
+            ANSWER_SIZE
+        };
+        let result_font = font::for_size(size);
+        let y = eq_y as i32 + 55 - result_font.baseline() as i32;
+        platform.draw_string("=", IVec2::new(margin as i32, y), size, result_color);
+        platform.draw_string_aligned(
+            &result_font.truncate(result_disp, room as u32),
+            IVec2::new(right, y),
+            size,
+            Align::Right,
+            result_color,
         );
     }
 }
 
 impl IcApp for Calculator {
     fn on_key(&mut self, key: IcKey, ctx: &InputContext) {
-        let action = self
-            .engine
-            .get_action(key, ctx.is_shifted(), ctx.is_super());
+        let action = self.get_action(key, ctx.is_shifted(), ctx.is_super());
+        if self.mode_menu.is_some() {
+            if let Some(act) = action {
+                self.mode_menu_key(act);
+            }
+            return;
+        }
         if let Some(mut act) = action {
-            if self.focused_ui == FocusUi::Widget {
+            if self.focus.is_focused(FOCUS_WIDGET) && !self.widget_usable() {
+                self.focus.set_focus(FOCUS_EQUATION);
+            }
+            if self.focus.is_focused(FOCUS_WIDGET) {
                 let current_result_str =
                     core::str::from_utf8(&self.current_result[..self.current_result_len])
                         .unwrap_or("0");
-                let handled =
-                    self.engine
-                        .on_widget_key(act, &mut self.current_eq, current_result_str);
+                let handled = self.engines[self.engine_idx].on_widget_key(act, &mut self.editor.buffer, current_result_str);
                 if !handled {
                     if act == KeyAction::MoveUp {
-                        self.focused_ui = FocusUi::Equation;
+                        self.focus.set_focus(FOCUS_EQUATION);
                         return;
                     }
                 } else {
@@ -1008,38 +1157,43 @@ impl IcApp for Calculator {
                 }
             }
             match act {
-                KeyAction::InsertChar(c) => self.current_eq.insert_char(c),
+                KeyAction::InsertChar(c) => self.editor.buffer.insert_char(c),
                 KeyAction::InsertChar2(c1, c2) => {
-                    self.current_eq.insert_char(c1);
-                    self.current_eq.insert_char(c2);
+                    self.editor.buffer.insert_char(c1);
+                    self.editor.buffer.insert_char(c2);
                 }
                 KeyAction::InsertChar3(c1, c2, c3) => {
-                    self.current_eq.insert_char(c1);
-                    self.current_eq.insert_char(c2);
-                    self.current_eq.insert_char(c3);
+                    self.editor.buffer.insert_char(c1);
+                    self.editor.buffer.insert_char(c2);
+                    self.editor.buffer.insert_char(c3);
                 }
                 KeyAction::InsertChar4(c1, c2, c3, c4) => {
-                    self.current_eq.insert_char(c1);
-                    self.current_eq.insert_char(c2);
-                    self.current_eq.insert_char(c3);
-                    self.current_eq.insert_char(c4);
+                    self.editor.buffer.insert_char(c1);
+                    self.editor.buffer.insert_char(c2);
+                    self.editor.buffer.insert_char(c3);
+                    self.editor.buffer.insert_char(c4);
                 }
                 KeyAction::InsertChar5(c1, c2, c3, c4, c5) => {
-                    self.current_eq.insert_char(c1);
-                    self.current_eq.insert_char(c2);
-                    self.current_eq.insert_char(c3);
-                    self.current_eq.insert_char(c4);
-                    self.current_eq.insert_char(c5);
+                    self.editor.buffer.insert_char(c1);
+                    self.editor.buffer.insert_char(c2);
+                    self.editor.buffer.insert_char(c3);
+                    self.editor.buffer.insert_char(c4);
+                    self.editor.buffer.insert_char(c5);
                 }
                 KeyAction::Backspace => {
                     if self.history_selection.is_none() {
-                        self.current_eq.backspace()
+                        self.editor.buffer.backspace()
                     } else {
                         self.delete_current_history_entry()
                     }
                 }
-                KeyAction::Clear => self.current_eq.clear(),
-                KeyAction::Delete => self.current_eq.backspace_del(),
+                // with a history entry selected Clear offers to empty the history
+                KeyAction::Clear if self.history_selection.is_some() => ctx.overlays.ask(
+                    DIALOG_CLEAR_HISTORY,
+                    Dialog::confirm("Clear history", "Delete all entries?", ctx.palette.dialog_style()),
+                ),
+                KeyAction::Clear => self.editor.buffer.clear(),
+                KeyAction::Delete => self.editor.buffer.backspace_del(),
                 KeyAction::Enter => {
                     if self.history_selection.is_none() {
                         self.run_equation();
@@ -1051,37 +1205,240 @@ impl IcApp for Calculator {
                 KeyAction::MoveDown => self.ui_nav(NavDir::Down),
                 KeyAction::MoveLeft => self.ui_nav(NavDir::Left),
                 KeyAction::MoveRight => self.ui_nav(NavDir::Right),
-                KeyAction::Home => self.current_eq.move_cursor_home(),
-                KeyAction::End => self.current_eq.move_cursor_end(),
-                KeyAction::Mode => {
-                    match self.engine_mode {
-                        EngineMode::Programmer => {
-                            self.engine_mode = EngineMode::Scientific;
-                            self.engine = Box::new(ScientificEngine::default());
-                        }
-                        EngineMode::Scientific => {
-                            self.engine_mode = EngineMode::Programmer;
-                            self.engine = Box::new(ProgrammerEngine::default());
-                        }
+                KeyAction::Home => self.editor.buffer.move_cursor_home(),
+                KeyAction::End => self.editor.buffer.move_cursor_end(),
+                KeyAction::Mode => self.open_mode_menu(),
+                KeyAction::InsertAnswer => self.insert_answer(),
+                KeyAction::TogglePrettyPrint => {
+                    self.pretty_print = !self.pretty_print;
+                    if let Some(prefs) = &self.prefs {
+                        ctx.prefs.set(prefs.pretty_print, self.pretty_print as i32);
                     }
-                    self.focused_ui = FocusUi::Equation;
                 }
             }
             self.update_realtime_result();
         }
     }
 
-    fn update(&mut self, platform: &mut dyn IcPlatform, _ctx: &InputContext) {
-        platform.clear(self.engine.get_color());
-        self.draw_history(platform);
+    fn on_key_event(&mut self, key: IcKey, kind: KeyEventKind, ctx: &InputContext) {
+        if kind == KeyEventKind::Press {
+            self.on_key(key, ctx);
+            return;
+        }
+        let action = self.get_action(key, ctx.is_shifted(), ctx.is_super());
+        match (kind, action) {
+            // movement and forward delete repeat while held
+            (
+                KeyEventKind::Repeat,
+                Some(
+                    KeyAction::MoveUp
+                    | KeyAction::MoveDown
+                    | KeyAction::MoveLeft
+                    | KeyAction::MoveRight
+                    | KeyAction::Delete,
+                ),
+            ) => self.on_key(key, ctx),
+            // Backspace repeats like the others until long_press_ms, when
+            // the rest of the equation goes at once
+            (KeyEventKind::Repeat, Some(KeyAction::Backspace))
+                if self.mode_menu.is_none() && self.history_selection.is_none() =>
+            {
+                self.on_key(key, ctx)
+            }
+            (KeyEventKind::LongPress, Some(KeyAction::Backspace))
+                if self.mode_menu.is_none() && self.history_selection.is_none() =>
+            {
+                self.editor.buffer.clear();
+                self.update_realtime_result();
+            }
+            _ => (),
+        }
+    }
+
+    fn on_dialog_result(&mut self, id: u32, choice: Option<usize>, ctx: &InputContext) {
+        if id == DIALOG_CLEAR_HISTORY && choice == Some(0) {
+            self.clear_history();
+            ctx.overlays.toast("History cleared");
+        }
+    }
+
+    fn update(&mut self, platform: &mut dyn IcPlatform, ctx: &InputContext) {
+        if self.palette != *ctx.palette {
+            self.palette = *ctx.palette;
+            self.editor.style = self.palette.field_style(self.editor.style);
+        }
+        platform.clear(self.palette.background);
+        self.draw_history(platform, &ctx.viewport);
         self.draw_editor(platform);
-        let result_str =
-            core::str::from_utf8(&self.current_result[..self.current_result_len]).unwrap_or("0");
-        self.engine
-            .draw_widgets(platform, result_str, self.focused_ui == FocusUi::Widget);
+        let result_str = if self.widget_usable() {
+            core::str::from_utf8(&self.current_result[..self.current_result_len]).unwrap_or("0")
+        } else {
+            ""
+        };
+        self.engine()
+            .draw_widgets(platform, result_str, self.focus.is_focused(FOCUS_WIDGET), &self.palette);
+        if let Some(menu) = &self.mode_menu {
+            menu.draw(platform);
+        }
+    }
+
+    fn key_legend(&self, legend: &mut KeyLegend) {
+        legend.fill(|key, layer| {
+            let (shifted, sup) = layer.modifiers();
+            let action = self.get_action(key, shifted, sup)?;
+            if self.mode_menu.is_none() {
+                return Some(action.label());
+            }
+            // only what mode_menu_key reacts to
+            match action {
+                KeyAction::MoveUp | KeyAction::MoveDown => Some(action.label()),
+                KeyAction::Enter => Some(String::from("OK")),
+                KeyAction::Backspace | KeyAction::Clear | KeyAction::Mode => {
+                    Some(String::from("Back"))
+                }
+                _ => None,
+            }
+        });
+    }
+
+    fn mode_text(&self) -> &str {
+        self.engine().mode_text()
+    }
+
+    // engine, history from oldest to newest, then the equation being edited
+    fn save_state(&self, w: &mut StateWriter) {
+        w.varint(self.engine_idx as u32);
+        w.varint(self.eq_history_len as u32);
+        for i in 0..self.eq_history_len {
+            let entry = &self.eq_history[self.get_physical_idx(i)];
+            w.bytes(&entry.equation[..entry.equation_len]);
+            w.bytes(&entry.result[..entry.result_len]);
+        }
+        let buffer = &self.editor.buffer;
+        w.bytes(&buffer.data[..buffer.len]);
+        w.varint(buffer.cursor as u32);
+    }
+
+    fn restore_state(&mut self, version: u16, r: &mut StateReader) -> Option<()> {
+        if version != self.state_version() {
+            return None;
+        }
+        let engine_idx = r.varint()? as usize;
+        let history_len = r.varint()? as usize;
+        if engine_idx >= self.engines.len() || history_len > EQ_HISTORY_MAX {
+            return None;
+        }
+        let mut history = [EqEntry::default(); EQ_HISTORY_MAX];
+        for entry in history.iter_mut().take(history_len) {
+            Self::copy_bytes(&mut entry.equation, &mut entry.equation_len, r.bytes()?);
+            Self::copy_bytes(&mut entry.result, &mut entry.result_len, r.bytes()?);
+        }
+        let equation = r.bytes()?;
+        let cursor = r.varint()? as usize;
+
+        self.engine_idx = engine_idx;
+        self.eq_history_len = 0;
+        self.eq_history_write_idx = 0;
+        for entry in history.iter().take(history_len) {
+            self.history_append(entry);
+        }
+        self.history_selection = None;
+        self.focus.set_focus(FOCUS_EQUATION);
+        self.editor.buffer.set_content(equation);
+        self.editor.buffer.cursor = cursor.min(self.editor.buffer.len);
+        self.update_realtime_result();
+        Some(())
+    }
+
+    fn register_prefs(&mut self, prefs: &mut Prefs) {
+        // choice options live as long as the prefs, which is the whole run
+        let modes: Vec<&'static str> = self.engines.iter().map(|e| e.name()).collect();
+        let modes: &'static [&'static str] = Box::leak(modes.into_boxed_slice());
+        self.prefs = Some(CalcPrefs {
+            default_mode: prefs.register(PrefDef::choice(
+                "calc.default_mode",
+                "Calc mode",
+                modes,
+                0,
+            )),
+            pretty_print: prefs.register(PrefDef::bool(
+                "calc.pretty_print",
+                "Pretty print",
+                false,
+            )),
+        });
+    }
+
+    fn on_prefs_changed(&mut self, prefs: &Prefs) {
+        let Some(ids) = &self.prefs else {
+            return;
+        };
+        self.pretty_print = prefs.get_bool(ids.pretty_print);
+        let default_mode = prefs.get(ids.default_mode);
+        if self.default_mode != Some(default_mode) {
+            self.default_mode = Some(default_mode);
+            self.set_engine(default_mode as usize);
+        }
+    }
+
+    fn register_keymaps(&mut self, keymaps: &mut Keymaps) {
+        self.keymap_ids = self
+            .engines
+            .iter()
+            .map(|engine| {
+                let (key, bindings) = engine.keymap();
+                keymaps.register(KeymapDef::new(key, engine.name(), CALC_ACTIONS, bindings))
+            })
+            .collect();
+    }
+
+    fn on_keymaps_changed(&mut self, keymaps: &Keymaps) {
+        for (table, id) in self.key_tables.iter_mut().zip(self.keymap_ids.iter()) {
+            *table = keymaps.table(*id, CALC_ACTIONS);
+        }
     }
 
     fn on_enter(&mut self) {
         ()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn live_result_tells_incomplete_from_invalid() {
+        for valid in ["1+2*3", "2^-1", "-(2)", "max(1, 2)", "2**3", "1<<3", "1!=2", "0x1F&~3"] {
+            assert!(!has_syntax_error(valid), "{}", valid);
+        }
+        for incomplete in ["(1+2", "3+", "sqrt(", "2*(3-", "0x", ""] {
+            assert!(is_incomplete(incomplete), "{}", incomplete);
+        }
+        for invalid in ["3+*(", "1)+(", "()", "(1+)", "*2", "2*/3", "(3,)"] {
+            assert!(!is_incomplete(invalid), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn live_result_keeps_last_value_while_incomplete() {
+        let mut calc = Calculator::new();
+        let result = |calc: &Calculator| {
+            String::from(core::str::from_utf8(&calc.current_result[..calc.current_result_len]).unwrap())
+        };
+        calc.editor.buffer.insert_str(b"3");
+        calc.update_realtime_result();
+        assert_eq!(calc.result_state, ResultState::Valid);
+        assert_eq!(result(&calc), "3");
+
+        calc.editor.buffer.insert_str(b"*(");
+        calc.update_realtime_result();
+        assert_eq!(calc.result_state, ResultState::Incomplete);
+        assert_eq!(result(&calc), "3");
+
+        calc.editor.buffer.set_content(b"3*)");
+        calc.update_realtime_result();
+        assert_eq!(calc.result_state, ResultState::Invalid);
+        assert!(calc.current_error_len > 0);
+    }
+}
@@ -1,7 +1,8 @@
 use crate::input::{IcKey, KeyState};
+use crate::legend::{KeyLayer, KeyLegend};
 use crate::text::text_to_pos;
 use crate::{
-    app::{ IcApp, InputContext },
+    app::{ FrameRequest, IcApp, InputContext },
     platform::{self, IcPlatform, rgb8_hex},
     text::{draw_text, draw_text_f},
 };
@@ -111,7 +112,6 @@ pub struct RoboEyes {
     laugh_toggle: bool,
 
     sweat: bool,
-    sweat_radius: u32,
     sweat_drops: [SweatDrop; 3],
 }
 
@@ -183,7 +183,7 @@ impl RoboEyes {
             confused: false, confused_timer: 0, confused_duration: 500, confused_toggle: true,
             laugh: false, laugh_timer: 0, laugh_duration: 500, laugh_toggle: true,
             
-            sweat: false, sweat_radius: 3,
+            sweat: false,
             sweat_drops: [SweatDrop::default(); 3],
         }
     }
@@ -260,6 +260,24 @@ impl RoboEyes {
         self.eye_r_open = true;
     }
 
+    pub fn frame_interval(&self) -> u64 {
+        self.frame_interval
+    }
+
+    // Drops any running animation and makes every timer due on the next
+    // frame, so nothing jumps ahead by however long the face was hidden.
+    pub fn reset_timers(&mut self) {
+        self.last_frame_time = 0;
+        self.blink_timer_ms = 0;
+        self.idle_timer_ms = 0;
+        self.laugh = false;
+        self.laugh_toggle = true;
+        self.v_flicker = false;
+        self.confused = false;
+        self.confused_toggle = true;
+        self.h_flicker = false;
+    }
+
     pub fn update(&mut self, platform: &mut dyn IcPlatform) {
         let now = platform.millis();
         
@@ -485,10 +503,11 @@ impl RoboEyes {
             }
             self.sweat_drops[0].x = self.sweat_drops[0].x_initial - (self.sweat_drops[0].w / 2.0) as i32;
             
-            platform.draw_rectangle_rounded(
-                IVec2::new(self.sweat_drops[0].x, self.sweat_drops[0].y as i32),
-                IVec2::new(self.sweat_drops[0].x + self.sweat_drops[0].w as i32, self.sweat_drops[0].y as i32 + self.sweat_drops[0].h as i32),
-                self.main_color, 0, Some(self.main_color), self.sweat_radius
+            let drop = &self.sweat_drops[0];
+            platform.draw_ellipse(
+                IVec2::new(drop.x + (drop.w / 2.0) as i32, (drop.y + drop.h / 2.0) as i32),
+                IVec2::new((drop.w / 2.0) as i32, (drop.h / 2.0) as i32),
+                self.main_color, 0, Some(self.main_color)
             );
         }
     }
@@ -512,6 +531,14 @@ impl IcApp for FaceCalculator {
         ()
     }
 
+    fn on_exit(&mut self) {
+        self.eyes.reset_timers();
+    }
+
+    fn on_suspend(&mut self) {
+        self.eyes.reset_timers();
+    }
+
     fn on_key(&mut self, key: IcKey, _ctx: &InputContext) {
         match key {
             IcKey::Num1 => self.eyes.set_mood(RoboMood::Default),
@@ -534,7 +561,33 @@ impl IcApp for FaceCalculator {
         }
     }
 
-    fn update(&mut self, platform: &mut dyn IcPlatform, _ctx: &InputContext) {
+    fn key_legend(&self, legend: &mut KeyLegend) {
+        let labels = [
+            (IcKey::Num1, "Calm"),
+            (IcKey::Num2, "Tired"),
+            (IcKey::Num3, "Angry"),
+            (IcKey::Num4, "Happy"),
+            (IcKey::Num5, "Huh"),
+            (IcKey::Num6, "Laugh"),
+            (IcKey::Num7, "Sweat"),
+            (IcKey::Num8, "Dry"),
+            (IcKey::Func1, "NW"),
+            (IcKey::Func2, "N"),
+            (IcKey::Func3, "NE"),
+            (IcKey::Func5, "Mid"),
+        ];
+        for (key, label) in labels {
+            legend.set(key, KeyLayer::Base, label);
+        }
+    }
+
+    fn update(&mut self, platform: &mut dyn IcPlatform, ctx: &InputContext) {
+        self.eyes.bg_color = ctx.palette.background;
+        self.eyes.main_color = ctx.palette.accent;
         self.eyes.update(platform);
     }
+
+    fn frame_request(&self) -> FrameRequest {
+        FrameRequest::AfterMs(self.eyes.frame_interval() as u32)
+    }
 }
\ No newline at end of file
@@ -5,4 +5,7 @@ pub use aspect_ratio_calculator::AspectRatioCalculator;
 mod range_mapper_calculator;
 pub use range_mapper_calculator::RangeMapperCalculator;
 mod face_calculator;
-pub use face_calculator::FaceCalculator;
\ No newline at end of file
+pub use face_calculator::FaceCalculator;mod settings;
+pub use settings::SettingsApp;
+mod battery;
+pub use battery::BatteryApp;
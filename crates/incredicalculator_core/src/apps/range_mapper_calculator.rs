@@ -1,176 +1,41 @@
-use crate::input::{IcKey, KeyState};
-use crate::text::text_to_pos;
+use crate::input::IcKey;
+use crate::keymap::{Binding, KeyTable, KeymapDef, KeymapId, Keymaps};
+use crate::legend::{KeyLayer, KeyLegend};
+use crate::ui::{FieldStyle, FocusManager, LineBuffer, NavDir, TextField};
 use crate::{
-    app::IcApp,
-    platform::{self, IcPlatform, rgb8_hex},
+    app::{IcApp, Viewport},
+    platform::IcPlatform,
+    state::{StateReader, StateWriter},
     text::{draw_text, draw_text_f},
+    theme::Palette,
 };
+use alloc::string::String;
 use glam::IVec2;
-use num_traits::{abs, clamp_max};
-use rgb::{RGB8, Rgb};
 
-// todo put LineBuffer in a common place for both this and calculator.rs to use
-struct LineBuffer<const N: usize> {
-    pub data: [u8; N],
-    pub len: usize,
-    pub cursor: usize,
-}
-
-impl<const N: usize> LineBuffer<N> {
-    pub const MAX_LEN: usize = 24;
-    pub fn default() -> Self {
-        Self {
-            data: [0; N],
-            len: 0,
-            cursor: 0,
-        }
-    }
-
-    pub fn insert_char(&mut self, char_code: u8) {
-        if self.cursor < Self::MAX_LEN {
-            for i in (self.cursor..=(self.len)).rev() {
-                if i == Self::MAX_LEN - 1 {
-                    break;
-                }
-                self.data[i + 1] = self.data[i];
-            }
-            self.data[self.cursor] = char_code;
-            self.cursor += 1;
-            self.len += 1;
-            if self.len > Self::MAX_LEN {
-                self.len = Self::MAX_LEN;
-            }
-        }
-    }
-
-    pub fn move_cursor(&mut self, right: bool) {
-        if right {
-            if self.cursor < self.len {
-                self.cursor += 1;
-            }
-        } else {
-            if self.cursor > 0 {
-                self.cursor -= 1;
-            }
-        }
-    }
-
-    pub fn move_cursor_home(&mut self) {
-        self.cursor = 0;
-    }
-
-    pub fn move_cursor_end(&mut self) {
-        self.cursor = self.len;
-    }
-
-    pub fn backspace(&mut self) {
-        if self.cursor > 0 {
-            for i in self.cursor..self.len {
-                self.data[i - 1] = self.data[i];
-            }
-            self.cursor -= 1;
-            self.len -= 1;
-            self.data[self.len] = 0;
-        }
-    }
-
-    pub fn backspace_del(&mut self) {
-        // delete is the same thing as pressing right and then backspace
-        self.move_cursor(true);
-        self.backspace();
-    }
-
-    pub fn as_str(&self) -> &str {
-        core::str::from_utf8(&self.data[..self.len]).unwrap_or("Invalid UTF-8")
-    }
+const IN_VALUE: usize = 0;
+const IN_MIN: usize = 1;
+const IN_MAX: usize = 2;
+const OUT_MIN: usize = 3;
+const OUT_MAX: usize = 4;
+const FIELD_COUNT: usize = 5;
 
-    pub fn set_content(&mut self, content: &[u8]) {
-        let copy_len = content.len().min(N);
-        self.data[..copy_len].copy_from_slice(&content[..copy_len]);
-        self.len = copy_len;
-        self.cursor = copy_len;
-    }
+type ExpressionField = TextField<24>;
 
-    pub fn clear(&mut self) {
-        self.data = [0; N];
-        self.len = 0;
-        self.cursor = 0;
-    }
-
-    pub fn evaluate(&self) -> f32 {
-        match exp_rs::interp(self.as_str(), None) {
-            Ok(v) => v as f32,
-            Err(_) => 0.0,
-        }
+fn evaluate(field: &ExpressionField) -> f32 {
+    match exp_rs::interp(field.buffer.as_str(), None) {
+        Ok(v) => v as f32,
+        Err(_) => 0.0,
     }
 }
 
-struct ExpressionInputBox {
-    expression: LineBuffer<48>,
-    pos: IVec2,
-    size: IVec2,
-}
-
-impl ExpressionInputBox {
-    fn new(pos: IVec2, size: IVec2) -> ExpressionInputBox {
-        ExpressionInputBox {
-            expression: LineBuffer::default(),
-            pos,
-            size,
-        }
-    }
-    fn draw(&mut self, platform: &mut dyn crate::platform::IcPlatform, has_focus: bool) {
-        let fill_color = rgb8_hex(if has_focus { 0xF1FF5E } else { 0x3A9AFF });
-        platform.draw_rectangle(
-            self.pos,
-            self.pos + self.size,
-            rgb8_hex(0xAD4B27),
-            0,
-            Some(fill_color),
-        );
-        let margin = 4;
-        let display_text = core::str::from_utf8(&self.expression.data[..self.expression.len])
-            .unwrap_or("Invalid UTF-8");
-        let text_scale = if self.expression.len > 5 { 2.0 } else { 4.0 };
-        let text_x = (self.pos.x + margin) as f32;
-        let text_y = (self.pos.y + margin) as f32;
-        draw_text(
-            platform,
-            &display_text,
-            text_x,
-            text_y,
-            text_scale,
-            rgb8_hex(if has_focus { 0x000000 } else { 0xffffff }),
-        );
-        let cursor_x = text_to_pos(&display_text, text_x, text_scale, self.expression.cursor);
-        if has_focus {
-            platform.draw_line(
-                IVec2::new(cursor_x as i32 - 3, text_y as i32 - 5),
-                IVec2::new(cursor_x as i32 - 3, (text_y + 8.0 * text_scale) as i32),
-                Rgb::new(0xff, 0x00, 0x44),
-                2,
-            );
-        }
-    }
-}
-
-#[derive(Clone, Copy, PartialEq, Eq)]
-enum FocusUi {
-    InValue,
-    InMin,
-    InMax,
-    OutMin,
-    OutMax,
-}
-
 pub struct RangeMapperCalculator {
-    focused_ui: FocusUi,
-    input_box_in_val: ExpressionInputBox,
-    input_box_in_min: ExpressionInputBox,
-    input_box_in_max: ExpressionInputBox,
-    input_box_out_min: ExpressionInputBox,
-    input_box_out_max: ExpressionInputBox,
+    focus: FocusManager<FIELD_COUNT>,
+    fields: [ExpressionField; FIELD_COUNT],
     answer: f32,
+    y_offset: i32,
+    palette: Palette,
+    keys: KeyTable<KeyAction>,
+    keymap_id: Option<KeymapId>,
 }
 
 #[derive(Clone, Copy, PartialEq, Eq)]
@@ -187,97 +52,134 @@ enum KeyAction {
     End,
 }
 
+impl KeyAction {
+    // for the key legend
+    fn label(&self) -> String {
+        match *self {
+            KeyAction::InsertChar(c) => String::from(c as char),
+            KeyAction::MoveLeft => String::from("<"),
+            KeyAction::MoveRight => String::from(">"),
+            KeyAction::MoveUp => String::from("^"),
+            KeyAction::MoveDown => String::from("v"),
+            KeyAction::Backspace => String::from("Bk"),
+            KeyAction::Enter => String::from("="),
+            KeyAction::Clear => String::from("Clr"),
+            KeyAction::Home => String::from("Hm"),
+            KeyAction::End => String::from("End"),
+        }
+    }
+}
+
+const RANGE_ACTIONS: &[(&str, KeyAction)] = &[
+    ("0", KeyAction::InsertChar(b'0')),
+    ("1", KeyAction::InsertChar(b'1')),
+    ("2", KeyAction::InsertChar(b'2')),
+    ("3", KeyAction::InsertChar(b'3')),
+    ("4", KeyAction::InsertChar(b'4')),
+    ("5", KeyAction::InsertChar(b'5')),
+    ("6", KeyAction::InsertChar(b'6')),
+    ("7", KeyAction::InsertChar(b'7')),
+    ("8", KeyAction::InsertChar(b'8')),
+    ("9", KeyAction::InsertChar(b'9')),
+    (".", KeyAction::InsertChar(b'.')),
+    ("(", KeyAction::InsertChar(b'(')),
+    (")", KeyAction::InsertChar(b')')),
+    ("+", KeyAction::InsertChar(b'+')),
+    ("-", KeyAction::InsertChar(b'-')),
+    ("*", KeyAction::InsertChar(b'*')),
+    ("/", KeyAction::InsertChar(b'/')),
+    ("^", KeyAction::InsertChar(b'^')),
+    ("left", KeyAction::MoveLeft),
+    ("right", KeyAction::MoveRight),
+    ("up", KeyAction::MoveUp),
+    ("down", KeyAction::MoveDown),
+    ("bksp", KeyAction::Backspace),
+    ("enter", KeyAction::Enter),
+    ("clear", KeyAction::Clear),
+    ("home", KeyAction::Home),
+    ("end", KeyAction::End),
+];
+
+const RANGE_KEYS: &[Binding] = &[
+    (IcKey::Num0, KeyLayer::Base, "0"),
+    (IcKey::Num1, KeyLayer::Base, "1"),
+    (IcKey::Num2, KeyLayer::Base, "2"),
+    (IcKey::Num3, KeyLayer::Base, "3"),
+    (IcKey::Num4, KeyLayer::Base, "4"),
+    (IcKey::Num5, KeyLayer::Base, "5"),
+    (IcKey::Num6, KeyLayer::Base, "6"),
+    (IcKey::Num7, KeyLayer::Base, "7"),
+    (IcKey::Num8, KeyLayer::Base, "8"),
+    (IcKey::Num9, KeyLayer::Base, "9"),
+    (IcKey::Func1, KeyLayer::Base, "bksp"),
+    (IcKey::Func2, KeyLayer::Base, "/"),
+    (IcKey::Func3, KeyLayer::Base, "*"),
+    (IcKey::Func4, KeyLayer::Base, "-"),
+    (IcKey::Func5, KeyLayer::Base, "+"),
+    (IcKey::Func6, KeyLayer::Base, "enter"),
+    (IcKey::Num6, KeyLayer::Shift, "."),
+    (IcKey::Num7, KeyLayer::Shift, "("),
+    (IcKey::Num8, KeyLayer::Shift, ")"),
+    (IcKey::Func6, KeyLayer::Shift, "^"),
+    (IcKey::Num1, KeyLayer::Super, "end"),
+    (IcKey::Num2, KeyLayer::Super, "down"),
+    (IcKey::Num4, KeyLayer::Super, "left"),
+    (IcKey::Num6, KeyLayer::Super, "right"),
+    (IcKey::Num7, KeyLayer::Super, "home"),
+    (IcKey::Num8, KeyLayer::Super, "up"),
+    (IcKey::Num9, KeyLayer::Super, "clear"),
+];
+
 impl RangeMapperCalculator {
     pub fn new() -> RangeMapperCalculator {
+        let palette = Palette::from_index(0);
+        let style = palette.field_style(FieldStyle {
+            border_width: 1,
+            shrink_after: 5,
+            ..FieldStyle::default()
+        });
+        let size = IVec2::new(122, 36);
         RangeMapperCalculator {
-            focused_ui: FocusUi::InValue,
-            input_box_in_val: ExpressionInputBox::new(IVec2::new(50, 7), IVec2::new(122, 36)),
-            input_box_in_min: ExpressionInputBox::new(IVec2::new(50, 56), IVec2::new(122, 36)),
-            input_box_in_max: ExpressionInputBox::new(IVec2::new(188, 56), IVec2::new(122, 36)),
-            input_box_out_min: ExpressionInputBox::new(IVec2::new(50, 105), IVec2::new(122, 36)),
-            input_box_out_max: ExpressionInputBox::new(IVec2::new(188, 105), IVec2::new(122, 36)),
+            focus: FocusManager::new([
+                IVec2::new(0, 0),
+                IVec2::new(0, 1),
+                IVec2::new(1, 1),
+                IVec2::new(0, 2),
+                IVec2::new(1, 2),
+            ]),
+            fields: [
+                TextField::new(IVec2::new(50, 7), size, style),
+                TextField::new(IVec2::new(50, 56), size, style),
+                TextField::new(IVec2::new(188, 56), size, style),
+                TextField::new(IVec2::new(50, 105), size, style),
+                TextField::new(IVec2::new(188, 105), size, style),
+            ],
             answer: 0.0,
+            y_offset: 0,
+            palette,
+            keys: KeyTable::new(RANGE_ACTIONS, RANGE_KEYS),
+            keymap_id: None,
         }
     }
 
-    fn get_action(&self, key: IcKey, is_shifted: bool, is_super: bool) -> Option<KeyAction> {
-        if is_shifted {
-            match key {
-                IcKey::Num0 => None,
-                IcKey::Num1 => None,
-                IcKey::Num2 => None,
-                IcKey::Num3 => None,
-                IcKey::Num4 => None,
-                IcKey::Num5 => None,
-                IcKey::Num6 => Some(KeyAction::InsertChar(b'.')),
-                IcKey::Num7 => Some(KeyAction::InsertChar(b'(')),
-                IcKey::Num8 => Some(KeyAction::InsertChar(b')')),
-                IcKey::Num9 => None,
-                IcKey::Func1 => None,
-                IcKey::Func2 => None,
-                IcKey::Func3 => None,
-                IcKey::Func4 => None,
-                IcKey::Func5 => None,
-                IcKey::Func6 => Some(KeyAction::InsertChar(b'^')),
-                IcKey::Shift => None,
-                IcKey::Super => None,
-                IcKey::_Max => None,
-            }
-        } else if is_super {
-            match key {
-                IcKey::Num0 => None,
-                IcKey::Num1 => Some(KeyAction::End),
-                IcKey::Num2 => Some(KeyAction::MoveDown),
-                IcKey::Num3 => None,
-                IcKey::Num4 => Some(KeyAction::MoveLeft),
-                IcKey::Num5 => None,
-                IcKey::Num6 => Some(KeyAction::MoveRight),
-                IcKey::Num7 => Some(KeyAction::Home),
-                IcKey::Num8 => Some(KeyAction::MoveUp),
-                IcKey::Num9 => Some(KeyAction::Clear),
-                IcKey::Func1 => None,
-                IcKey::Func2 => None,
-                IcKey::Func3 => None,
-                IcKey::Func4 => None,
-                IcKey::Func5 => None,
-                IcKey::Func6 => None,
-                IcKey::Shift => None,
-                IcKey::Super => None,
-                IcKey::_Max => None,
-            }
-        } else {
-            match key {
-                IcKey::Num0 => Some(KeyAction::InsertChar(b'0')),
-                IcKey::Num1 => Some(KeyAction::InsertChar(b'1')),
-                IcKey::Num2 => Some(KeyAction::InsertChar(b'2')),
-                IcKey::Num3 => Some(KeyAction::InsertChar(b'3')),
-                IcKey::Num4 => Some(KeyAction::InsertChar(b'4')),
-                IcKey::Num5 => Some(KeyAction::InsertChar(b'5')),
-                IcKey::Num6 => Some(KeyAction::InsertChar(b'6')),
-                IcKey::Num7 => Some(KeyAction::InsertChar(b'7')),
-                IcKey::Num8 => Some(KeyAction::InsertChar(b'8')),
-                IcKey::Num9 => Some(KeyAction::InsertChar(b'9')),
-                IcKey::Func1 => Some(KeyAction::Backspace),
-                IcKey::Func2 => Some(KeyAction::InsertChar(b'/')),
-                IcKey::Func3 => Some(KeyAction::InsertChar(b'*')),
-                IcKey::Func4 => Some(KeyAction::InsertChar(b'-')),
-                IcKey::Func5 => Some(KeyAction::InsertChar(b'+')),
-                IcKey::Func6 => Some(KeyAction::Enter),
-                IcKey::Shift => None,
-                IcKey::Super => None,
-                IcKey::_Max => None,
+    // The layout already has a 7px top margin, only move it down by however
+    // much the viewport takes beyond that.
+    fn fit_viewport(&mut self, viewport: &Viewport) {
+        let y_offset = (viewport.pos.y - 7).max(0);
+        if y_offset != self.y_offset {
+            for field in self.fields.iter_mut() {
+                field.pos.y += y_offset - self.y_offset;
             }
+            self.y_offset = y_offset;
         }
     }
 
-    fn get_focused_input_box(&mut self) -> &mut ExpressionInputBox {
-        match self.focused_ui {
-            FocusUi::InValue => &mut self.input_box_in_val,
-            FocusUi::InMin => &mut self.input_box_in_min,
-            FocusUi::InMax => &mut self.input_box_in_max,
-            FocusUi::OutMin => &mut self.input_box_out_min,
-            FocusUi::OutMax => &mut self.input_box_out_max,
-        }
+    fn get_action(&self, key: IcKey, is_shifted: bool, is_super: bool) -> Option<KeyAction> {
+        self.keys.get(key, is_shifted, is_super)
+    }
+
+    fn focused_buffer(&mut self) -> &mut LineBuffer<24> {
+        &mut self.fields[self.focus.focused()].buffer
     }
 
     fn has_valid_inputs(&self) -> bool {
@@ -288,11 +190,11 @@ impl RangeMapperCalculator {
         if !self.has_valid_inputs() {
             self.answer = 0.0;
         }
-        let x = self.input_box_in_val.expression.evaluate();
-        let a = self.input_box_in_min.expression.evaluate();
-        let b = self.input_box_in_max.expression.evaluate();
-        let c = self.input_box_out_min.expression.evaluate();
-        let d = self.input_box_out_max.expression.evaluate();
+        let x = evaluate(&self.fields[IN_VALUE]);
+        let a = evaluate(&self.fields[IN_MIN]);
+        let b = evaluate(&self.fields[IN_MAX]);
+        let c = evaluate(&self.fields[OUT_MIN]);
+        let d = evaluate(&self.fields[OUT_MAX]);
         self.answer = c + ((x - a) * (d - c) / (b - a));
     }
 }
@@ -302,79 +204,116 @@ impl IcApp for RangeMapperCalculator {
         ()
     }
 
+    fn key_legend(&self, legend: &mut KeyLegend) {
+        legend.fill(|key, layer| {
+            let (shifted, sup) = layer.modifiers();
+            self.get_action(key, shifted, sup).map(|a| a.label())
+        });
+    }
+
+    fn register_keymaps(&mut self, keymaps: &mut Keymaps) {
+        let def = KeymapDef::new("range", "Range mapper", RANGE_ACTIONS, RANGE_KEYS);
+        self.keymap_id = Some(keymaps.register(def));
+    }
+
+    fn on_keymaps_changed(&mut self, keymaps: &Keymaps) {
+        if let Some(id) = self.keymap_id {
+            self.keys = keymaps.table(id, RANGE_ACTIONS);
+        }
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        for field in self.fields.iter() {
+            w.bytes(field.buffer.as_str().as_bytes());
+        }
+    }
+
+    fn restore_state(&mut self, version: u16, r: &mut StateReader) -> Option<()> {
+        if version != self.state_version() {
+            return None;
+        }
+        let mut contents: [&[u8]; FIELD_COUNT] = [&[]; FIELD_COUNT];
+        for c in contents.iter_mut() {
+            *c = r.bytes()?;
+        }
+        for (field, c) in self.fields.iter_mut().zip(contents) {
+            field.buffer.set_content(c);
+        }
+        self.update_math();
+        Some(())
+    }
+
     fn on_key(&mut self, key: IcKey, ctx: &crate::app::InputContext) {
         let action = self.get_action(key, ctx.is_shifted(), ctx.is_super());
         match action {
             Some(KeyAction::InsertChar(d)) => {
-                self.get_focused_input_box().expression.insert_char(d as u8);
+                self.focused_buffer().insert_char(d as u8);
                 self.update_math();
             }
-            Some(KeyAction::MoveUp) => (),
-            Some(KeyAction::MoveDown) => (),
+            Some(KeyAction::MoveUp) => {
+                self.focus.navigate(NavDir::Up);
+            }
+            Some(KeyAction::MoveDown) => {
+                self.focus.navigate(NavDir::Down);
+            }
             Some(KeyAction::MoveLeft) => {
-                self.get_focused_input_box().expression.move_cursor(false);
+                self.focused_buffer().move_cursor(false);
             }
             Some(KeyAction::MoveRight) => {
-                self.get_focused_input_box().expression.move_cursor(true);
+                self.focused_buffer().move_cursor(true);
             }
             Some(KeyAction::Backspace) => {
-                self.get_focused_input_box().expression.backspace();
+                self.focused_buffer().backspace();
                 self.update_math();
             }
             Some(KeyAction::Clear) => {
-                self.get_focused_input_box().expression.clear();
+                self.focused_buffer().clear();
                 self.update_math();
             }
-            Some(KeyAction::Enter) => {
-                self.focused_ui = match self.focused_ui {
-                    FocusUi::InValue => FocusUi::InMin,
-                    FocusUi::InMin => FocusUi::InMax,
-                    FocusUi::InMax => FocusUi::OutMin,
-                    FocusUi::OutMin => FocusUi::OutMax,
-                    FocusUi::OutMax => FocusUi::InValue,
-                }
-            }
+            Some(KeyAction::Enter) => self.focus.next(),
             Some(KeyAction::Home) => {
-                self.get_focused_input_box().expression.move_cursor_home();
+                self.focused_buffer().move_cursor_home();
             }
             Some(KeyAction::End) => {
-                self.get_focused_input_box().expression.move_cursor_end();
+                self.focused_buffer().move_cursor_end();
             }
             None => (),
         }
     }
 
     fn update(&mut self, platform: &mut dyn IcPlatform, ctx: &crate::app::InputContext) {
-        platform.clear(rgb8_hex(0x1C0770));
-        self.input_box_in_val
-            .draw(platform, self.focused_ui == FocusUi::InValue);
-        self.input_box_in_min
-            .draw(platform, self.focused_ui == FocusUi::InMin);
-        self.input_box_in_max
-            .draw(platform, self.focused_ui == FocusUi::InMax);
-        self.input_box_out_min
-            .draw(platform, self.focused_ui == FocusUi::OutMin);
-        self.input_box_out_max
-            .draw(platform, self.focused_ui == FocusUi::OutMax);
+        self.fit_viewport(&ctx.viewport);
+        let dy = self.y_offset as f32;
+        if self.palette != *ctx.palette {
+            self.palette = *ctx.palette;
+            for field in self.fields.iter_mut() {
+                field.style = self.palette.field_style(field.style);
+            }
+        }
+        let palette = self.palette;
+        platform.clear(palette.background);
+        for (id, field) in self.fields.iter().enumerate() {
+            field.draw(platform, self.focus.is_focused(id));
+        }
         draw_text_f(
             platform,
             format_args!("{}", self.answer),
             49.0,
-            155.0,
+            155.0 + dy,
             4.0,
-            rgb8_hex(0xffffff),
+            palette.accent,
         );
-        draw_text(platform, "Map", 7.0, 19.0, 2.0, rgb8_hex(0xffffff));
-        draw_text(platform, "from", 6.0, 68.0, 2.0, rgb8_hex(0xffffff));
-        draw_text(platform, "to", 28.0, 114.0, 2.0, rgb8_hex(0xffffff));
-        draw_text(platform, "=", 32.0, 162.0, 2.0, rgb8_hex(0xffffff));
+        draw_text(platform, "Map", 7.0, 19.0 + dy, 2.0, palette.text);
+        draw_text(platform, "from", 6.0, 68.0 + dy, 2.0, palette.text);
+        draw_text(platform, "to", 28.0, 114.0 + dy, 2.0, palette.text);
+        draw_text(platform, "=", 32.0, 162.0 + dy, 2.0, palette.text);
         draw_text(
             platform,
             "X = C + ((X-A)*(D-C) / B-A)",
             36.0,
-            207.0,
+            207.0 + dy,
             2.0,
-            rgb8_hex(0x3A9AFF),
+            palette.dim,
         )
     }
 }
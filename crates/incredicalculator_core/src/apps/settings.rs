@@ -0,0 +1,273 @@
+use crate::app::{IcApp, InputContext};
+use crate::font::{self, Align};
+use crate::input::{IcKey, KeyEventKind};
+use crate::keymap::{KeymapId, KeymapIssue, Keymaps, is_reserved};
+use crate::legend::{KeyLayer, KeyLegend};
+use crate::platform::IcPlatform;
+use crate::prefs::{PrefKind, Prefs};
+use crate::text::draw_text;
+use crate::ui::{ListStyle, ListView, NavDir};
+use alloc::format;
+use alloc::string::String;
+use glam::IVec2;
+use num_traits::FromPrimitive;
+
+const ROW_H: i32 = 24;
+// draw_string size of the rows
+const TEXT_SIZE: u32 = 3;
+// below the binding list, for what the validator found
+const FOOTER_H: i32 = 20;
+
+// Every key and layer an app can have a binding on, key by key.
+fn bindable() -> impl Iterator<Item = (IcKey, KeyLayer)> {
+    (0..IcKey::COUNT)
+        .filter_map(IcKey::from_usize)
+        .flat_map(|key| KeyLayer::ALL.map(|layer| (key, layer)))
+        .filter(|(key, layer)| !is_reserved(*key, *layer))
+}
+
+fn slot_name(key: IcKey, layer: KeyLayer) -> String {
+    match layer {
+        KeyLayer::Base => String::from(key.name()),
+        KeyLayer::Shift => format!("Shift {}", key.name()),
+        KeyLayer::Super => format!("Super {}", key.name()),
+    }
+}
+
+fn issue_text(keymaps: &Keymaps, id: KeymapId, issue: KeymapIssue) -> String {
+    match issue {
+        KeymapIssue::Conflict { key, layer } => format!("{} is bound twice", slot_name(key, layer)),
+        KeymapIssue::UnknownAction { name, .. } => format!("Unknown action {}", name),
+        KeymapIssue::Reserved { key, layer } => {
+            format!("{} belongs to the shell", slot_name(key, layer))
+        }
+        KeymapIssue::Unreachable { action } => {
+            format!("{} is on no key", keymaps.def(id).actions[action])
+        }
+    }
+}
+
+// Lists every registered preference, then the apps' keymaps. Up/down picks
+// one, left/right changes it, 5 steps bools and choices forward and opens
+// keymaps. In a keymap left/right cycle the action on the selected key, 0
+// puts back the default and Bk goes back.
+pub struct SettingsApp {
+    list: ListView,
+    // the keymap being edited, None while the preferences are listed
+    keymap: Option<KeymapId>,
+    // row to go back to when the keymap closes
+    keymap_row: usize,
+}
+
+impl Default for SettingsApp {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SettingsApp {
+    pub fn new() -> Self {
+        Self {
+            list: ListView::new(IVec2::ZERO, IVec2::ZERO, ROW_H, ListStyle::default()),
+            keymap: None,
+            keymap_row: 0,
+        }
+    }
+
+    fn value_text(prefs: &Prefs, idx: usize) -> String {
+        let Some(id) = prefs.id_at(idx) else {
+            return String::new();
+        };
+        let value = prefs.get(id);
+        match prefs.def(id).kind {
+            PrefKind::Bool => String::from(if value != 0 { "On" } else { "Off" }),
+            PrefKind::Choice(options) => String::from(options[value as usize]),
+            PrefKind::Range { unit, .. } => format!("{}{}", value, unit),
+        }
+    }
+
+    fn row_count(&self, ctx: &InputContext) -> usize {
+        match self.keymap {
+            Some(_) => bindable().count(),
+            None => ctx.prefs.len() + ctx.keymaps.len(),
+        }
+    }
+
+    // No action is one step before the first.
+    fn step_binding(keymaps: &Keymaps, id: KeymapId, key: IcKey, layer: KeyLayer, forward: bool) {
+        let n = keymaps.def(id).actions.len() + 1;
+        let current = keymaps.get(id, key, layer).map_or(0, |a| a + 1);
+        let next = (current + if forward { 1 } else { n - 1 }) % n;
+        keymaps.set(id, key, layer, next.checked_sub(1));
+    }
+
+    fn keymap_key(&mut self, id: KeymapId, key: IcKey, ctx: &InputContext) {
+        let slot = bindable().nth(self.list.selected());
+        match (key, slot) {
+            (IcKey::Num8, _) => {
+                self.list.navigate(NavDir::Up);
+            }
+            (IcKey::Num2, _) => {
+                self.list.navigate(NavDir::Down);
+            }
+            (IcKey::Num4, Some((k, l))) => Self::step_binding(ctx.keymaps, id, k, l, false),
+            (IcKey::Num6 | IcKey::Num5 | IcKey::Func6, Some((k, l))) => {
+                Self::step_binding(ctx.keymaps, id, k, l, true)
+            }
+            (IcKey::Num0, Some((k, l))) => ctx.keymaps.reset(id, k, l),
+            (IcKey::Func1, _) => {
+                self.keymap = None;
+                self.list.set_len(self.row_count(ctx));
+                self.list.select(self.keymap_row);
+            }
+            _ => {}
+        }
+    }
+
+    fn draw_keymap(&self, platform: &mut dyn IcPlatform, id: KeymapId, ctx: &InputContext) {
+        let palette = *ctx.palette;
+        let keymaps = ctx.keymaps;
+        let right = ctx.viewport.size.x - 16;
+        let text_offset = (ROW_H - font::for_size(TEXT_SIZE).height() as i32) / 2;
+        self.list.draw_with(platform, true, |platform, idx, top_left, _| {
+            let Some((key, layer)) = bindable().nth(idx) else {
+                return;
+            };
+            let y = top_left.y + text_offset;
+            let name = slot_name(key, layer);
+            platform.draw_string(&name, IVec2::new(6, y), TEXT_SIZE, palette.text);
+            let value = keymaps
+                .get(id, key, layer)
+                .map_or("-", |a| keymaps.def(id).actions[a]);
+            // rebound keys stand out from the app's defaults
+            let color = if keymaps.is_default(id, key, layer) {
+                palette.dim
+            } else {
+                palette.accent
+            };
+            let value_pos = IVec2::new(right, y);
+            platform.draw_string_aligned(value, value_pos, TEXT_SIZE, Align::Right, color);
+        });
+
+        let issues = keymaps.validate(id);
+        let footer_y = (ctx.viewport.pos.y + ctx.viewport.size.y - FOOTER_H + 4) as f32;
+        let (text, color) = match issues.first() {
+            Some(issue) if issues.len() > 1 => (
+                format!("{} (+{} more)", issue_text(keymaps, id, *issue), issues.len() - 1),
+                palette.error,
+            ),
+            Some(issue) => (issue_text(keymaps, id, *issue), palette.error),
+            None => (String::from(keymaps.def(id).label), palette.dim),
+        };
+        draw_text(platform, &text, 6.0, footer_y, 1.5, color);
+    }
+}
+
+impl IcApp for SettingsApp {
+    fn on_enter(&mut self) {}
+
+    fn on_key(&mut self, key: IcKey, ctx: &InputContext) {
+        self.list.set_len(self.row_count(ctx));
+        if let Some(id) = self.keymap {
+            self.keymap_key(id, key, ctx);
+            return;
+        }
+        let row = self.list.selected();
+        let selected = ctx.prefs.id_at(row);
+        let keymap = row.checked_sub(ctx.prefs.len()).and_then(|i| ctx.keymaps.id_at(i));
+        match key {
+            IcKey::Num8 => {
+                self.list.navigate(NavDir::Up);
+            }
+            IcKey::Num2 => {
+                self.list.navigate(NavDir::Down);
+            }
+            IcKey::Num4 => {
+                if let Some(id) = selected {
+                    ctx.prefs.step(id, false);
+                }
+            }
+            IcKey::Num6 | IcKey::Num5 | IcKey::Func6 => {
+                if let Some(id) = selected {
+                    ctx.prefs.step(id, true);
+                } else if keymap.is_some() {
+                    self.keymap = keymap;
+                    self.keymap_row = row;
+                    self.list.set_len(self.row_count(ctx));
+                    self.list.select(0);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn on_key_event(&mut self, key: IcKey, kind: KeyEventKind, ctx: &InputContext) {
+        let repeats = matches!(key, IcKey::Num8 | IcKey::Num2 | IcKey::Num4 | IcKey::Num6);
+        if kind == KeyEventKind::Press || (kind == KeyEventKind::Repeat && repeats) {
+            self.on_key(key, ctx);
+        }
+    }
+
+    fn key_legend(&self, legend: &mut KeyLegend) {
+        let labels: &[(IcKey, &str)] = match self.keymap {
+            Some(_) => &[
+                (IcKey::Num8, "^"),
+                (IcKey::Num2, "v"),
+                (IcKey::Num4, "-"),
+                (IcKey::Num6, "+"),
+                (IcKey::Num5, "+"),
+                (IcKey::Func6, "+"),
+                (IcKey::Num0, "Def"),
+                (IcKey::Func1, "Back"),
+            ],
+            None => &[
+                (IcKey::Num8, "^"),
+                (IcKey::Num2, "v"),
+                (IcKey::Num4, "-"),
+                (IcKey::Num6, "+"),
+                (IcKey::Num5, "+"),
+                (IcKey::Func6, "+"),
+            ],
+        };
+        for (key, label) in labels {
+            legend.set(*key, KeyLayer::Base, label);
+        }
+    }
+
+    fn update(&mut self, platform: &mut dyn IcPlatform, ctx: &InputContext) {
+        let palette = *ctx.palette;
+        platform.clear(palette.background);
+        self.list.style = palette.list_style();
+        self.list.pos = ctx.viewport.pos + IVec2::new(0, 4);
+        self.list.size = ctx.viewport.size - IVec2::new(0, 4);
+        self.list.set_len(self.row_count(ctx));
+        if let Some(id) = self.keymap {
+            self.list.size.y -= FOOTER_H;
+            self.draw_keymap(platform, id, ctx);
+            return;
+        }
+        let prefs = ctx.prefs;
+        let keymaps = ctx.keymaps;
+        let right = ctx.viewport.size.x - 16;
+        let text_offset = (ROW_H - font::for_size(TEXT_SIZE).height() as i32) / 2;
+        self.list.draw_with(platform, true, |platform, idx, top_left, _| {
+            let y = top_left.y + text_offset;
+            let (label, value, color) = if let Some(id) = prefs.id_at(idx) {
+                (String::from(prefs.def(id).label), Self::value_text(prefs, idx), palette.accent)
+            } else if let Some(id) = keymaps.id_at(idx - prefs.len()) {
+                let issues = keymaps.validate(id).len();
+                let label = format!("Keys: {}", keymaps.def(id).label);
+                match issues {
+                    0 => (label, String::from(">"), palette.accent),
+                    1 => (label, String::from("1 issue"), palette.error),
+                    n => (label, format!("{} issues", n), palette.error),
+                }
+            } else {
+                return;
+            };
+            platform.draw_string(&label, IVec2::new(6, y), TEXT_SIZE, palette.text);
+            let value_pos = IVec2::new(right, y);
+            platform.draw_string_aligned(&value, value_pos, TEXT_SIZE, Align::Right, color);
+        });
+    }
+}
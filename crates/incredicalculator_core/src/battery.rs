@@ -0,0 +1,123 @@
+use crate::font::Align;
+use crate::platform::IcPlatform;
+use crate::theme::Palette;
+use crate::ui::SCREEN_SIZE;
+use alloc::format;
+use glam::IVec2;
+
+// how far the charge has to climb back over a threshold before it counts as
+// crossed again, so a reading that jitters around it doesn't warn repeatedly
+const HYSTERESIS: i32 = 2;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BatteryLevel {
+    Normal,
+    // the shell warns once when this is reached
+    Low,
+    // the shell saves everything and only shows the shutdown screen
+    Critical,
+}
+
+// Percentages at or below which the battery counts as low or critical.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct BatteryThresholds {
+    pub low: i32,
+    pub critical: i32,
+}
+
+impl Default for BatteryThresholds {
+    fn default() -> Self {
+        Self {
+            low: 20,
+            critical: 5,
+        }
+    }
+}
+
+// Turns state of charge readings into levels. Charging always counts as
+// Normal, an unknown charge (negative) too.
+pub struct BatteryMonitor {
+    pub thresholds: BatteryThresholds,
+    level: BatteryLevel,
+}
+
+impl BatteryMonitor {
+    pub fn new(thresholds: BatteryThresholds) -> Self {
+        Self {
+            thresholds,
+            level: BatteryLevel::Normal,
+        }
+    }
+
+    pub fn level(&self) -> BatteryLevel {
+        self.level
+    }
+
+    // Returns the new level when it changed.
+    pub fn update(&mut self, soc: i32, charging: bool) -> Option<BatteryLevel> {
+        let critical = self.thresholds.critical.min(self.thresholds.low);
+        let low = self.thresholds.low;
+        // thresholds move up by the hysteresis while they are in effect
+        let margin = |level: BatteryLevel| if self.level == level { HYSTERESIS } else { 0 };
+        let level = if charging || soc < 0 {
+            BatteryLevel::Normal
+        } else if soc <= critical + margin(BatteryLevel::Critical) {
+            BatteryLevel::Critical
+        } else if soc <= low + margin(BatteryLevel::Low).max(margin(BatteryLevel::Critical)) {
+            BatteryLevel::Low
+        } else {
+            BatteryLevel::Normal
+        };
+        if level == self.level {
+            return None;
+        }
+        self.level = level;
+        Some(level)
+    }
+}
+
+// Shown instead of everything else while the battery is critical.
+pub fn draw_shutdown_screen(platform: &mut dyn IcPlatform, soc: i32, palette: &Palette) {
+    platform.clear(palette.background);
+    let lines = ["Battery empty", "Work saved, connect", "a charger to go on"];
+    let center = SCREEN_SIZE.x / 2;
+    let mut y = 70;
+    for (i, line) in lines.iter().enumerate() {
+        let (size, color) = if i == 0 { (4, palette.error) } else { (3, palette.text) };
+        platform.draw_string_aligned(line, IVec2::new(center, y), size, Align::Center, color);
+        y += if i == 0 { 40 } else { 24 };
+    }
+    platform.draw_string_aligned(
+        &format!("{}%", soc.max(0)),
+        IVec2::new(center, y + 16),
+        3,
+        Align::Center,
+        palette.dim,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels_warn_once_per_crossing() {
+        let mut monitor = BatteryMonitor::new(BatteryThresholds {
+            low: 20,
+            critical: 5,
+        });
+        assert_eq!(monitor.update(50, false), None);
+        assert_eq!(monitor.update(20, false), Some(BatteryLevel::Low));
+        // jitter around the threshold stays Low
+        assert_eq!(monitor.update(21, false), None);
+        assert_eq!(monitor.update(19, false), None);
+        assert_eq!(monitor.update(5, false), Some(BatteryLevel::Critical));
+        assert_eq!(monitor.update(7, false), None);
+        // plugging in lifts the shutdown straight away
+        assert_eq!(monitor.update(6, true), Some(BatteryLevel::Normal));
+        assert_eq!(monitor.update(5, false), Some(BatteryLevel::Critical));
+        assert_eq!(monitor.update(8, false), Some(BatteryLevel::Low));
+        assert_eq!(monitor.update(23, false), Some(BatteryLevel::Normal));
+        assert_eq!(monitor.update(-1, false), None);
+    }
+}
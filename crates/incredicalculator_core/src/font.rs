@@ -0,0 +1,368 @@
+use alloc::borrow::Cow;
+use alloc::format;
+use alloc::vec::Vec;
+use core::convert::Infallible;
+use embedded_graphics::image::{Image, ImageDrawableExt};
+use embedded_graphics::mono_font::MonoFont;
+use embedded_graphics::mono_font::iso_8859_7::{FONT_6X10, FONT_7X13, FONT_9X15, FONT_10X20};
+use embedded_graphics::pixelcolor::BinaryColor;
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::Rectangle;
+
+// tallest glyph there is, the 10x20 font doubled
+const MAX_ROWS: usize = 40;
+
+// Fonts by draw_string size, smallest first. The largest is meant for
+// calculator results: the 10x20 glyphs smoothed up to twice their size.
+static FONTS: [Font; 5] = [
+    Font::new(&FONT_6X10, false),
+    Font::new(&FONT_7X13, false),
+    Font::new(&FONT_9X15, false),
+    Font::new(&FONT_10X20, false),
+    Font::new(&FONT_10X20, true),
+];
+
+pub const MAX_SIZE: u32 = FONTS.len() as u32;
+// the big font results are drawn in
+pub const RESULT_SIZE: u32 = MAX_SIZE;
+
+// The font draw_string uses for size. 0 gets the smallest and sizes past
+// MAX_SIZE the largest.
+pub fn for_size(size: u32) -> &'static Font {
+    &FONTS[(size.clamp(1, MAX_SIZE) - 1) as usize]
+}
+
+// drawn where truncate cut text off
+pub const ELLIPSIS: char = '…';
+
+// Where the x given to draw_string_aligned sits on the text.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Align {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+impl Align {
+    // left edge of something width wide aligned at x
+    pub fn start(self, x: i32, width: i32) -> i32 {
+        match self {
+            Align::Left => x,
+            Align::Center => x - width / 2,
+            Align::Right => x - width,
+        }
+    }
+}
+
+// Bitmap of one character, bit x of a row is the pixel in column x.
+#[derive(Clone, Copy)]
+pub struct Glyph {
+    rows: [u32; MAX_ROWS],
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Glyph {
+    fn blank(width: u32, height: u32) -> Self {
+        Self {
+            rows: [0; MAX_ROWS],
+            width,
+            height,
+        }
+    }
+
+    pub fn get(&self, x: i32, y: i32) -> bool {
+        x >= 0
+            && y >= 0
+            && (x as u32) < self.width
+            && (y as u32) < self.height
+            && self.rows[y as usize] & (1 << x) != 0
+    }
+
+    fn set(&mut self, x: i32, y: i32) {
+        if x >= 0 && y >= 0 && (x as u32) < self.width && (y as u32) < self.height {
+            self.rows[y as usize] |= 1 << x;
+        }
+    }
+
+    // first column with ink and one past the last, None for a blank glyph
+    fn ink(&self) -> Option<(u32, u32)> {
+        let all = self.rows.iter().fold(0, |acc, r| acc | r);
+        (all != 0).then(|| (all.trailing_zeros(), 32 - all.leading_zeros()))
+    }
+
+    fn line(&mut self, from: (i32, i32), to: (i32, i32)) {
+        let (mut x, mut y) = from;
+        let dx = (to.0 - x).abs();
+        let dy = -(to.1 - y).abs();
+        let sx = if x < to.0 { 1 } else { -1 };
+        let sy = if y < to.1 { 1 } else { -1 };
+        let mut err = dx + dy;
+        loop {
+            self.set(x, y);
+            if (x, y) == to {
+                break;
+            }
+            let e2 = 2 * err;
+            if e2 >= dy {
+                err += dy;
+                x += sx;
+            }
+            if e2 <= dx {
+                err += dx;
+                y += sy;
+            }
+        }
+    }
+
+    // Scale2x: every pixel becomes four, diagonals get filled in instead of
+    // turning into stairs.
+    fn doubled(&self) -> Self {
+        let mut out = Self::blank(self.width * 2, self.height * 2);
+        for y in 0..self.height as i32 {
+            for x in 0..self.width as i32 {
+                let p = self.get(x, y);
+                let a = self.get(x, y - 1);
+                let b = self.get(x + 1, y);
+                let c = self.get(x - 1, y);
+                let d = self.get(x, y + 1);
+                let quads = [
+                    if c == a && c != d && a != b { a } else { p },
+                    if a == b && a != c && b != d { b } else { p },
+                    if d == c && d != b && c != a { c } else { p },
+                    if b == d && b != a && d != c { d } else { p },
+                ];
+                for (i, on) in quads.into_iter().enumerate() {
+                    if on {
+                        out.set(x * 2 + (i as i32 & 1), y * 2 + (i as i32 >> 1));
+                    }
+                }
+            }
+        }
+        out
+    }
+}
+
+impl OriginDimensions for Glyph {
+    fn size(&self) -> Size {
+        Size::new(self.width, self.height)
+    }
+}
+
+// lets the font images draw themselves into a glyph
+impl DrawTarget for Glyph {
+    type Color = BinaryColor;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(p, color) in pixels {
+            if color.is_on() {
+                self.set(p.x, p.y);
+            }
+        }
+        Ok(())
+    }
+}
+
+type Stroke = ((i32, i32), (i32, i32));
+
+// Math symbols the bitmap fonts don't have, as strokes on a 9x9 grid that
+// covers the cell from cap height down to the baseline.
+fn symbol_strokes(c: char) -> Option<&'static [Stroke]> {
+    Some(match c {
+        '√' => &[((0, 5), (2, 8)), ((2, 8), (5, 0)), ((5, 0), (8, 0))],
+        '∠' => &[((7, 1), (0, 8)), ((0, 8), (8, 8))],
+        '×' => &[((1, 3), (6, 8)), ((6, 3), (1, 8))],
+        '÷' => &[((0, 5), (7, 5)), ((3, 2), (4, 2)), ((3, 8), (4, 8))],
+        '…' => &[((0, 8), (0, 8)), ((3, 8), (3, 8)), ((6, 8), (6, 8))],
+        _ => return None,
+    })
+}
+
+// One of the embedded-graphics mono fonts, with proportional spacing worked
+// out from the ink in each glyph. Digits keep the full cell width so numbers
+// line up in columns.
+pub struct Font {
+    mono: &'static MonoFont<'static>,
+    double: bool,
+}
+
+impl Font {
+    const fn new(mono: &'static MonoFont<'static>, double: bool) -> Self {
+        Self { mono, double }
+    }
+
+    fn scale(&self) -> u32 {
+        if self.double { 2 } else { 1 }
+    }
+
+    pub fn height(&self) -> u32 {
+        self.mono.character_size.height * self.scale()
+    }
+
+    // distance from the top of a line to the baseline
+    pub fn baseline(&self) -> u32 {
+        self.mono.baseline * self.scale()
+    }
+
+    pub fn glyph(&self, c: char) -> Glyph {
+        let cell = self.mono.character_size;
+        let mut glyph = Glyph::blank(cell.width, cell.height);
+        if let Some(strokes) = symbol_strokes(c) {
+            let right = cell.width as i32 - 2;
+            let bottom = self.mono.baseline as i32;
+            let top = bottom / 6;
+            // the 10x20 font has two pixel wide downstrokes
+            let weight = (cell.width as i32 / 5).max(1);
+            let at = |(x, y): (i32, i32), dx| (x * right / 8 + dx, top + y * (bottom - top) / 8);
+            for (from, to) in strokes {
+                for dx in 0..weight {
+                    glyph.line(at(*from, dx), at(*to, dx));
+                }
+            }
+        } else {
+            // the Greek fonts have mu but not the micro sign
+            let c = if c == 'µ' { 'μ' } else { c };
+            let per_row = self.mono.image.size().width / cell.width;
+            let index = self.mono.glyph_mapping.index(c) as u32;
+            let origin = Point::new(
+                ((index % per_row) * cell.width) as i32,
+                ((index / per_row) * cell.height) as i32,
+            );
+            let sub = self.mono.image.sub_image(&Rectangle::new(origin, cell));
+            Image::new(&sub, Point::zero()).draw(&mut glyph).unwrap();
+        }
+        if self.double { glyph.doubled() } else { glyph }
+    }
+
+    // The glyph for c, the column its ink starts at and how far the pen
+    // moves past it.
+    fn placed(&self, c: char) -> (Glyph, u32, u32) {
+        let glyph = self.glyph(c);
+        let cell_w = glyph.width;
+        match glyph.ink() {
+            _ if c.is_ascii_digit() => (glyph, 0, cell_w),
+            Some((start, end)) => (glyph, start, end - start + self.scale()),
+            None => (glyph, 0, cell_w / 2),
+        }
+    }
+
+    pub fn advance(&self, c: char) -> u32 {
+        self.placed(c).2
+    }
+
+    // width of the widest line
+    pub fn width(&self, text: &str) -> u32 {
+        text.split('\n')
+            .map(|line| line.chars().map(|c| self.advance(c)).sum())
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn measure(&self, text: &str) -> Size {
+        Size::new(self.width(text), self.height() * text.split('\n').count() as u32)
+    }
+
+    // text cut short with an ellipsis where it is wider than max_w
+    pub fn truncate<'a>(&self, text: &'a str, max_w: u32) -> Cow<'a, str> {
+        if self.width(text) <= max_w {
+            return Cow::Borrowed(text);
+        }
+        let room = max_w.saturating_sub(self.advance(ELLIPSIS));
+        let mut w = 0;
+        let end = text
+            .char_indices()
+            .find(|(_, c)| {
+                w += self.advance(*c);
+                w > room
+            })
+            .map_or(text.len(), |(i, _)| i);
+        Cow::Owned(format!("{}{}", &text[..end], ELLIPSIS))
+    }
+
+    // Splits text into lines no wider than max_w. Lines break after the last
+    // space that fits, words longer than a line break wherever they have to.
+    pub fn wrap<'a>(&self, text: &'a str, max_w: u32) -> Vec<&'a str> {
+        let mut lines = Vec::new();
+        for para in text.split('\n') {
+            let mut start = 0;
+            let mut w = 0;
+            let mut space = None;
+            for (i, c) in para.char_indices() {
+                let advance = self.advance(c);
+                // spaces can hang past the edge, they are cut off anyway
+                if c != ' ' && w + advance > max_w && i > start {
+                    let (end, next) = space.map_or((i, i), |s| (s, s + 1));
+                    lines.push(&para[start..end]);
+                    start = next;
+                    w = self.width(&para[start..i]);
+                    space = None;
+                }
+                if c == ' ' {
+                    space = Some(i);
+                }
+                w += advance;
+            }
+            lines.push(&para[start..]);
+        }
+        lines
+    }
+
+    // Draws text with its top left at pos and returns where the pen ended up.
+    // '\n' starts a new line under the first.
+    pub fn draw<D: DrawTarget>(&self, target: &mut D, text: &str, pos: Point, color: D::Color) -> Point {
+        let mut pen = pos;
+        for c in text.chars() {
+            if c == '\n' {
+                pen = Point::new(pos.x, pen.y + self.height() as i32);
+                continue;
+            }
+            let (glyph, start, advance) = self.placed(c);
+            let pixels = (0..glyph.height as i32).flat_map(|y| {
+                (start as i32..glyph.width as i32)
+                    .filter(move |x| glyph.get(*x, y))
+                    .map(move |x| Pixel(pen + Point::new(x - start as i32, y), color))
+            });
+            let _ = target.draw_iter(pixels);
+            pen.x += advance as i32;
+        }
+        pen
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spacing_is_proportional_but_digits_are_not() {
+        let font = for_size(4);
+        assert!(font.advance('.') < font.advance('W'));
+        assert_eq!(font.advance('1'), font.advance('8'));
+        assert_eq!(for_size(0).height(), 10);
+        assert_eq!(for_size(99).height(), 40);
+        for c in ['π', 'µ', 'Ω', '√', '∠'] {
+            assert!(font.glyph(c).ink().is_some());
+            assert!(font.glyph(c).rows != font.glyph('?').rows);
+        }
+    }
+
+    #[test]
+    fn truncate_and_wrap_stay_within_width() {
+        let font = for_size(2);
+        let text = "the quick brown fox";
+        let max_w = font.width("the quick");
+        assert_eq!(font.wrap(text, max_w), ["the quick", "brown fox"]);
+        assert_eq!(font.wrap("abcdefgh\nab", font.width("abc")), ["abc", "def", "gh", "ab"]);
+        assert_eq!(font.truncate(text, 1000), text);
+        let cut = font.truncate(text, max_w);
+        assert!(cut.ends_with(ELLIPSIS) && text.starts_with(cut.trim_end_matches(ELLIPSIS)));
+        assert!(font.width(&cut) <= max_w);
+        assert_eq!(font.measure("a\nb").height, 2 * font.height());
+        assert_eq!(Align::Center.start(100, 20), 90);
+    }
+}
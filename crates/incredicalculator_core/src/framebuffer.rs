@@ -0,0 +1,543 @@
+use crate::font;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::convert::Infallible;
+use embedded_graphics::pixelcolor::Rgb565;
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::{
+    Arc, Circle, Ellipse, Line, Polyline, PrimitiveStyle, PrimitiveStyleBuilder, Rectangle,
+    RoundedRectangle, StrokeAlignment, Triangle,
+};
+use glam::IVec2;
+use rgb::RGB8;
+
+// Size of the blocks the screen is split into for change detection. 320x240
+// divides evenly into these.
+pub const TILE_W: u32 = 32;
+pub const TILE_H: u32 = 16;
+// Past this many rectangles the window setup overhead outweighs the saved
+// pixels, so take_dirty_rects sends their bounding box instead.
+const MAX_DIRTY_RECTS: usize = 24;
+
+pub fn rgb8_to_rgb565(c: RGB8) -> Rgb565 {
+    Rgb565::new(c.r >> 3, c.g >> 2, c.b >> 3)
+}
+
+// f32::ceil needs std
+fn ceil(v: f32) -> i32 {
+    let t = v as i32;
+    if (t as f32) < v { t + 1 } else { t }
+}
+
+fn point(v: IVec2) -> Point {
+    Point::new(v.x, v.y)
+}
+
+fn shape_style(
+    stroke_color: RGB8,
+    stroke_width: u32,
+    fill_color: Option<RGB8>,
+) -> PrimitiveStyle<Rgb565> {
+    let mut style_builder = PrimitiveStyleBuilder::new()
+        .stroke_color(rgb8_to_rgb565(stroke_color))
+        .stroke_width(stroke_width)
+        .stroke_alignment(StrokeAlignment::Center);
+    if let Some(c) = fill_color {
+        style_builder = style_builder.fill_color(rgb8_to_rgb565(c));
+    }
+    style_builder.build()
+}
+
+// RGB565 canvas shared by the platforms. It implements the drawing half of
+// IcPlatform so the PC and RP builds only forward to it, and it remembers what
+// each tile looked like at the last flush so only changed areas get sent to
+// the display. Storage is anything that derefs to a pixel slice, eg. a static
+// array on the RP or a Vec on the PC.
+pub struct FrameBuffer<S> {
+    storage: S,
+    width: u32,
+    height: u32,
+    tile_hashes: Vec<u32>,
+    // the display content is unknown, next flush has to send everything
+    invalid: bool,
+}
+
+impl<S: AsRef<[Rgb565]> + AsMut<[Rgb565]>> FrameBuffer<S> {
+    pub fn new(storage: S, width: u32, height: u32) -> Self {
+        assert!(storage.as_ref().len() >= (width * height) as usize);
+        let tiles = (width.div_ceil(TILE_W) * height.div_ceil(TILE_H)) as usize;
+        Self {
+            storage,
+            width,
+            height,
+            tile_hashes: vec![0; tiles],
+            invalid: true,
+        }
+    }
+
+    pub fn pixels(&self) -> &[Rgb565] {
+        &self.storage.as_ref()[..(self.width * self.height) as usize]
+    }
+
+    pub fn clear(&mut self, color: RGB8) {
+        self.storage.as_mut().fill(rgb8_to_rgb565(color));
+    }
+
+    pub fn draw_line(&mut self, start: IVec2, end: IVec2, color: RGB8, width: u32) {
+        Line::new(point(start), point(end))
+            .into_styled(PrimitiveStyle::with_stroke(rgb8_to_rgb565(color), width))
+            .draw(self)
+            .unwrap();
+    }
+
+    pub fn draw_rectangle(
+        &mut self,
+        start: IVec2,
+        end: IVec2,
+        stroke_color: RGB8,
+        stroke_width: u32,
+        fill_color: Option<RGB8>,
+    ) {
+        Rectangle::with_corners(point(start), point(end))
+            .into_styled(shape_style(stroke_color, stroke_width, fill_color))
+            .draw(self)
+            .unwrap();
+    }
+
+    pub fn draw_rectangle_rounded(
+        &mut self,
+        start: IVec2,
+        end: IVec2,
+        stroke_color: RGB8,
+        stroke_width: u32,
+        fill_color: Option<RGB8>,
+        corner_radius: u32,
+    ) {
+        RoundedRectangle::with_equal_corners(
+            Rectangle::with_corners(point(start), point(end)),
+            Size::new(corner_radius, corner_radius),
+        )
+        .into_styled(shape_style(stroke_color, stroke_width, fill_color))
+        .draw(self)
+        .unwrap();
+    }
+
+    pub fn draw_triangle(
+        &mut self,
+        vertex1: IVec2,
+        vertex2: IVec2,
+        vertex3: IVec2,
+        stroke_color: RGB8,
+        stroke_width: u32,
+        fill_color: Option<RGB8>,
+    ) {
+        Triangle::new(point(vertex1), point(vertex2), point(vertex3))
+            .into_styled(shape_style(stroke_color, stroke_width, fill_color))
+            .draw(self)
+            .unwrap();
+    }
+
+    pub fn draw_circle(
+        &mut self,
+        center: IVec2,
+        radius: u32,
+        stroke_color: RGB8,
+        stroke_width: u32,
+        fill_color: Option<RGB8>,
+    ) {
+        Circle::with_center(point(center), radius * 2 + 1)
+            .into_styled(shape_style(stroke_color, stroke_width, fill_color))
+            .draw(self)
+            .unwrap();
+    }
+
+    pub fn draw_ellipse(
+        &mut self,
+        center: IVec2,
+        radii: IVec2,
+        stroke_color: RGB8,
+        stroke_width: u32,
+        fill_color: Option<RGB8>,
+    ) {
+        let size = Size::new(radii.x.max(0) as u32 * 2 + 1, radii.y.max(0) as u32 * 2 + 1);
+        Ellipse::with_center(point(center), size)
+            .into_styled(shape_style(stroke_color, stroke_width, fill_color))
+            .draw(self)
+            .unwrap();
+    }
+
+    // angles in degrees clockwise from 3 o'clock
+    pub fn draw_arc(
+        &mut self,
+        center: IVec2,
+        radius: u32,
+        start_deg: f32,
+        sweep_deg: f32,
+        color: RGB8,
+        width: u32,
+    ) {
+        Arc::with_center(
+            point(center),
+            radius * 2 + 1,
+            Angle::from_degrees(start_deg),
+            Angle::from_degrees(sweep_deg),
+        )
+        .into_styled(PrimitiveStyle::with_stroke(rgb8_to_rgb565(color), width))
+        .draw(self)
+        .unwrap();
+    }
+
+    pub fn draw_polyline(&mut self, points: &[IVec2], color: RGB8, width: u32) {
+        let points: Vec<Point> = points.iter().map(|p| point(*p)).collect();
+        Polyline::new(&points)
+            .into_styled(PrimitiveStyle::with_stroke(rgb8_to_rgb565(color), width))
+            .draw(self)
+            .unwrap();
+    }
+
+    // Closed outline through points. Edges may cross, where they do the fill
+    // follows the even-odd rule.
+    pub fn draw_polygon(
+        &mut self,
+        points: &[IVec2],
+        stroke_color: RGB8,
+        stroke_width: u32,
+        fill_color: Option<RGB8>,
+    ) {
+        if points.len() < 2 {
+            return;
+        }
+        if let Some(c) = fill_color {
+            self.fill_polygon(points, rgb8_to_rgb565(c));
+        }
+        if stroke_width > 0 {
+            let mut closed = points.to_vec();
+            closed.push(points[0]);
+            self.draw_polyline(&closed, stroke_color, stroke_width);
+        }
+    }
+
+    // Scanline fill, a pixel is inside when its centre is.
+    fn fill_polygon(&mut self, points: &[IVec2], color: Rgb565) {
+        let top = points.iter().map(|p| p.y).min().unwrap().max(0);
+        let bottom = points.iter().map(|p| p.y).max().unwrap().min(self.height as i32);
+        let mut crossings: Vec<f32> = Vec::new();
+        for y in top..bottom {
+            let yc = y as f32 + 0.5;
+            crossings.clear();
+            for (i, a) in points.iter().enumerate() {
+                let b = points[(i + 1) % points.len()];
+                let (ay, by) = (a.y as f32, b.y as f32);
+                if (ay <= yc) != (by <= yc) {
+                    crossings.push(a.x as f32 + (yc - ay) * (b.x - a.x) as f32 / (by - ay));
+                }
+            }
+            crossings.sort_by(|a, b| a.total_cmp(b));
+            for span in crossings.chunks_exact(2) {
+                let x0 = ceil(span[0] - 0.5);
+                let x1 = ceil(span[1] - 0.5);
+                if x1 > x0 {
+                    let row = Rectangle::new(Point::new(x0, y), Size::new((x1 - x0) as u32, 1));
+                    self.fill_solid(&row, color).unwrap();
+                }
+            }
+        }
+    }
+
+    pub fn draw_pixel(&mut self, pos: IVec2, color: RGB8) {
+        Pixel(point(pos), rgb8_to_rgb565(color)).draw(self).unwrap();
+    }
+
+    // Copies pixels, row by row width wide, with their top left at pos.
+    pub fn draw_pixels(&mut self, pos: IVec2, width: u32, pixels: &[RGB8]) {
+        if width == 0 {
+            return;
+        }
+        let area = Rectangle::new(point(pos), Size::new(width, pixels.len() as u32 / width));
+        self.fill_contiguous(&area, pixels.iter().map(|c| rgb8_to_rgb565(*c)))
+            .unwrap();
+    }
+
+    // size picks one of the fonts in font.rs, 4 is the 10x20 one
+    pub fn draw_string(&mut self, text: &str, pos: IVec2, size: u32, color: RGB8) {
+        font::for_size(size).draw(self, text, point(pos), rgb8_to_rgb565(color));
+    }
+
+    // Forget what the display shows, eg. after it was reset or something
+    // else drew on it. The next take_dirty_rects covers the whole screen.
+    pub fn invalidate(&mut self) {
+        self.invalid = true;
+    }
+
+    fn tile_rect(&self, tx: u32, ty: u32) -> Rectangle {
+        let x = tx * TILE_W;
+        let y = ty * TILE_H;
+        Rectangle::new(
+            Point::new(x as i32, y as i32),
+            Size::new(TILE_W.min(self.width - x), TILE_H.min(self.height - y)),
+        )
+    }
+
+    // FNV-1a over the tile's pixels
+    fn tile_hash(&self, tx: u32, ty: u32) -> u32 {
+        let rect = self.tile_rect(tx, ty);
+        let pixels = self.pixels();
+        let mut hash: u32 = 0x811c9dc5;
+        for y in rect.rows() {
+            let row_start = (y as u32 * self.width) as usize + rect.top_left.x as usize;
+            for p in &pixels[row_start..row_start + rect.size.width as usize] {
+                let raw = p.into_storage();
+                hash = (hash ^ (raw & 0xff) as u32).wrapping_mul(0x01000193);
+                hash = (hash ^ (raw >> 8) as u32).wrapping_mul(0x01000193);
+            }
+        }
+        hash
+    }
+
+    // Areas that changed since the last call, merged into as few rectangles
+    // as is cheap to work out. Call once per flush, it marks everything as
+    // sent.
+    pub fn take_dirty_rects(&mut self) -> Vec<Rectangle> {
+        let tiles_x = self.width.div_ceil(TILE_W);
+        let tiles_y = self.height.div_ceil(TILE_H);
+        let mut rects: Vec<Rectangle> = Vec::new();
+        for ty in 0..tiles_y {
+            let mut run_start: Option<u32> = None;
+            for tx in 0..=tiles_x {
+                let dirty = if tx < tiles_x {
+                    let idx = (ty * tiles_x + tx) as usize;
+                    let hash = self.tile_hash(tx, ty);
+                    let changed = self.invalid || hash != self.tile_hashes[idx];
+                    self.tile_hashes[idx] = hash;
+                    changed
+                } else {
+                    false
+                };
+                match (dirty, run_start) {
+                    (true, None) => run_start = Some(tx),
+                    (false, Some(start)) => {
+                        let first = self.tile_rect(start, ty);
+                        let last = self.tile_rect(tx - 1, ty);
+                        let run = Rectangle::new(
+                            first.top_left,
+                            Size::new(
+                                (last.top_left.x - first.top_left.x) as u32 + last.size.width,
+                                first.size.height,
+                            ),
+                        );
+                        Self::push_merged(&mut rects, run);
+                        run_start = None;
+                    }
+                    _ => (),
+                }
+            }
+        }
+        self.invalid = false;
+        if rects.len() > MAX_DIRTY_RECTS {
+            let bounds = rects
+                .iter()
+                .skip(1)
+                .fold(rects[0], |acc, r| Self::bounding_box(&acc, r));
+            rects.clear();
+            rects.push(bounds);
+        }
+        rects
+    }
+
+    // grows a rectangle from the row above when the new run lines up with it
+    fn push_merged(rects: &mut Vec<Rectangle>, run: Rectangle) {
+        for r in rects.iter_mut() {
+            if r.top_left.x == run.top_left.x
+                && r.size.width == run.size.width
+                && r.top_left.y + r.size.height as i32 == run.top_left.y
+            {
+                r.size.height += run.size.height;
+                return;
+            }
+        }
+        rects.push(run);
+    }
+
+    fn bounding_box(a: &Rectangle, b: &Rectangle) -> Rectangle {
+        let top_left = a.top_left.component_min(b.top_left);
+        let a_end = a.top_left + a.size;
+        let b_end = b.top_left + b.size;
+        let bottom_right = a_end.component_max(b_end);
+        Rectangle::new(
+            top_left,
+            Size::new(
+                (bottom_right.x - top_left.x) as u32,
+                (bottom_right.y - top_left.y) as u32,
+            ),
+        )
+    }
+
+    // Pixels of rect in row order, the layout display drivers expect for
+    // fill_contiguous.
+    pub fn pixels_in<'a>(&'a self, rect: &Rectangle) -> impl Iterator<Item = Rgb565> + 'a {
+        let rect = rect.intersection(&self.bounding_box());
+        let width = self.width as usize;
+        let x0 = rect.top_left.x as usize;
+        let w = rect.size.width as usize;
+        let pixels = self.pixels();
+        rect.rows().flat_map(move |y| {
+            pixels[y as usize * width + x0..y as usize * width + x0 + w]
+                .iter()
+                .copied()
+        })
+    }
+}
+
+impl<S> OriginDimensions for FrameBuffer<S> {
+    fn size(&self) -> Size {
+        Size::new(self.width, self.height)
+    }
+}
+
+impl<S: AsRef<[Rgb565]> + AsMut<[Rgb565]>> DrawTarget for FrameBuffer<S> {
+    type Color = Rgb565;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        let width = self.width as i32;
+        let height = self.height as i32;
+        let data = self.storage.as_mut();
+        for Pixel(p, color) in pixels {
+            if p.x >= 0 && p.y >= 0 && p.x < width && p.y < height {
+                data[(p.y * width + p.x) as usize] = color;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const W: u32 = 320;
+    const H: u32 = 240;
+
+    // Stands in for the SPI display: keeps what it was sent and how many bytes
+    // that took.
+    struct FakeDisplay {
+        pixels: Vec<Rgb565>,
+        bytes_sent: usize,
+    }
+
+    impl FakeDisplay {
+        fn new() -> Self {
+            Self {
+                pixels: vec![Rgb565::BLACK; (W * H) as usize],
+                bytes_sent: 0,
+            }
+        }
+
+        fn fill_contiguous(&mut self, rect: &Rectangle, colors: impl Iterator<Item = Rgb565>) {
+            let mut points = rect.points();
+            for c in colors {
+                let p = points.next().unwrap();
+                self.pixels[(p.y as u32 * W + p.x as u32) as usize] = c;
+                self.bytes_sent += 2;
+            }
+            assert!(points.next().is_none());
+        }
+
+        fn full_flush(&mut self, fb: &FrameBuffer<Vec<Rgb565>>) {
+            let all = fb.bounding_box();
+            self.fill_contiguous(&all, fb.pixels_in(&all));
+        }
+
+        fn dirty_flush(&mut self, fb: &mut FrameBuffer<Vec<Rgb565>>) {
+            for rect in fb.take_dirty_rects() {
+                self.fill_contiguous(&rect, fb.pixels_in(&rect));
+            }
+        }
+    }
+
+    fn draw_scene(fb: &mut FrameBuffer<Vec<Rgb565>>, cursor_x: i32) {
+        fb.clear(RGB8::new(0x20, 0, 0));
+        fb.draw_rectangle(
+            IVec2::new(10, 10),
+            IVec2::new(200, 60),
+            RGB8::new(0xff, 0xff, 0xff),
+            2,
+            None,
+        );
+        fb.draw_string("12+34", IVec2::new(20, 20), 4, RGB8::new(0xff, 0xff, 0));
+        fb.draw_line(
+            IVec2::new(cursor_x, 150),
+            IVec2::new(cursor_x, 180),
+            RGB8::new(0xff, 0xff, 0x44),
+            2,
+        );
+    }
+
+    #[test]
+    fn dirty_flush_matches_full_flush() {
+        let mut fb = FrameBuffer::new(vec![Rgb565::BLACK; (W * H) as usize], W, H);
+        let mut full = FakeDisplay::new();
+        let mut dirty = FakeDisplay::new();
+        for cursor_x in [40, 40, 52, 300, 7] {
+            draw_scene(&mut fb, cursor_x);
+            full.full_flush(&fb);
+            dirty.dirty_flush(&mut fb);
+            assert!(full.pixels == dirty.pixels);
+        }
+        assert!(dirty.bytes_sent < full.bytes_sent / 2);
+    }
+
+    #[test]
+    fn first_flush_and_invalidate_send_everything() {
+        let mut fb = FrameBuffer::new(vec![Rgb565::BLACK; (W * H) as usize], W, H);
+        assert_eq!(fb.take_dirty_rects(), vec![fb.bounding_box()]);
+        assert!(fb.take_dirty_rects().is_empty());
+        fb.invalidate();
+        assert_eq!(fb.take_dirty_rects(), vec![fb.bounding_box()]);
+    }
+
+    #[test]
+    fn polygons_fill_by_pixel_centres() {
+        let mut fb = FrameBuffer::new(vec![Rgb565::BLACK; (W * H) as usize], W, H);
+        let white = RGB8::new(0xff, 0xff, 0xff);
+        let lit = |fb: &FrameBuffer<Vec<Rgb565>>| {
+            fb.pixels().iter().filter(|p| **p != Rgb565::BLACK).count()
+        };
+        let square = [
+            IVec2::new(10, 10),
+            IVec2::new(20, 10),
+            IVec2::new(20, 20),
+            IVec2::new(10, 20),
+        ];
+        fb.draw_polygon(&square, white, 0, Some(white));
+        assert_eq!(lit(&fb), 100);
+        // a bow tie crosses itself, its middle belongs to both halves once
+        fb.clear(RGB8::new(0, 0, 0));
+        let bow = [IVec2::new(0, 0), IVec2::new(10, 10), IVec2::new(10, 0), IVec2::new(0, 10)];
+        fb.draw_polygon(&bow, white, 0, Some(white));
+        assert_eq!(lit(&fb), 50);
+
+        fb.clear(RGB8::new(0, 0, 0));
+        fb.draw_pixels(IVec2::new(318, 0), 3, &[white; 6]);
+        fb.draw_pixel(IVec2::new(-1, 5), white);
+        assert_eq!(lit(&fb), 4);
+    }
+
+    #[test]
+    fn redrawing_same_frame_is_clean() {
+        let mut fb = FrameBuffer::new(vec![Rgb565::BLACK; (W * H) as usize], W, H);
+        draw_scene(&mut fb, 40);
+        fb.take_dirty_rects();
+        draw_scene(&mut fb, 40);
+        assert!(fb.take_dirty_rects().is_empty());
+        draw_scene(&mut fb, 41);
+        let rects = fb.take_dirty_rects();
+        assert_eq!(
+            rects,
+            vec![Rectangle::new(Point::new(32, 144), Size::new(32, 48))]
+        );
+    }
+}
@@ -24,25 +24,289 @@ pub enum IcKey {
 
 impl IcKey {
     pub const COUNT: usize = IcKey::_Max as usize;
+
+    // what's printed on the key cap
+    pub fn name(self) -> &'static str {
+        const NAMES: [&str; IcKey::COUNT] = [
+            "0", "1", "2", "3", "4", "5", "6", "7", "8", "9", "F1", "F2", "F3", "F4", "F5", "F6",
+            "Shift", "Super",
+        ];
+        NAMES.get(self as usize).copied().unwrap_or("")
+    }
+}
+
+// How a key reached the app. Press always comes first, the others are extra
+// events on top of it, so an app that only cares about Press can ignore them.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum KeyEventKind {
+    Press,
+    // key held past repeat_delay_ms, sent every repeat_interval_ms after that
+    Repeat,
+    // key held for long_press_ms, sent once per hold
+    LongPress,
+    // second press within double_tap_ms of the previous one, sent after its Press
+    DoubleTap,
 }
 
 #[derive(Clone, Copy)]
+pub struct KeyTiming {
+    pub repeat_delay_ms: u64,
+    pub repeat_interval_ms: u64,
+    pub long_press_ms: u64,
+    // 0 turns double tap detection off
+    pub double_tap_ms: u64,
+}
+
+impl Default for KeyTiming {
+    fn default() -> Self {
+        KeyTiming {
+            repeat_delay_ms: 500,
+            repeat_interval_ms: 80,
+            long_press_ms: 700,
+            double_tap_ms: 300,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default)]
 pub struct KeyState {
     pub is_down: bool,
     pub was_down: bool,
     pub just_pressed: bool,
     pub just_released: bool,
+    pub pressed_at: u64,
+    // when the last Press or Repeat went out, None before the first press
+    last_event_at: Option<u64>,
+    long_press_sent: bool,
+    // the previous press already counted as the second half of a double tap
+    tap_used: bool,
 }
 
+impl KeyState {
+    // Refreshes the edge flags and works out which events this key produces
+    // at time now. Call once per update.
+    pub fn update(&mut self, timing: &KeyTiming, now: u64) -> [Option<KeyEventKind>; 2] {
+        self.just_pressed = self.is_down && !self.was_down;
+        self.just_released = !self.is_down && self.was_down;
+        self.was_down = self.is_down;
+        let mut events = [None, None];
+        if self.just_pressed {
+            events[0] = Some(KeyEventKind::Press);
+            let is_double = timing.double_tap_ms > 0
+                && !self.tap_used
+                && self.last_event_at.is_some()
+                && now.saturating_sub(self.pressed_at) <= timing.double_tap_ms;
+            if is_double {
+                events[1] = Some(KeyEventKind::DoubleTap);
+            }
+            self.tap_used = is_double;
+            self.pressed_at = now;
+            self.last_event_at = Some(now);
+            self.long_press_sent = false;
+        } else if self.is_down {
+            let held = now.saturating_sub(self.pressed_at);
+            let since_event = now.saturating_sub(self.last_event_at.unwrap_or(self.pressed_at));
+            if held >= timing.repeat_delay_ms && since_event >= timing.repeat_interval_ms {
+                events[0] = Some(KeyEventKind::Repeat);
+                self.last_event_at = Some(now);
+            }
+            if !self.long_press_sent && held >= timing.long_press_ms {
+                events[1] = Some(KeyEventKind::LongPress);
+                self.long_press_sent = true;
+            }
+        }
+        events
+    }
 
+    // ms until this key has a Repeat or LongPress due, None when it is not held
+    pub fn next_event_in(&self, timing: &KeyTiming, now: u64) -> Option<u64> {
+        if !self.is_down {
+            return None;
+        }
+        let repeat_at = (self.pressed_at + timing.repeat_delay_ms)
+            .max(self.last_event_at.unwrap_or(self.pressed_at) + timing.repeat_interval_ms);
+        let mut next = repeat_at;
+        if !self.long_press_sent {
+            next = next.min(self.pressed_at + timing.long_press_ms);
+        }
+        Some(next.saturating_sub(now))
+    }
+}
 
-impl Default for KeyState {
-    fn default() -> Self {
-        KeyState {
-            is_down: false,
-            was_down: false,
-            just_pressed: false,
-            just_released: false,
+// How Shift and Super behave when tapped on their own. Holding them and
+// pressing another key works the same in every mode.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ModifierMode {
+    // only active while held
+    Momentary,
+    // a tap applies to the next key, a second tap locks it, a third unlocks
+    OneShot,
+    // a tap toggles it on and off like caps lock
+    Locking,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ModifierLatch {
+    Off,
+    OneShot,
+    Locked,
+}
+
+#[derive(Clone, Copy)]
+pub struct ModifierState {
+    pub mode: ModifierMode,
+    latch: ModifierLatch,
+    // another key went down while this one was held, so releasing it is not a tap
+    used_while_held: bool,
+}
+
+impl ModifierState {
+    pub fn new(mode: ModifierMode) -> Self {
+        Self {
+            mode,
+            latch: ModifierLatch::Off,
+            used_while_held: false,
         }
     }
-}
\ No newline at end of file
+
+    pub fn latch(&self) -> ModifierLatch {
+        self.latch
+    }
+
+    pub fn is_latched(&self) -> bool {
+        self.latch != ModifierLatch::Off
+    }
+
+    pub fn clear(&mut self) {
+        self.latch = ModifierLatch::Off;
+    }
+
+    pub fn on_press(&mut self) {
+        self.used_while_held = false;
+    }
+
+    pub fn on_release(&mut self) {
+        if self.used_while_held {
+            return;
+        }
+        self.latch = match (self.mode, self.latch) {
+            (ModifierMode::Momentary, _) => ModifierLatch::Off,
+            (ModifierMode::OneShot, ModifierLatch::Off) => ModifierLatch::OneShot,
+            (ModifierMode::OneShot, ModifierLatch::OneShot) => ModifierLatch::Locked,
+            (ModifierMode::Locking, ModifierLatch::Off) => ModifierLatch::Locked,
+            (_, _) => ModifierLatch::Off,
+        };
+    }
+
+    // any other key was pressed, this uses up a one-shot
+    pub fn on_other_key(&mut self, is_down: bool) {
+        if is_down {
+            self.used_while_held = true;
+        }
+        if self.latch == ModifierLatch::OneShot {
+            self.latch = ModifierLatch::Off;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn events_between(
+        state: &mut KeyState,
+        timing: &KeyTiming,
+        from: u64,
+        to: u64,
+    ) -> alloc::vec::Vec<(u64, KeyEventKind)> {
+        let mut out = alloc::vec::Vec::new();
+        for now in (from..to).step_by(10) {
+            for e in state.update(timing, now).into_iter().flatten() {
+                out.push((now, e));
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn hold_repeats_and_long_presses_once() {
+        let timing = KeyTiming::default();
+        let mut key = KeyState::default();
+        key.is_down = true;
+        let events = events_between(&mut key, &timing, 1000, 1800);
+        assert_eq!(events[0], (1000, KeyEventKind::Press));
+        assert_eq!(events[1], (1500, KeyEventKind::Repeat));
+        assert_eq!(events[2], (1580, KeyEventKind::Repeat));
+        let long: alloc::vec::Vec<_> = events
+            .iter()
+            .filter(|e| e.1 == KeyEventKind::LongPress)
+            .collect();
+        assert_eq!(long, [&(1700, KeyEventKind::LongPress)]);
+        assert_eq!(key.next_event_in(&timing, 1800), Some(20));
+    }
+
+    #[test]
+    fn double_tap_counts_a_first_press_at_time_zero() {
+        let timing = KeyTiming::default();
+        let mut key = KeyState {
+            is_down: true,
+            ..Default::default()
+        };
+        assert_eq!(key.update(&timing, 0), [Some(KeyEventKind::Press), None]);
+        key.is_down = false;
+        key.update(&timing, 50);
+        key.is_down = true;
+        assert_eq!(
+            key.update(&timing, 100),
+            [Some(KeyEventKind::Press), Some(KeyEventKind::DoubleTap)]
+        );
+    }
+
+    #[test]
+    fn one_shot_modifier_latches_then_locks() {
+        let mut shift = ModifierState::new(ModifierMode::OneShot);
+        shift.on_press();
+        shift.on_release();
+        assert_eq!(shift.latch(), ModifierLatch::OneShot);
+        shift.on_other_key(false);
+        assert_eq!(shift.latch(), ModifierLatch::Off);
+
+        for _ in 0..2 {
+            shift.on_press();
+            shift.on_release();
+        }
+        assert_eq!(shift.latch(), ModifierLatch::Locked);
+        shift.on_other_key(false);
+        assert_eq!(shift.latch(), ModifierLatch::Locked);
+
+        // held as a chord it does not count as a tap
+        shift.on_press();
+        shift.on_other_key(true);
+        shift.on_release();
+        assert_eq!(shift.latch(), ModifierLatch::Locked);
+        shift.on_press();
+        shift.on_release();
+        assert_eq!(shift.latch(), ModifierLatch::Off);
+    }
+
+    #[test]
+    fn double_tap_needs_a_quick_second_press() {
+        let timing = KeyTiming::default();
+        let mut key = KeyState::default();
+        let mut tap = |key: &mut KeyState, now: u64| {
+            key.is_down = true;
+            let e = key.update(&timing, now);
+            key.is_down = false;
+            key.update(&timing, now + 10);
+            e
+        };
+        assert_eq!(tap(&mut key, 1000), [Some(KeyEventKind::Press), None]);
+        assert_eq!(
+            tap(&mut key, 1200),
+            [Some(KeyEventKind::Press), Some(KeyEventKind::DoubleTap)]
+        );
+        // a third tap starts a new pair instead of chaining
+        assert_eq!(tap(&mut key, 1400), [Some(KeyEventKind::Press), None]);
+        assert_eq!(tap(&mut key, 2000), [Some(KeyEventKind::Press), None]);
+    }
+}
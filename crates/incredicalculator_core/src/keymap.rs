@@ -0,0 +1,391 @@
+use crate::input::IcKey;
+use crate::legend::KeyLayer;
+use alloc::vec::Vec;
+use core::cell::Cell;
+use num_traits::FromPrimitive;
+
+// Where an action sits by default: the key, its modifier layer and the name
+// of the action.
+pub type Binding = (IcKey, KeyLayer, &'static str);
+
+// A key layout an app registers with the shell. Actions are referred to by
+// name so saved overrides survive the app reordering or adding actions.
+// Actions without a default binding are spares the user can put on a free key.
+pub struct KeymapDef {
+    // stable name overrides are saved under, eg. "calc.sci"
+    pub key: &'static str,
+    pub label: &'static str,
+    pub actions: Vec<&'static str>,
+    pub bindings: &'static [Binding],
+}
+
+impl KeymapDef {
+    pub fn new<A>(
+        key: &'static str,
+        label: &'static str,
+        actions: &[(&'static str, A)],
+        bindings: &'static [Binding],
+    ) -> Self {
+        Self {
+            key,
+            label,
+            actions: actions.iter().map(|(name, _)| *name).collect(),
+            bindings,
+        }
+    }
+}
+
+// Keys the shell handles itself, an app never sees them.
+pub fn is_reserved(key: IcKey, layer: KeyLayer) -> bool {
+    let func = (IcKey::Func1 as usize..=IcKey::Func6 as usize).contains(&(key as usize));
+    key == IcKey::Shift || key == IcKey::Super || (layer == KeyLayer::Super && func)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum KeymapIssue {
+    // the table binds the key twice, the first binding is used
+    Conflict { key: IcKey, layer: KeyLayer },
+    // the table names an action the app doesn't have
+    UnknownAction { key: IcKey, layer: KeyLayer, name: &'static str },
+    // the shell takes the key before the app sees it
+    Reserved { key: IcKey, layer: KeyLayer },
+    // an action that has a default key but isn't on any key any more
+    Unreachable { action: usize },
+}
+
+// Action index per key and layer.
+type Slots = [[Option<u8>; 3]; IcKey::COUNT];
+
+// Turns a table into slots, along with what's wrong with it.
+fn resolve(def: &KeymapDef) -> (Slots, Vec<KeymapIssue>) {
+    let mut slots = [[None; 3]; IcKey::COUNT];
+    let mut issues = Vec::new();
+    let mut seen = [[false; 3]; IcKey::COUNT];
+    for &(key, layer, name) in def.bindings {
+        if key as usize >= IcKey::COUNT {
+            continue;
+        }
+        if is_reserved(key, layer) {
+            issues.push(KeymapIssue::Reserved { key, layer });
+            continue;
+        }
+        let seen = &mut seen[key as usize][layer as usize];
+        if *seen {
+            issues.push(KeymapIssue::Conflict { key, layer });
+            continue;
+        }
+        *seen = true;
+        match def.actions.iter().position(|a| *a == name) {
+            Some(idx) => slots[key as usize][layer as usize] = Some(idx as u8),
+            None => issues.push(KeymapIssue::UnknownAction { key, layer, name }),
+        }
+    }
+    (slots, issues)
+}
+
+// What an app looks its actions up in. Built from the defaults by the app
+// itself and rebuilt with the user's overrides through Keymaps::table().
+#[derive(Clone)]
+pub struct KeyTable<A> {
+    slots: [[Option<A>; 3]; IcKey::COUNT],
+}
+
+impl<A: Copy> KeyTable<A> {
+    pub fn new(actions: &[(&'static str, A)], bindings: &'static [Binding]) -> Self {
+        let def = KeymapDef::new("", "", actions, bindings);
+        Self::from_slots(&resolve(&def).0, actions)
+    }
+
+    fn from_slots(slots: &Slots, actions: &[(&'static str, A)]) -> Self {
+        let mut table = Self {
+            slots: [[None; 3]; IcKey::COUNT],
+        };
+        for (to, from) in table.slots.iter_mut().zip(slots.iter()) {
+            for (to, from) in to.iter_mut().zip(from.iter()) {
+                *to = from.and_then(|idx| actions.get(idx as usize)).map(|(_, a)| *a);
+            }
+        }
+        table
+    }
+
+    // shift wins when both modifiers are active
+    pub fn get(&self, key: IcKey, is_shifted: bool, is_super: bool) -> Option<A> {
+        let layer = KeyLayer::from_modifiers(is_shifted, is_super);
+        *self.slots.get(key as usize)?.get(layer as usize)?
+    }
+}
+
+struct Keymap {
+    def: KeymapDef,
+    defaults: Slots,
+    // what each key does now, differs from defaults where the user rebound it
+    current: [[Cell<Option<u8>>; 3]; IcKey::COUNT],
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct KeymapId(usize);
+
+// Every keymap registered by the apps, with the user's overrides. Works like
+// Prefs: the settings app rebinds keys through the shared reference in
+// InputContext, the shell notices with take_changed() and passes the new
+// layout on through IcApp::on_keymaps_changed.
+#[derive(Default)]
+pub struct Keymaps {
+    maps: Vec<Keymap>,
+    changed: Cell<bool>,
+}
+
+impl Keymaps {
+    // Stored next to the encoded overrides, bumped whenever the encoding
+    // changes. Blobs with another version are ignored.
+    pub const FORMAT_VERSION: u16 = 1;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Registering a key twice returns the existing id. Broken table entries
+    // are left out, validate() lists them.
+    pub fn register(&mut self, def: KeymapDef) -> KeymapId {
+        if let Some(id) = self.find(def.key) {
+            return id;
+        }
+        let (defaults, _) = resolve(&def);
+        let current = core::array::from_fn(|k| core::array::from_fn(|l| Cell::new(defaults[k][l])));
+        self.maps.push(Keymap {
+            def,
+            defaults,
+            current,
+        });
+        KeymapId(self.maps.len() - 1)
+    }
+
+    pub fn find(&self, key: &str) -> Option<KeymapId> {
+        self.maps.iter().position(|m| m.def.key == key).map(KeymapId)
+    }
+
+    pub fn len(&self) -> usize {
+        self.maps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.maps.is_empty()
+    }
+
+    pub fn id_at(&self, idx: usize) -> Option<KeymapId> {
+        (idx < self.maps.len()).then_some(KeymapId(idx))
+    }
+
+    pub fn def(&self, id: KeymapId) -> &KeymapDef {
+        &self.maps[id.0].def
+    }
+
+    // index into def().actions
+    pub fn get(&self, id: KeymapId, key: IcKey, layer: KeyLayer) -> Option<usize> {
+        let slot = self.maps[id.0].current.get(key as usize)?;
+        slot[layer as usize].get().map(|idx| idx as usize)
+    }
+
+    pub fn is_default(&self, id: KeymapId, key: IcKey, layer: KeyLayer) -> bool {
+        let map = &self.maps[id.0];
+        map.current
+            .get(key as usize)
+            .is_none_or(|s| s[layer as usize].get() == map.defaults[key as usize][layer as usize])
+    }
+
+    // None unbinds the key. Reserved keys and unknown actions are ignored.
+    pub fn set(&self, id: KeymapId, key: IcKey, layer: KeyLayer, action: Option<usize>) {
+        let map = &self.maps[id.0];
+        if key as usize >= IcKey::COUNT
+            || is_reserved(key, layer)
+            || action.is_some_and(|a| a >= map.def.actions.len())
+        {
+            return;
+        }
+        let action = action.map(|a| a as u8);
+        if map.current[key as usize][layer as usize].replace(action) != action {
+            self.changed.set(true);
+        }
+    }
+
+    pub fn reset(&self, id: KeymapId, key: IcKey, layer: KeyLayer) {
+        let map = &self.maps[id.0];
+        if let Some(default) = map.defaults.get(key as usize) {
+            self.set(id, key, layer, default[layer as usize].map(|a| a as usize));
+        }
+    }
+
+    // The layout as the app's own action type, actions are matched by name.
+    pub fn table<A: Copy>(&self, id: KeymapId, actions: &[(&'static str, A)]) -> KeyTable<A> {
+        let map = &self.maps[id.0];
+        let mut slots = [[None; 3]; IcKey::COUNT];
+        for (to, from) in slots.iter_mut().zip(map.current.iter()) {
+            for (to, from) in to.iter_mut().zip(from.iter()) {
+                let name = from.get().map(|idx| map.def.actions[idx as usize]);
+                *to = name.and_then(|n| actions.iter().position(|(a, _)| *a == n)).map(|i| i as u8);
+            }
+        }
+        KeyTable::from_slots(&slots, actions)
+    }
+
+    // Problems with the app's table, then actions the user's overrides left
+    // without a key.
+    pub fn validate(&self, id: KeymapId) -> Vec<KeymapIssue> {
+        let map = &self.maps[id.0];
+        let (_, mut issues) = resolve(&map.def);
+        for action in 0..map.def.actions.len() {
+            let action_slot = Some(action as u8);
+            let by_default = map.defaults.iter().flatten().any(|s| *s == action_slot);
+            let now = map.current.iter().flatten().any(|s| s.get() == action_slot);
+            if by_default && !now {
+                issues.push(KeymapIssue::Unreachable { action });
+            }
+        }
+        issues
+    }
+
+    // true once after any binding changed
+    pub fn take_changed(&self) -> bool {
+        self.changed.replace(false)
+    }
+
+    // Only the overrides, per entry: [map key len][map key][key][layer]
+    // [action name len][action name], an empty name unbinds the key.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        for map in self.maps.iter() {
+            for (k, layers) in map.current.iter().enumerate() {
+                for (l, action) in layers.iter().enumerate() {
+                    let action = action.get();
+                    if action == map.defaults[k][l] {
+                        continue;
+                    }
+                    let name = action.map_or("", |a| map.def.actions[a as usize]);
+                    out.push(map.def.key.len() as u8);
+                    out.extend_from_slice(map.def.key.as_bytes());
+                    out.extend_from_slice(&[k as u8, l as u8, name.len() as u8]);
+                    out.extend_from_slice(name.as_bytes());
+                }
+            }
+        }
+        out
+    }
+
+    // Applies whatever overrides still match a registered keymap and action.
+    // Doesn't count as a change, the caller knows it just loaded.
+    pub fn decode(&self, data: &[u8]) {
+        let mut rest = data;
+        while let Some((&map_len, tail)) = rest.split_first() {
+            let map_len = map_len as usize;
+            if tail.len() < map_len + 3 {
+                return;
+            }
+            let (map_key, tail) = tail.split_at(map_len);
+            let (key, layer, name_len) = (tail[0], tail[1], tail[2] as usize);
+            let tail = &tail[3..];
+            if tail.len() < name_len {
+                return;
+            }
+            let (name, tail) = tail.split_at(name_len);
+            rest = tail;
+
+            let map = core::str::from_utf8(map_key).ok().and_then(|k| self.find(k));
+            let key = IcKey::from_u8(key).filter(|k| (*k as usize) < IcKey::COUNT);
+            let layer = KeyLayer::ALL.get(layer as usize).copied();
+            let (Some(map), Some(key), Some(layer)) = (map, key, layer) else {
+                continue;
+            };
+            let actions = &self.maps[map.0].def.actions;
+            let action = match core::str::from_utf8(name) {
+                Ok("") => None,
+                Ok(name) => match actions.iter().position(|a| *a == name) {
+                    Some(idx) => Some(idx),
+                    None => continue,
+                },
+                Err(_) => continue,
+            };
+            if !is_reserved(key, layer) {
+                self.maps[map.0].current[key as usize][layer as usize].set(action.map(|a| a as u8));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone, Copy, PartialEq, Eq, Debug)]
+    enum Action {
+        Up,
+        Down,
+        Ans,
+    }
+
+    const ACTIONS: &[(&str, Action)] = &[("up", Action::Up), ("down", Action::Down), ("ans", Action::Ans)];
+    const KEYS: &[Binding] = &[
+        (IcKey::Num8, KeyLayer::Super, "up"),
+        (IcKey::Num2, KeyLayer::Super, "down"),
+    ];
+    const BROKEN_KEYS: &[Binding] = &[
+        (IcKey::Num8, KeyLayer::Base, "up"),
+        (IcKey::Num8, KeyLayer::Base, "down"),
+        (IcKey::Num2, KeyLayer::Base, "left"),
+        (IcKey::Func1, KeyLayer::Super, "up"),
+    ];
+
+    #[test]
+    fn overrides_round_trip_by_name() {
+        let mut keymaps = Keymaps::new();
+        let id = keymaps.register(KeymapDef::new("test", "Test", ACTIONS, KEYS));
+        assert!(keymaps.validate(id).is_empty());
+
+        // move up to another key and put the spare ans on a free Super slot
+        keymaps.set(id, IcKey::Num8, KeyLayer::Super, None);
+        keymaps.set(id, IcKey::Num7, KeyLayer::Super, Some(0));
+        keymaps.set(id, IcKey::Num0, KeyLayer::Super, Some(2));
+        // the shell's shortcuts can't be taken
+        keymaps.set(id, IcKey::Func1, KeyLayer::Super, Some(2));
+        assert!(keymaps.take_changed());
+        let table = keymaps.table(id, ACTIONS);
+        assert_eq!(table.get(IcKey::Num8, false, true), None);
+        assert_eq!(table.get(IcKey::Num7, false, true), Some(Action::Up));
+        assert_eq!(table.get(IcKey::Num0, false, true), Some(Action::Ans));
+        assert_eq!(table.get(IcKey::Func1, false, true), None);
+
+        // registered with the actions in another order
+        let reordered: &[(&str, Action)] = &[("ans", Action::Ans), ("up", Action::Up), ("down", Action::Down)];
+        let mut loaded = Keymaps::new();
+        let id2 = loaded.register(KeymapDef::new("test", "Test", reordered, KEYS));
+        loaded.decode(&keymaps.encode());
+        assert!(!loaded.take_changed());
+        let table = loaded.table(id2, reordered);
+        assert_eq!(table.get(IcKey::Num8, false, true), None);
+        assert_eq!(table.get(IcKey::Num7, false, true), Some(Action::Up));
+        assert_eq!(table.get(IcKey::Num0, false, true), Some(Action::Ans));
+        assert!(!loaded.is_default(id2, IcKey::Num8, KeyLayer::Super));
+        loaded.reset(id2, IcKey::Num8, KeyLayer::Super);
+        assert!(loaded.is_default(id2, IcKey::Num8, KeyLayer::Super));
+    }
+
+    #[test]
+    fn validator_flags_conflicts_and_unreachable_actions() {
+        let mut keymaps = Keymaps::new();
+        let broken = keymaps.register(KeymapDef::new("broken", "Broken", ACTIONS, BROKEN_KEYS));
+        assert_eq!(
+            keymaps.validate(broken),
+            [
+                KeymapIssue::Conflict { key: IcKey::Num8, layer: KeyLayer::Base },
+                KeymapIssue::UnknownAction { key: IcKey::Num2, layer: KeyLayer::Base, name: "left" },
+                KeymapIssue::Reserved { key: IcKey::Func1, layer: KeyLayer::Super },
+            ]
+        );
+        // the first binding wins
+        let table = keymaps.table(broken, ACTIONS);
+        assert_eq!(table.get(IcKey::Num8, false, false), Some(Action::Up));
+
+        let id = keymaps.register(KeymapDef::new("test", "Test", ACTIONS, KEYS));
+        keymaps.set(id, IcKey::Num2, KeyLayer::Super, Some(0));
+        assert_eq!(keymaps.validate(id), [KeymapIssue::Unreachable { action: 1 }]);
+    }
+}
@@ -0,0 +1,129 @@
+use crate::app::AppInfo;
+use crate::platform::IcPlatform;
+use crate::text::{draw_text, draw_text_f, text_to_pos};
+use crate::theme::Palette;
+use crate::ui::{NavDir, SCREEN_SIZE};
+use glam::IVec2;
+
+const COLUMNS: usize = 3;
+const TILE_SIZE: IVec2 = IVec2::new(96, 70);
+const TILE_GAP: i32 = 8;
+const GRID_TOP: i32 = 30;
+const VISIBLE_ROWS: usize = 2;
+
+// Grid of every registered app. The shell owns the app list, this only
+// tracks the selection and draws the tiles.
+pub struct Launcher {
+    selected: usize,
+    scroll_row: usize,
+}
+
+impl Launcher {
+    pub fn new() -> Self {
+        Self {
+            selected: 0,
+            scroll_row: 0,
+        }
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    pub fn select(&mut self, idx: usize) {
+        self.selected = idx;
+        self.scroll_to_selection();
+    }
+
+    pub fn navigate(&mut self, dir: NavDir, app_count: usize) {
+        if app_count == 0 {
+            return;
+        }
+        let sel = self.selected;
+        self.selected = match dir {
+            NavDir::Left => (sel + app_count - 1) % app_count,
+            NavDir::Right => (sel + 1) % app_count,
+            NavDir::Up if sel >= COLUMNS => sel - COLUMNS,
+            NavDir::Up => sel,
+            NavDir::Down => (sel + COLUMNS).min(app_count - 1),
+        };
+        self.scroll_to_selection();
+    }
+
+    fn scroll_to_selection(&mut self) {
+        let row = self.selected / COLUMNS;
+        if row < self.scroll_row {
+            self.scroll_row = row;
+        } else if row >= self.scroll_row + VISIBLE_ROWS {
+            self.scroll_row = row + 1 - VISIBLE_ROWS;
+        }
+    }
+
+    // favourites[slot] is the app index bound to Super+F<slot + 1>
+    pub fn draw(
+        &self,
+        platform: &mut dyn IcPlatform,
+        apps: &[AppInfo],
+        favourites: &[Option<usize>],
+        palette: &Palette,
+    ) {
+        platform.clear(palette.background);
+        let grid_w = COLUMNS as i32 * TILE_SIZE.x + (COLUMNS as i32 - 1) * TILE_GAP;
+        let left = (SCREEN_SIZE.x - grid_w) / 2;
+        let first = self.scroll_row * COLUMNS;
+        let last = (first + VISIBLE_ROWS * COLUMNS).min(apps.len());
+        for (idx, info) in apps.iter().enumerate().take(last).skip(first) {
+            let col = (idx % COLUMNS) as i32;
+            let row = (idx / COLUMNS - self.scroll_row) as i32;
+            let pos = IVec2::new(
+                left + col * (TILE_SIZE.x + TILE_GAP),
+                GRID_TOP + row * (TILE_SIZE.y + TILE_GAP),
+            );
+            platform.draw_rectangle_rounded(pos, pos + TILE_SIZE, info.color, 0, Some(info.color), 8);
+            let icon_w = text_to_pos(info.icon, 0.0, 3.0, info.icon.len());
+            draw_text(
+                platform,
+                info.icon,
+                pos.x as f32 + (TILE_SIZE.x as f32 - icon_w) / 2.0,
+                (pos.y + 10) as f32,
+                3.0,
+                palette.tile_text,
+            );
+            let name_w = text_to_pos(info.name, 0.0, 2.0, info.name.len());
+            draw_text(
+                platform,
+                info.name,
+                pos.x as f32 + (TILE_SIZE.x as f32 - name_w) / 2.0,
+                (pos.y + TILE_SIZE.y - 20) as f32,
+                2.0,
+                palette.tile_text,
+            );
+            if let Some(slot) = favourites.iter().position(|f| *f == Some(idx)) {
+                draw_text_f(
+                    platform,
+                    format_args!("F{}", slot + 1),
+                    (pos.x + 4) as f32,
+                    (pos.y + 4) as f32,
+                    1.0,
+                    palette.accent,
+                );
+            }
+            if idx == self.selected {
+                platform.draw_rectangle(
+                    pos - IVec2::splat(3),
+                    pos + TILE_SIZE + IVec2::splat(3),
+                    palette.text,
+                    3,
+                    None,
+                );
+            }
+        }
+        let rows = apps.len().div_ceil(COLUMNS);
+        if self.scroll_row > 0 {
+            draw_text(platform, "\x18", 304.0, 30.0, 2.0, palette.text);
+        }
+        if self.scroll_row + VISIBLE_ROWS < rows {
+            draw_text(platform, "\x19", 304.0, 210.0, 2.0, palette.text);
+        }
+    }
+}
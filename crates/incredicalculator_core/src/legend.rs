@@ -0,0 +1,163 @@
+use crate::input::IcKey;
+use crate::platform::IcPlatform;
+use crate::status_bar::STATUS_BAR_HEIGHT;
+use crate::text::{draw_text, text_to_pos};
+use crate::theme::Palette;
+use crate::ui::SCREEN_SIZE;
+use alloc::string::String;
+use glam::IVec2;
+use num_traits::FromPrimitive;
+
+// The modifier a key label applies under. Shift wins when both are active,
+// like in the apps' action maps.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum KeyLayer {
+    Base,
+    Shift,
+    Super,
+}
+
+impl KeyLayer {
+    pub const ALL: [KeyLayer; 3] = [KeyLayer::Base, KeyLayer::Shift, KeyLayer::Super];
+
+    pub fn from_modifiers(is_shifted: bool, is_super: bool) -> Self {
+        if is_shifted {
+            KeyLayer::Shift
+        } else if is_super {
+            KeyLayer::Super
+        } else {
+            KeyLayer::Base
+        }
+    }
+
+    // (is_shifted, is_super) as the action maps take them
+    pub fn modifiers(self) -> (bool, bool) {
+        match self {
+            KeyLayer::Base => (false, false),
+            KeyLayer::Shift => (true, false),
+            KeyLayer::Super => (false, true),
+        }
+    }
+}
+
+// Short label per key and layer for what the key does right now, "" where it
+// does nothing. Filled by the active app and the shell, shown by the help
+// overlay and on the simulator's key caps.
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct KeyLegend {
+    labels: [[String; 3]; IcKey::COUNT],
+}
+
+impl KeyLegend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, key: IcKey, layer: KeyLayer) -> &str {
+        self.labels
+            .get(key as usize)
+            .map_or("", |l| l[layer as usize].as_str())
+    }
+
+    pub fn set(&mut self, key: IcKey, layer: KeyLayer, label: &str) {
+        if let Some(l) = self.labels.get_mut(key as usize) {
+            l[layer as usize] = String::from(label);
+        }
+    }
+
+    // Asks label() about every key on every layer, for apps with an action map.
+    pub fn fill(&mut self, mut label: impl FnMut(IcKey, KeyLayer) -> Option<String>) {
+        for i in 0..IcKey::COUNT {
+            let Some(key) = IcKey::from_usize(i) else {
+                continue;
+            };
+            for layer in KeyLayer::ALL {
+                self.labels[i][layer as usize] = label(key, layer).unwrap_or_default();
+            }
+        }
+    }
+}
+
+// Where the keys sit on the device, top row first.
+const KEY_GRID: [[Option<IcKey>; 4]; 6] = [
+    [None, None, None, Some(IcKey::Func1)],
+    [None, None, None, Some(IcKey::Func2)],
+    [Some(IcKey::Num7), Some(IcKey::Num8), Some(IcKey::Num9), Some(IcKey::Func3)],
+    [Some(IcKey::Num4), Some(IcKey::Num5), Some(IcKey::Num6), Some(IcKey::Func4)],
+    [Some(IcKey::Num1), Some(IcKey::Num2), Some(IcKey::Num3), Some(IcKey::Func5)],
+    [Some(IcKey::Num0), Some(IcKey::Shift), Some(IcKey::Super), Some(IcKey::Func6)],
+];
+
+// Largest of the given scales the label fits in max_w with.
+fn fit_scale(label: &str, max_w: f32, scales: &[f32]) -> f32 {
+    let smallest = scales.last().copied().unwrap_or(1.0);
+    scales
+        .iter()
+        .copied()
+        .find(|s| text_to_pos(label, 0.0, *s, label.len()) <= max_w)
+        .unwrap_or(smallest)
+}
+
+// Keys laid out like on the device below the status bar. Each cell has the
+// plain label in the middle, Shift in the accent colour top right and Super
+// dimmed bottom left, the same places as on the simulator's caps.
+pub fn draw_help(platform: &mut dyn IcPlatform, legend: &KeyLegend, title: &str, palette: &Palette) {
+    let top = STATUS_BAR_HEIGHT;
+    let cell = IVec2::new(SCREEN_SIZE.x / 4, (SCREEN_SIZE.y - top) / KEY_GRID.len() as i32);
+    platform.draw_rectangle(
+        IVec2::new(0, top),
+        SCREEN_SIZE,
+        palette.background,
+        0,
+        Some(palette.background),
+    );
+    // the empty corner above the number pad
+    draw_text(platform, title, 8.0, (top + 8) as f32, 2.0, palette.accent);
+    draw_text(platform, "Any key closes", 8.0, (top + 34) as f32, 1.5, palette.dim);
+    for (row, keys) in KEY_GRID.iter().enumerate() {
+        for (col, key) in keys.iter().enumerate() {
+            let Some(key) = *key else {
+                continue;
+            };
+            let pos = IVec2::new(col as i32 * cell.x, top + row as i32 * cell.y);
+            platform.draw_rectangle(
+                pos + IVec2::new(1, 1),
+                pos + cell - IVec2::new(1, 1),
+                palette.dim,
+                1,
+                None,
+            );
+            let inner_w = (cell.x - 6) as f32;
+            let base = legend.get(key, KeyLayer::Base);
+            let scale = fit_scale(base, inner_w, &[2.0, 1.5, 1.0]);
+            let w = text_to_pos(base, 0.0, scale, base.len());
+            let x = pos.x as f32 + (cell.x as f32 - w) / 2.0;
+            draw_text(platform, base, x, (pos.y + 11) as f32, scale, palette.text);
+
+            let shift = legend.get(key, KeyLayer::Shift);
+            let scale = fit_scale(shift, inner_w / 2.0, &[1.0, 0.8]);
+            let w = text_to_pos(shift, 0.0, scale, shift.len());
+            let x = (pos.x + cell.x - 4) as f32 - w;
+            draw_text(platform, shift, x, (pos.y + 3) as f32, scale, palette.accent);
+
+            let sup = legend.get(key, KeyLayer::Super);
+            let scale = fit_scale(sup, inner_w / 2.0, &[1.0, 0.8]);
+            draw_text(platform, sup, (pos.x + 4) as f32, (pos.y + cell.y - 11) as f32, scale, palette.dim);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fill_clears_unmapped_keys() {
+        let mut legend = KeyLegend::new();
+        legend.set(IcKey::Num1, KeyLayer::Super, "old");
+        legend.fill(|key, layer| (key == IcKey::Num7 && layer == KeyLayer::Shift).then(|| String::from("(")));
+        assert_eq!(legend.get(IcKey::Num7, KeyLayer::Shift), "(");
+        assert_eq!(legend.get(IcKey::Num7, KeyLayer::Base), "");
+        assert_eq!(legend.get(IcKey::Num1, KeyLayer::Super), "");
+    }
+}
@@ -6,7 +6,24 @@ extern crate alloc;
 
 pub mod app;
 pub mod apps;
+pub mod battery;
+pub mod font;
+pub mod framebuffer;
 pub mod input;
+pub mod keymap;
+mod launcher;
+pub mod legend;
+pub mod macros;
+pub mod math_render;
+pub mod overlay;
 pub mod platform;
+pub mod png;
+pub mod power;
+pub mod prefs;
 pub mod shell;
+pub mod state;
+pub mod status_bar;
+pub mod storage;
+pub mod theme;
+pub mod ui;
 mod text;
@@ -0,0 +1,254 @@
+use crate::input::IcKey;
+use crate::state::{StateReader, StateWriter};
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use num_traits::FromPrimitive;
+
+// Shift+Super+F1..F4 play the macro in the matching slot
+pub const MACRO_SLOTS: usize = 4;
+// longer recordings are cut off, so a forgotten recording can't fill storage
+pub const MAX_STEPS: usize = 64;
+
+// key byte of a pause in the encoding
+const PAUSE: u8 = 0xff;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MacroStep {
+    // a press as the app got it, with the modifiers that were active
+    Key { key: IcKey, shifted: bool, sup: bool },
+    // playback waits here until the user continues it
+    Pause,
+}
+
+// What playback feeds to IcShell::key_down/key_up, one per update so the
+// shell sees every change as its own press or release.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum KeyChange {
+    Down(IcKey),
+    Up(IcKey),
+    Pause,
+}
+
+// Recorded key sequences. The shell records presses as they reach the app and
+// plays them back through its own key entry points, so a macro behaves
+// exactly like typing it, modifiers included.
+#[derive(Default)]
+pub struct Macros {
+    slots: [Vec<MacroStep>; MACRO_SLOTS],
+    recording: Option<Vec<MacroStep>>,
+    // changes still to send during playback, front first
+    playback: VecDeque<KeyChange>,
+    paused: bool,
+}
+
+impl Macros {
+    // Stored next to the encoded slots, bumped whenever the encoding changes.
+    // Blobs with another version are ignored.
+    pub const FORMAT_VERSION: u16 = 1;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn steps(&self, slot: usize) -> &[MacroStep] {
+        self.slots.get(slot).map_or(&[], |s| s.as_slice())
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    pub fn is_playing(&self) -> bool {
+        !self.playback.is_empty()
+    }
+
+    // waiting at a pause for the user to continue
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn start_recording(&mut self) {
+        self.recording = Some(Vec::new());
+    }
+
+    // Returns false once the recording is full.
+    pub fn record(&mut self, step: MacroStep) -> bool {
+        let Some(steps) = self.recording.as_mut() else {
+            return false;
+        };
+        if steps.len() >= MAX_STEPS {
+            return false;
+        }
+        steps.push(step);
+        true
+    }
+
+    // Stores the recording in slot, an empty one clears the slot. Returns
+    // false when nothing was being recorded.
+    pub fn finish_recording(&mut self, slot: usize) -> bool {
+        match (self.recording.take(), self.slots.get_mut(slot)) {
+            (Some(steps), Some(to)) => {
+                *to = steps;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn cancel_recording(&mut self) {
+        self.recording = None;
+    }
+
+    // Returns false for an empty slot. Modifiers are pressed around each key
+    // that needs them and let go right after.
+    pub fn play(&mut self, slot: usize) -> bool {
+        let steps = self.steps(slot);
+        if steps.is_empty() {
+            return false;
+        }
+        let mut changes = VecDeque::new();
+        for step in steps {
+            let MacroStep::Key { key, shifted, sup } = *step else {
+                changes.push_back(KeyChange::Pause);
+                continue;
+            };
+            let modifiers = [(shifted, IcKey::Shift), (sup, IcKey::Super)];
+            for (_, m) in modifiers.iter().filter(|(on, _)| *on) {
+                changes.push_back(KeyChange::Down(*m));
+            }
+            changes.push_back(KeyChange::Down(key));
+            changes.push_back(KeyChange::Up(key));
+            for (_, m) in modifiers.iter().rev().filter(|(on, _)| *on) {
+                changes.push_back(KeyChange::Up(*m));
+            }
+        }
+        self.playback = changes;
+        self.paused = false;
+        true
+    }
+
+    // The next key to press (true) or release (false), None while paused or
+    // once playback is done.
+    pub fn next_change(&mut self) -> Option<(IcKey, bool)> {
+        if self.paused {
+            return None;
+        }
+        match self.playback.pop_front()? {
+            KeyChange::Down(key) => Some((key, true)),
+            KeyChange::Up(key) => Some((key, false)),
+            KeyChange::Pause => {
+                // a pause at the end has nothing to wait for
+                self.paused = !self.playback.is_empty();
+                None
+            }
+        }
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    // Ends playback and returns the keys it may still be holding.
+    pub fn stop(&mut self) -> Vec<IcKey> {
+        self.paused = false;
+        self.playback
+            .drain(..)
+            .filter_map(|c| match c {
+                KeyChange::Up(key) => Some(key),
+                _ => None,
+            })
+            .collect()
+    }
+
+    // Per slot: [step count varint], per step: [key or 0xff for a pause]
+    // [bit 0 shift, bit 1 super].
+    pub fn encode(&self) -> Vec<u8> {
+        let mut w = StateWriter::new();
+        for steps in self.slots.iter() {
+            w.varint(steps.len() as u32);
+            for step in steps {
+                match *step {
+                    MacroStep::Key { key, shifted, sup } => {
+                        w.u8(key as u8);
+                        w.u8(shifted as u8 | (sup as u8) << 1);
+                    }
+                    MacroStep::Pause => {
+                        w.u8(PAUSE);
+                        w.u8(0);
+                    }
+                }
+            }
+        }
+        w.as_bytes().to_vec()
+    }
+
+    // Leaves the slots alone if the data doesn't make sense.
+    pub fn decode(&mut self, data: &[u8]) -> Option<()> {
+        let mut r = StateReader::new(data);
+        let mut slots: [Vec<MacroStep>; MACRO_SLOTS] = Default::default();
+        for steps in slots.iter_mut() {
+            let len = r.varint()? as usize;
+            if len > MAX_STEPS {
+                return None;
+            }
+            for _ in 0..len {
+                let (key, flags) = (r.u8()?, r.u8()?);
+                steps.push(if key == PAUSE {
+                    MacroStep::Pause
+                } else {
+                    MacroStep::Key {
+                        key: IcKey::from_u8(key).filter(|k| (*k as usize) < IcKey::COUNT)?,
+                        shifted: flags & 1 != 0,
+                        sup: flags & 2 != 0,
+                    }
+                });
+            }
+        }
+        self.slots = slots;
+        Some(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn playback_wraps_keys_in_their_modifiers_and_stops_at_pauses() {
+        let mut macros = Macros::new();
+        assert!(!macros.play(0));
+        macros.start_recording();
+        macros.record(MacroStep::Key { key: IcKey::Num7, shifted: true, sup: false });
+        macros.record(MacroStep::Pause);
+        macros.record(MacroStep::Key { key: IcKey::Func6, shifted: false, sup: false });
+        assert!(macros.finish_recording(1));
+        assert!(!macros.is_recording());
+
+        assert!(macros.play(1));
+        let mut changes = Vec::new();
+        while let Some(change) = macros.next_change() {
+            changes.push(change);
+        }
+        assert_eq!(
+            changes,
+            [
+                (IcKey::Shift, true),
+                (IcKey::Num7, true),
+                (IcKey::Num7, false),
+                (IcKey::Shift, false),
+            ]
+        );
+        assert!(macros.is_paused());
+        macros.resume();
+        assert_eq!(macros.next_change(), Some((IcKey::Func6, true)));
+        assert_eq!(macros.stop(), [IcKey::Func6]);
+        assert!(!macros.is_playing());
+
+        let mut loaded = Macros::new();
+        assert_eq!(loaded.decode(&macros.encode()), Some(()));
+        assert_eq!(loaded.steps(1), macros.steps(1));
+        // a truncated blob changes nothing
+        assert_eq!(loaded.decode(&[0, 3, 7]), None);
+        assert_eq!(loaded.steps(1).len(), 3);
+    }
+}
@@ -0,0 +1,487 @@
+// Textbook style ("2D") drawing of expressions: stacked fractions, raised
+// exponents, radical signs and subscripts. The expression is parsed with
+// exp-rs, turned into a tree of boxes that know their own size and then drawn
+// with the stroke font and lines. If the expression doesn't parse or the
+// boxes don't fit in the space given, the plain text is drawn instead.
+use crate::platform::IcPlatform;
+use crate::text::{draw_text, text_to_pos};
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use bumpalo::Bump;
+use exp_rs::{AstExpr, LogicalOperator};
+use glam::IVec2;
+use rgb::RGB8;
+
+#[derive(Clone, Copy)]
+pub struct MathStyle {
+    pub scale: f32,
+    // nested parts (exponents, fractions) shrink but never below this
+    pub min_scale: f32,
+    pub color: RGB8,
+}
+
+enum MathBox {
+    Text { text: String, scale: f32 },
+    Row(Vec<MathBox>),
+    Frac { num: Box<MathBox>, den: Box<MathBox>, scale: f32 },
+    Sup { base: Box<MathBox>, exp: Box<MathBox> },
+    Sub { base: Box<MathBox>, sub: Box<MathBox> },
+    Sqrt { inner: Box<MathBox>, scale: f32 },
+}
+
+#[derive(Clone, Copy)]
+struct Metrics {
+    width: f32,
+    ascent: f32,
+    descent: f32,
+}
+
+impl Metrics {
+    fn height(&self) -> f32 {
+        self.ascent + self.descent
+    }
+}
+
+// Glyphs in the stroke font sit on row 6 of their grid, descenders go to 8.
+const GLYPH_BASELINE: f32 = 6.0;
+const GLYPH_DESCENT: f32 = 2.0;
+
+impl MathBox {
+    fn text(s: &str, scale: f32) -> MathBox {
+        MathBox::Text {
+            text: String::from(s),
+            scale,
+        }
+    }
+
+    fn metrics(&self) -> Metrics {
+        match self {
+            MathBox::Text { text, scale } => Metrics {
+                width: text_to_pos(text, 0.0, *scale, text.len()),
+                ascent: (GLYPH_BASELINE + 1.0) * scale,
+                descent: GLYPH_DESCENT * scale,
+            },
+            MathBox::Row(items) => {
+                let mut m = Metrics {
+                    width: 0.0,
+                    ascent: 0.0,
+                    descent: 0.0,
+                };
+                for item in items {
+                    let im = item.metrics();
+                    m.width += im.width;
+                    m.ascent = m.ascent.max(im.ascent);
+                    m.descent = m.descent.max(im.descent);
+                }
+                m
+            }
+            MathBox::Frac { num, den, scale } => {
+                let nm = num.metrics();
+                let dm = den.metrics();
+                let axis = Self::frac_axis(*scale);
+                let gap = Self::frac_gap(*scale);
+                Metrics {
+                    width: nm.width.max(dm.width) + 2.0 * gap,
+                    ascent: axis + gap + nm.height(),
+                    descent: (dm.height() + gap - axis).max(0.0),
+                }
+            }
+            MathBox::Sup { base, exp } => {
+                let bm = base.metrics();
+                let em = exp.metrics();
+                let raise = Self::sup_raise(&bm, &em);
+                Metrics {
+                    width: bm.width + em.width,
+                    ascent: bm.ascent.max(raise + em.ascent),
+                    descent: bm.descent,
+                }
+            }
+            MathBox::Sub { base, sub } => {
+                let bm = base.metrics();
+                let sm = sub.metrics();
+                let drop = Self::sub_drop(&sm);
+                Metrics {
+                    width: bm.width + sm.width,
+                    ascent: bm.ascent,
+                    descent: bm.descent.max(drop + sm.descent),
+                }
+            }
+            MathBox::Sqrt { inner, scale } => {
+                let im = inner.metrics();
+                Metrics {
+                    width: Self::radical_width(*scale) + im.width + scale,
+                    ascent: im.ascent + Self::frac_gap(*scale) + 1.0,
+                    descent: im.descent,
+                }
+            }
+        }
+    }
+
+    fn frac_axis(scale: f32) -> f32 {
+        // middle of a digit, where the minus sign sits
+        3.0 * scale
+    }
+
+    fn frac_gap(scale: f32) -> f32 {
+        (scale * 1.5).max(2.0)
+    }
+
+    fn radical_width(scale: f32) -> f32 {
+        5.0 * scale
+    }
+
+    fn sup_raise(base: &Metrics, exp: &Metrics) -> f32 {
+        (base.ascent - exp.ascent * 0.5).max(exp.descent)
+    }
+
+    fn sub_drop(sub: &Metrics) -> f32 {
+        sub.ascent * 0.5
+    }
+
+    // x is the left edge and baseline the y the box's baseline sits on
+    fn draw(&self, platform: &mut dyn IcPlatform, x: f32, baseline: f32, color: RGB8) {
+        match self {
+            MathBox::Text { text, scale } => {
+                draw_text(
+                    platform,
+                    text,
+                    x,
+                    baseline - GLYPH_BASELINE * scale,
+                    *scale,
+                    color,
+                );
+            }
+            MathBox::Row(items) => {
+                let mut x = x;
+                for item in items {
+                    item.draw(platform, x, baseline, color);
+                    x += item.metrics().width;
+                }
+            }
+            MathBox::Frac { num, den, scale } => {
+                let m = self.metrics();
+                let nm = num.metrics();
+                let dm = den.metrics();
+                let axis_y = baseline - Self::frac_axis(*scale);
+                let gap = Self::frac_gap(*scale);
+                num.draw(
+                    platform,
+                    x + (m.width - nm.width) / 2.0,
+                    axis_y - gap - nm.descent,
+                    color,
+                );
+                den.draw(
+                    platform,
+                    x + (m.width - dm.width) / 2.0,
+                    axis_y + gap + dm.ascent,
+                    color,
+                );
+                platform.draw_line(
+                    IVec2::new((x + 1.0) as i32, axis_y as i32),
+                    IVec2::new((x + m.width - 1.0) as i32, axis_y as i32),
+                    color,
+                    ((*scale * 0.75 + 0.5) as u32).max(1),
+                );
+            }
+            MathBox::Sup { base, exp } => {
+                let bm = base.metrics();
+                let em = exp.metrics();
+                base.draw(platform, x, baseline, color);
+                exp.draw(
+                    platform,
+                    x + bm.width,
+                    baseline - Self::sup_raise(&bm, &em),
+                    color,
+                );
+            }
+            MathBox::Sub { base, sub } => {
+                let bm = base.metrics();
+                let sm = sub.metrics();
+                base.draw(platform, x, baseline, color);
+                sub.draw(platform, x + bm.width, baseline + Self::sub_drop(&sm), color);
+            }
+            MathBox::Sqrt { inner, scale } => {
+                let m = self.metrics();
+                let rw = Self::radical_width(*scale);
+                let top = baseline - m.ascent;
+                let bottom = baseline + m.descent;
+                let width = ((*scale * 0.75 + 0.5) as u32).max(1);
+                let p0 = IVec2::new(x as i32, (baseline - m.ascent * 0.4) as i32);
+                let p1 = IVec2::new((x + rw * 0.3) as i32, (baseline - m.ascent * 0.5) as i32);
+                let p2 = IVec2::new((x + rw * 0.6) as i32, bottom as i32);
+                let p3 = IVec2::new((x + rw) as i32, top as i32);
+                let p4 = IVec2::new((x + m.width) as i32, top as i32);
+                platform.draw_line(p0, p1, color, width);
+                platform.draw_line(p1, p2, color, width);
+                platform.draw_line(p2, p3, color, width);
+                platform.draw_line(p3, p4, color, width);
+                inner.draw(platform, x + rw + scale * 0.5, baseline, color);
+            }
+        }
+    }
+}
+
+// Binding strength of a node, higher binds tighter. Used to decide where the
+// parentheses that the parser threw away need to come back.
+fn precedence(expr: &AstExpr) -> u8 {
+    match expr {
+        AstExpr::Function { name, args } if args.len() == 2 => match *name {
+            "," | ";" => 1,
+            "|" => 4,
+            "&" => 6,
+            "==" | "!=" | "<" | ">" | "<=" | ">=" | "<>" => 7,
+            "<<" | ">>" | "<<<" | ">>>" => 8,
+            "+" | "-" => 9,
+            "*" | "%" => 10,
+            // a stacked fraction groups itself, except as the base of a power
+            "/" => 12,
+            "^" | "**" => 15,
+            _ => 100,
+        },
+        AstExpr::Function { name, args } if args.len() == 1 && (*name == "neg" || *name == "~") => {
+            14
+        }
+        AstExpr::LogicalOp { op, .. } => match op {
+            LogicalOperator::And => 3,
+            LogicalOperator::Or => 2,
+        },
+        AstExpr::Conditional { .. } => 1,
+        _ => 100,
+    }
+}
+
+struct Builder {
+    style: MathStyle,
+}
+
+impl Builder {
+    fn smaller(&self, scale: f32, factor: f32) -> f32 {
+        (scale * factor).max(self.style.min_scale)
+    }
+
+    fn number(v: f64) -> String {
+        format!("{}", v)
+    }
+
+    fn paren(&self, inner: MathBox, scale: f32) -> MathBox {
+        MathBox::Row(alloc::vec![
+            MathBox::text("(", scale),
+            inner,
+            MathBox::text(")", scale),
+        ])
+    }
+
+    fn operand(&self, expr: &AstExpr, scale: f32, min_prec: u8) -> MathBox {
+        let b = self.build(expr, scale);
+        if precedence(expr) < min_prec {
+            self.paren(b, scale)
+        } else {
+            b
+        }
+    }
+
+    fn variable(&self, name: &str, scale: f32) -> MathBox {
+        match name.split_once('_') {
+            Some((base, sub)) if !base.is_empty() && !sub.is_empty() => MathBox::Sub {
+                base: Box::new(MathBox::text(base, scale)),
+                sub: Box::new(MathBox::text(sub, self.smaller(scale, 0.6))),
+            },
+            _ => MathBox::text(name, scale),
+        }
+    }
+
+    fn build(&self, expr: &AstExpr, scale: f32) -> MathBox {
+        match expr {
+            AstExpr::Constant(v) => MathBox::Text {
+                text: Self::number(*v),
+                scale,
+            },
+            AstExpr::Variable(name) => self.variable(name, scale),
+            AstExpr::Function { name, args } if args.len() == 2 && precedence(expr) < 100 => {
+                let prec = precedence(expr);
+                match *name {
+                    "/" => {
+                        let inner = self.smaller(scale, 0.75);
+                        MathBox::Frac {
+                            num: Box::new(self.build(&args[0], inner)),
+                            den: Box::new(self.build(&args[1], inner)),
+                            scale,
+                        }
+                    }
+                    "^" | "**" => MathBox::Sup {
+                        base: Box::new(self.operand(&args[0], scale, prec + 1)),
+                        exp: Box::new(self.build(&args[1], self.smaller(scale, 0.6))),
+                    },
+                    op => MathBox::Row(alloc::vec![
+                        self.operand(&args[0], scale, prec),
+                        MathBox::text(op, scale),
+                        // left associative, so a-(b-c) keeps its parentheses
+                        self.operand(&args[1], scale, prec + 1),
+                    ]),
+                }
+            }
+            AstExpr::Function { name, args } if args.len() == 1 && *name == "neg" => {
+                MathBox::Row(alloc::vec![
+                    MathBox::text("-", scale),
+                    self.operand(&args[0], scale, 14),
+                ])
+            }
+            AstExpr::Function { name, args } if args.len() == 1 && *name == "~" => {
+                MathBox::Row(alloc::vec![
+                    MathBox::text("~", scale),
+                    self.operand(&args[0], scale, 14),
+                ])
+            }
+            AstExpr::Function { name, args } if args.len() == 1 && *name == "sqrt" => {
+                MathBox::Sqrt {
+                    inner: Box::new(self.build(&args[0], scale)),
+                    scale,
+                }
+            }
+            AstExpr::Function { name, args } if args.len() == 1 && *name == "abs" => {
+                MathBox::Row(alloc::vec![
+                    MathBox::text("|", scale),
+                    self.build(&args[0], scale),
+                    MathBox::text("|", scale),
+                ])
+            }
+            AstExpr::Function { name, args } => {
+                let mut inner = Vec::new();
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        inner.push(MathBox::text(",", scale));
+                    }
+                    inner.push(self.build(arg, scale));
+                }
+                MathBox::Row(alloc::vec![
+                    self.variable(name, scale),
+                    self.paren(MathBox::Row(inner), scale),
+                ])
+            }
+            AstExpr::Array { name, index } => MathBox::Row(alloc::vec![
+                MathBox::text(name, scale),
+                MathBox::text("[", scale),
+                self.build(index, scale),
+                MathBox::text("]", scale),
+            ]),
+            AstExpr::Attribute { base, attr } => MathBox::Text {
+                text: format!("{}.{}", base, attr),
+                scale,
+            },
+            AstExpr::LogicalOp { op, left, right } => {
+                let prec = precedence(expr);
+                let op_str = match op {
+                    LogicalOperator::And => "&&",
+                    LogicalOperator::Or => "||",
+                };
+                MathBox::Row(alloc::vec![
+                    self.operand(left, scale, prec),
+                    MathBox::text(op_str, scale),
+                    self.operand(right, scale, prec + 1),
+                ])
+            }
+            AstExpr::Conditional {
+                condition,
+                true_branch,
+                false_branch,
+            } => MathBox::Row(alloc::vec![
+                self.operand(condition, scale, 2),
+                MathBox::text("?", scale),
+                self.operand(true_branch, scale, 2),
+                MathBox::text(":", scale),
+                self.operand(false_branch, scale, 1),
+            ]),
+        }
+    }
+}
+
+// Size the expression would take up when drawn in 2D, None if it can't be
+// parsed.
+pub fn measure_expression(expression: &str, style: MathStyle) -> Option<IVec2> {
+    let arena = Bump::new();
+    let ast = exp_rs::parse_expression(expression, &arena).ok()?;
+    let m = Builder { style }.build(&ast, style.scale).metrics();
+    Some(IVec2::new(m.width as i32, m.height() as i32))
+}
+
+// Draws the expression in 2D inside the box at pos with the given size,
+// vertically centered. Falls back to the plain text when the expression
+// doesn't parse or wouldn't fit. Returns true if the 2D form was drawn.
+pub fn draw_expression(
+    platform: &mut dyn IcPlatform,
+    expression: &str,
+    pos: IVec2,
+    size: IVec2,
+    style: MathStyle,
+) -> bool {
+    let arena = Bump::new();
+    if let Ok(ast) = exp_rs::parse_expression(expression, &arena) {
+        let layout = Builder { style }.build(&ast, style.scale);
+        let m = layout.metrics();
+        if m.width <= size.x as f32 && m.height() <= size.y as f32 {
+            let top = pos.y as f32 + (size.y as f32 - m.height()) / 2.0;
+            layout.draw(platform, pos.x as f32, top + m.ascent, style.color);
+            return true;
+        }
+    }
+    let text_h = (GLYPH_BASELINE + 1.0) * style.scale;
+    draw_text(
+        platform,
+        expression,
+        pos.x as f32,
+        pos.y as f32 + ((size.y as f32 - text_h) / 2.0).max(0.0),
+        style.scale,
+        style.color,
+    );
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn measure(expression: &str) -> IVec2 {
+        let style = MathStyle {
+            scale: 2.0,
+            min_scale: 1.0,
+            color: RGB8::new(0, 0, 0),
+        };
+        measure_expression(expression, style).unwrap()
+    }
+
+    #[test]
+    fn fractions_and_exponents_stack() {
+        let digit = measure("1");
+        // both parts shrink a bit, but it is still two lines tall
+        assert!(measure("1/2").y > digit.y * 3 / 2);
+        // a fraction is as wide as its wider part, not both side by side
+        assert_eq!(measure("123/4").x, measure("4/123").x);
+        assert!(measure("123/4").x < measure("123+4").x);
+        let power = measure("2^3");
+        assert!(power.y > digit.y);
+        assert!(power.x < measure("23").x);
+    }
+
+    #[test]
+    fn parentheses_come_back_where_precedence_needs_them() {
+        assert!(measure("(1+2)*3").x > measure("1+2*3").x);
+        assert_eq!(measure("(1*2)+3").x, measure("1*2+3").x);
+        assert_eq!(measure("(1-2)-3").x, measure("1-2-3").x);
+        assert!(measure("1-(2-3)").x > measure("1-2-3").x);
+        assert!(measure("(1+2)^2").x > measure("1+2^2").x);
+    }
+
+    #[test]
+    fn invalid_input_measures_as_none() {
+        let style = MathStyle {
+            scale: 2.0,
+            min_scale: 1.0,
+            color: RGB8::new(0, 0, 0),
+        };
+        for expression in ["3+*", "(1+2", ""] {
+            assert!(measure_expression(expression, style).is_none(), "{}", expression);
+        }
+    }
+}
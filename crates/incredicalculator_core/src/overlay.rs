@@ -0,0 +1,171 @@
+use crate::input::{IcKey, KeyEventKind};
+use crate::platform::IcPlatform;
+use crate::text::{draw_text, text_to_pos};
+use crate::theme::Palette;
+use crate::ui::{Dialog, NavDir, SCREEN_SIZE};
+use alloc::collections::VecDeque;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use glam::IVec2;
+
+// how long each toast stays on screen
+pub const TOAST_MS: u64 = 2000;
+
+// Toasts and dialogs the shell draws on top of the active app. Apps queue them
+// through InputContext::overlays, which like the preferences only hands out a
+// shared reference. An open dialog takes every key until it is answered, the
+// answer goes to IcApp::on_dialog_result of the app that asked.
+#[derive(Default)]
+pub struct Overlays {
+    queued_toasts: RefCell<VecDeque<String>>,
+    asked: RefCell<Vec<(u32, Dialog)>>,
+    // text on screen and when it goes away
+    toast: Option<(String, u64)>,
+    // (app index, dialog id, dialog), the front one is on screen
+    dialogs: VecDeque<(usize, u32, Dialog)>,
+}
+
+impl Overlays {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Toasts are shown one after another, they never take input.
+    pub fn toast(&self, text: &str) {
+        self.queued_toasts.borrow_mut().push_back(String::from(text));
+    }
+
+    // id comes back with the answer so an app can tell its dialogs apart. The
+    // shell restyles the dialog to the current theme.
+    pub fn ask(&self, id: u32, dialog: Dialog) {
+        self.asked.borrow_mut().push((id, dialog));
+    }
+
+    // Called by the shell after each call into an app, dialogs asked for
+    // since then belong to that app.
+    pub(crate) fn adopt(&mut self, app_idx: usize) {
+        for (id, dialog) in self.asked.get_mut().drain(..) {
+            self.dialogs.push_back((app_idx, id, dialog));
+        }
+    }
+
+    pub(crate) fn has_dialog(&self) -> bool {
+        !self.dialogs.is_empty()
+    }
+
+    // Left/right pick a button, 5 or = chooses it and Bk cancels. Returns
+    // (app index, dialog id, chosen button) once the dialog closes.
+    pub(crate) fn dialog_key(
+        &mut self,
+        key: IcKey,
+        kind: KeyEventKind,
+    ) -> Option<(usize, u32, Option<usize>)> {
+        let (_, _, dialog) = self.dialogs.front_mut()?;
+        let choice = match (key, kind) {
+            (IcKey::Num4, KeyEventKind::Press | KeyEventKind::Repeat) => {
+                dialog.navigate(NavDir::Left);
+                return None;
+            }
+            (IcKey::Num6, KeyEventKind::Press | KeyEventKind::Repeat) => {
+                dialog.navigate(NavDir::Right);
+                return None;
+            }
+            (IcKey::Num5 | IcKey::Func6, KeyEventKind::Press) => Some(dialog.selected()),
+            (IcKey::Func1, KeyEventKind::Press) => None,
+            _ => return None,
+        };
+        let (app_idx, id, _) = self.dialogs.pop_front()?;
+        Some((app_idx, id, choice))
+    }
+
+    // Moves on to the next toast once the current one timed out.
+    pub(crate) fn update(&mut self, now: u64) {
+        if self.toast.as_ref().is_some_and(|(_, until)| now >= *until) {
+            self.toast = None;
+        }
+        if self.toast.is_none() {
+            self.toast = self
+                .queued_toasts
+                .get_mut()
+                .pop_front()
+                .map(|text| (text, now + TOAST_MS));
+        }
+    }
+
+    pub(crate) fn next_change_in(&self, now: u64) -> Option<u64> {
+        self.toast
+            .as_ref()
+            .map(|(_, until)| until.saturating_sub(now))
+    }
+
+    pub(crate) fn draw(&mut self, platform: &mut dyn IcPlatform, palette: &Palette) {
+        if let Some((_, _, dialog)) = self.dialogs.front_mut() {
+            dialog.style = palette.dialog_style();
+            dialog.draw(platform);
+        }
+        if let Some((text, _)) = &self.toast {
+            let text_w = text_to_pos(text, 0.0, 2.0, text.len()) as i32;
+            let size = IVec2::new(text_w + 24, 28);
+            let pos = IVec2::new((SCREEN_SIZE.x - size.x) / 2, SCREEN_SIZE.y - size.y - 10);
+            platform.draw_rectangle_rounded(
+                pos,
+                pos + size,
+                palette.text,
+                1,
+                Some(palette.selection),
+                6,
+            );
+            draw_text(
+                platform,
+                text,
+                (pos.x + 12) as f32,
+                (pos.y + 7) as f32,
+                2.0,
+                palette.text,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ui::DialogStyle;
+
+    #[test]
+    fn dialogs_answer_the_app_that_asked() {
+        let mut overlays = Overlays::new();
+        overlays.ask(7, Dialog::confirm("t", "m", DialogStyle::default()));
+        assert!(!overlays.has_dialog());
+        overlays.adopt(2);
+        assert!(overlays.has_dialog());
+        assert_eq!(overlays.dialog_key(IcKey::Num6, KeyEventKind::Press), None);
+        assert_eq!(overlays.dialog_key(IcKey::Num5, KeyEventKind::LongPress), None);
+        assert_eq!(
+            overlays.dialog_key(IcKey::Num5, KeyEventKind::Press),
+            Some((2, 7, Some(1)))
+        );
+        assert!(!overlays.has_dialog());
+
+        overlays.ask(8, Dialog::confirm("t", "m", DialogStyle::default()));
+        overlays.adopt(0);
+        assert_eq!(
+            overlays.dialog_key(IcKey::Func1, KeyEventKind::Press),
+            Some((0, 8, None))
+        );
+    }
+
+    #[test]
+    fn toasts_show_one_after_another() {
+        let mut overlays = Overlays::new();
+        overlays.toast("a");
+        overlays.toast("b");
+        overlays.update(100);
+        assert_eq!(overlays.next_change_in(100), Some(TOAST_MS));
+        overlays.update(100 + TOAST_MS);
+        assert_eq!(overlays.next_change_in(100 + TOAST_MS), Some(TOAST_MS));
+        overlays.update(100 + 2 * TOAST_MS);
+        assert_eq!(overlays.next_change_in(100 + 2 * TOAST_MS), None);
+    }
+}
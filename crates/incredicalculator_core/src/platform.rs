@@ -1,6 +1,9 @@
 use glam::IVec2;
 use rgb::*;
 use core::fmt;
+use crate::font::{self, Align};
+use crate::power::PowerState;
+use crate::storage::Storage;
 
 
 pub trait IcPlatform {
@@ -16,12 +19,65 @@ pub trait IcPlatform {
         corner_radius: u32,
     );
     fn draw_triangle(&mut self, vertex1: IVec2, vertex2: IVec2, vertex3: IVec2, stroke_color: RGB8, stroke_width: u32, fill_color: Option<RGB8>);
+    fn draw_circle(&mut self, center: IVec2, radius: u32, stroke_color: RGB8, stroke_width: u32, fill_color: Option<RGB8>);
+    // radii.x across, radii.y up and down
+    fn draw_ellipse(&mut self, center: IVec2, radii: IVec2, stroke_color: RGB8, stroke_width: u32, fill_color: Option<RGB8>);
+    // angles in degrees clockwise from 3 o'clock
+    fn draw_arc(&mut self, center: IVec2, radius: u32, start_deg: f32, sweep_deg: f32, color: RGB8, width: u32);
+    fn draw_polyline(&mut self, points: &[IVec2], color: RGB8, width: u32);
+    // closes the outline back to the first point
+    fn draw_polygon(&mut self, points: &[IVec2], stroke_color: RGB8, stroke_width: u32, fill_color: Option<RGB8>);
+    fn draw_pixel(&mut self, pos: IVec2, color: RGB8);
+    // pixels in rows of width, top left at pos
+    fn draw_pixels(&mut self, pos: IVec2, width: u32, pixels: &[RGB8]);
     fn draw_string(&mut self, text: &str, pos: IVec2, size: u32, color: RGB8);
     fn draw_string_f(&mut self, arg: fmt::Arguments, pos: IVec2, size: u32, color: RGB8);
+    // how much room draw_string needs for text at size
+    fn measure_string(&self, text: &str, size: u32) -> IVec2 {
+        let size = font::for_size(size).measure(text);
+        IVec2::new(size.width as i32, size.height as i32)
+    }
+    // Like draw_string, with pos.x being the left edge, centre or right edge
+    // of each line depending on align.
+    fn draw_string_aligned(&mut self, text: &str, pos: IVec2, size: u32, align: Align, color: RGB8) {
+        let line_h = font::for_size(size).height() as i32;
+        for (i, line) in text.split('\n').enumerate() {
+            let x = align.start(pos.x, self.measure_string(line, size).x);
+            self.draw_string(line, IVec2::new(x, pos.y + i as i32 * line_h), size, color);
+        }
+    }
     fn clear(&mut self, color: RGB8);
     fn log(&mut self, arg: fmt::Arguments);
     fn millis(&self) -> u64;
     fn get_battery_soc(&self) -> i32;
+    fn is_charging(&self) -> bool {
+        false
+    }
+    // volts, None where there is no gauge to ask
+    fn battery_voltage(&self) -> Option<f32> {
+        None
+    }
+    // percent per hour, negative while discharging
+    fn battery_charge_rate(&self) -> Option<f32> {
+        None
+    }
+    // seconds since local midnight, None if the platform has no clock
+    fn time_of_day(&self) -> Option<u32> {
+        None
+    }
+    // percent, 0 turns the backlight off
+    fn set_backlight(&mut self, _level: u8) {}
+    // lets the platform slow down whatever it can while the screen is off
+    fn set_power_state(&mut self, _state: PowerState) {}
+    // Saves what is on screen right now, eg. with png::write_png. Returns false
+    // if the platform has nowhere to put it.
+    fn save_screenshot(&mut self) -> bool {
+        false
+    }
+    // None when there is nowhere to keep data across power cycles
+    fn storage(&mut self) -> Option<&mut dyn Storage> {
+        None
+    }
 }
 
 #[macro_export]
@@ -0,0 +1,195 @@
+use alloc::vec::Vec;
+use embedded_graphics::pixelcolor::Rgb565;
+use embedded_graphics::prelude::RgbColor;
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+// zlib header for deflate with a 32K window and no preset dictionary
+const ZLIB_HEADER: [u8; 2] = [0x78, 0x01];
+
+// Writes pixels as an 8 bit RGB PNG. The image data is deflate'd with stored
+// (uncompressed) blocks, one row per IDAT chunk, so only a single row is ever
+// buffered and firmware can stream a screenshot out without a copy of the
+// whole screen. out gets called with consecutive pieces of the file.
+pub fn write_png(pixels: &[Rgb565], width: u32, height: u32, out: &mut dyn FnMut(&[u8])) {
+    assert!(pixels.len() >= (width * height) as usize);
+    out(&SIGNATURE);
+
+    let mut ihdr = Vec::with_capacity(13);
+    ihdr.extend_from_slice(&width.to_be_bytes());
+    ihdr.extend_from_slice(&height.to_be_bytes());
+    // bit depth 8, colour type 2 (RGB), deflate, adaptive filtering, no interlace
+    ihdr.extend_from_slice(&[8, 2, 0, 0, 0]);
+    write_chunk(b"IHDR", &ihdr, out);
+
+    // a row is its filter byte (0, none) and the pixels
+    let row_len = 1 + width as usize * 3;
+    let mut adler = Adler32::new();
+    let mut data = Vec::with_capacity(ZLIB_HEADER.len() + 5 + row_len + 4);
+    for y in 0..height as usize {
+        data.clear();
+        if y == 0 {
+            data.extend_from_slice(&ZLIB_HEADER);
+        }
+        let last = y + 1 == height as usize;
+        // stored block header: BFINAL on the last row, then LEN and its complement
+        data.push(last as u8);
+        data.extend_from_slice(&(row_len as u16).to_le_bytes());
+        data.extend_from_slice(&(!(row_len as u16)).to_le_bytes());
+        let row_start = data.len();
+        data.push(0);
+        for p in &pixels[y * width as usize..(y + 1) * width as usize] {
+            data.extend_from_slice(&rgb565_to_rgb888(*p));
+        }
+        adler.update(&data[row_start..]);
+        if last {
+            data.extend_from_slice(&adler.finish().to_be_bytes());
+        }
+        write_chunk(b"IDAT", &data, out);
+    }
+    if height == 0 {
+        // still a valid zlib stream, one empty final block
+        let mut data = Vec::new();
+        data.extend_from_slice(&ZLIB_HEADER);
+        data.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+        data.extend_from_slice(&Adler32::new().finish().to_be_bytes());
+        write_chunk(b"IDAT", &data, out);
+    }
+    write_chunk(b"IEND", &[], out);
+}
+
+// Whole file in memory, for platforms that have the room.
+pub fn encode_png(pixels: &[Rgb565], width: u32, height: u32) -> Vec<u8> {
+    let mut png = Vec::new();
+    write_png(pixels, width, height, &mut |bytes| png.extend_from_slice(bytes));
+    png
+}
+
+// Repeats the top bits into the bottom ones so white stays 0xff.
+fn rgb565_to_rgb888(p: Rgb565) -> [u8; 3] {
+    [
+        p.r() << 3 | p.r() >> 2,
+        p.g() << 2 | p.g() >> 4,
+        p.b() << 3 | p.b() >> 2,
+    ]
+}
+
+fn write_chunk(kind: &[u8; 4], data: &[u8], out: &mut dyn FnMut(&[u8])) {
+    out(&(data.len() as u32).to_be_bytes());
+    out(kind);
+    out(data);
+    let crc = crc32(crc32(0, kind), data);
+    out(&crc.to_be_bytes());
+}
+
+// CRC-32 as used by PNG (and zip). Bitwise since it only runs for
+// screenshots, a table would cost 1K of flash. Pass 0 to start.
+fn crc32(crc: u32, data: &[u8]) -> u32 {
+    let mut c = !crc;
+    for b in data {
+        c ^= *b as u32;
+        for _ in 0..8 {
+            c = if c & 1 != 0 { 0xedb8_8320 ^ (c >> 1) } else { c >> 1 };
+        }
+    }
+    !c
+}
+
+struct Adler32 {
+    a: u32,
+    b: u32,
+}
+
+impl Adler32 {
+    const MOD: u32 = 65521;
+
+    fn new() -> Self {
+        Self { a: 1, b: 0 }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        for byte in data {
+            self.a = (self.a + *byte as u32) % Self::MOD;
+            self.b = (self.b + self.a) % Self::MOD;
+        }
+    }
+
+    fn finish(&self) -> u32 {
+        self.b << 16 | self.a
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checksums_match_reference_values() {
+        assert_eq!(crc32(0, b"123456789"), 0xcbf4_3926);
+        let mut adler = Adler32::new();
+        adler.update(b"Wikipedia");
+        assert_eq!(adler.finish(), 0x11e6_0398);
+    }
+
+    // Walks the chunks, checks their CRCs and unpacks the stored deflate
+    // blocks again.
+    #[test]
+    fn png_round_trips_through_stored_blocks() {
+        let (w, h) = (3, 2);
+        let pixels = [
+            Rgb565::WHITE,
+            Rgb565::BLACK,
+            Rgb565::RED,
+            Rgb565::GREEN,
+            Rgb565::BLUE,
+            Rgb565::new(16, 32, 16),
+        ];
+        let png = encode_png(&pixels, w, h);
+        assert_eq!(png[..8], SIGNATURE);
+
+        let mut rest = &png[8..];
+        let mut kinds = Vec::new();
+        let mut zlib = Vec::new();
+        while !rest.is_empty() {
+            let len = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+            let kind = &rest[4..8];
+            let data = &rest[8..8 + len];
+            let crc = u32::from_be_bytes(rest[8 + len..12 + len].try_into().unwrap());
+            assert_eq!(crc, crc32(crc32(0, kind), data));
+            if kind == b"IHDR" {
+                assert_eq!(data, [0, 0, 0, 3, 0, 0, 0, 2, 8, 2, 0, 0, 0]);
+            }
+            if kind == b"IDAT" {
+                zlib.extend_from_slice(data);
+            }
+            kinds.push(kind.to_vec());
+            rest = &rest[12 + len..];
+        }
+        assert_eq!(kinds.first().unwrap(), b"IHDR");
+        assert_eq!(kinds.last().unwrap(), b"IEND");
+
+        assert_eq!(zlib[..2], ZLIB_HEADER);
+        let mut raw = Vec::new();
+        let mut pos = 2;
+        loop {
+            let last = zlib[pos] & 1 == 1;
+            let len = u16::from_le_bytes([zlib[pos + 1], zlib[pos + 2]]);
+            let nlen = u16::from_le_bytes([zlib[pos + 3], zlib[pos + 4]]);
+            assert_eq!(len, !nlen);
+            raw.extend_from_slice(&zlib[pos + 5..pos + 5 + len as usize]);
+            pos += 5 + len as usize;
+            if last {
+                break;
+            }
+        }
+        let mut adler = Adler32::new();
+        adler.update(&raw);
+        assert_eq!(zlib[pos..], adler.finish().to_be_bytes());
+        assert_eq!(
+            raw,
+            [
+                0, 255, 255, 255, 0, 0, 0, 255, 0, 0, //
+                0, 0, 255, 0, 0, 0, 255, 132, 130, 132,
+            ]
+        );
+    }
+}
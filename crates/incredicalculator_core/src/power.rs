@@ -0,0 +1,162 @@
+// When to dim and blank the screen. Times count from the last key, 0 turns a
+// stage off.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PowerPolicy {
+    pub dim_after_ms: u64,
+    pub blank_after_ms: u64,
+    // backlight percentages
+    pub brightness: u8,
+    pub dim_brightness: u8,
+}
+
+impl Default for PowerPolicy {
+    fn default() -> Self {
+        Self {
+            dim_after_ms: 30_000,
+            blank_after_ms: 120_000,
+            brightness: 80,
+            dim_brightness: 15,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PowerState {
+    Active,
+    Dimmed,
+    // backlight off, the shell suspends the active app
+    Blank,
+}
+
+// Works on timestamps passed in by the shell so it can be tested without a
+// platform.
+pub struct PowerManager {
+    pub policy: PowerPolicy,
+    state: PowerState,
+    last_activity_ms: u64,
+}
+
+impl PowerManager {
+    pub fn new(policy: PowerPolicy, now: u64) -> Self {
+        Self {
+            policy,
+            state: PowerState::Active,
+            last_activity_ms: now,
+        }
+    }
+
+    pub fn state(&self) -> PowerState {
+        self.state
+    }
+
+    // A key was used. Returns true if it woke a blank screen, the shell
+    // swallows such keys so waking up doesn't also type something.
+    pub fn on_activity(&mut self, now: u64) -> bool {
+        let woke = self.state == PowerState::Blank;
+        self.last_activity_ms = now;
+        self.state = PowerState::Active;
+        woke
+    }
+
+    pub fn update(&mut self, now: u64) -> PowerState {
+        let idle = now.saturating_sub(self.last_activity_ms);
+        let reached = |after: u64| after > 0 && idle >= after;
+        self.state = if reached(self.policy.blank_after_ms) {
+            PowerState::Blank
+        } else if reached(self.policy.dim_after_ms) {
+            PowerState::Dimmed
+        } else {
+            PowerState::Active
+        };
+        self.state
+    }
+
+    // ms until update() would move to the next stage, None once there is
+    // nothing left but a key press
+    pub fn next_change_in(&self, now: u64) -> Option<u64> {
+        let idle = now.saturating_sub(self.last_activity_ms);
+        [self.policy.dim_after_ms, self.policy.blank_after_ms]
+            .into_iter()
+            .filter(|after| *after > idle)
+            .map(|after| after - idle)
+            .min()
+    }
+
+    pub fn backlight(&self) -> u8 {
+        match self.state {
+            PowerState::Active => self.policy.brightness,
+            PowerState::Dimmed => self.policy.dim_brightness.min(self.policy.brightness),
+            PowerState::Blank => 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FakeClock {
+        now: u64,
+    }
+
+    impl FakeClock {
+        fn advance(&mut self, ms: u64) -> u64 {
+            self.now += ms;
+            self.now
+        }
+    }
+
+    fn policy() -> PowerPolicy {
+        PowerPolicy {
+            dim_after_ms: 10_000,
+            blank_after_ms: 30_000,
+            brightness: 90,
+            dim_brightness: 20,
+        }
+    }
+
+    #[test]
+    fn dims_blanks_and_wakes() {
+        let mut clock = FakeClock { now: 1000 };
+        let mut power = PowerManager::new(policy(), clock.now);
+        assert_eq!(power.update(clock.advance(9_999)), PowerState::Active);
+        assert_eq!(power.backlight(), 90);
+        assert_eq!(power.next_change_in(clock.now), Some(1));
+        assert_eq!(power.update(clock.advance(1)), PowerState::Dimmed);
+        assert_eq!(power.backlight(), 20);
+        assert_eq!(power.next_change_in(clock.now), Some(20_000));
+
+        // a key while dimmed is a normal key and restarts the timers
+        assert!(!power.on_activity(clock.advance(5_000)));
+        assert_eq!(power.update(clock.now), PowerState::Active);
+        assert_eq!(power.update(clock.advance(29_999)), PowerState::Dimmed);
+        assert_eq!(power.update(clock.advance(1)), PowerState::Blank);
+        assert_eq!(power.backlight(), 0);
+        assert_eq!(power.next_change_in(clock.now), None);
+        assert_eq!(power.update(clock.advance(100_000)), PowerState::Blank);
+
+        assert!(power.on_activity(clock.advance(1)));
+        assert_eq!(power.state(), PowerState::Active);
+        assert_eq!(power.backlight(), 90);
+    }
+
+    #[test]
+    fn disabled_stages_are_skipped() {
+        let mut clock = FakeClock { now: 0 };
+        let mut power = PowerManager::new(
+            PowerPolicy {
+                dim_after_ms: 0,
+                ..policy()
+            },
+            clock.now,
+        );
+        assert_eq!(power.next_change_in(clock.now), Some(30_000));
+        assert_eq!(power.update(clock.advance(29_000)), PowerState::Active);
+        assert_eq!(power.update(clock.advance(1_000)), PowerState::Blank);
+
+        power.policy.blank_after_ms = 0;
+        power.on_activity(clock.now);
+        assert_eq!(power.update(clock.advance(1_000_000)), PowerState::Active);
+        assert_eq!(power.next_change_in(clock.now), None);
+    }
+}
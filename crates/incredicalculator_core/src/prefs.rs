@@ -0,0 +1,239 @@
+use alloc::vec::Vec;
+use core::cell::Cell;
+
+// What values a preference can take and how the settings app edits it. Every
+// kind is stored as an i32: 0/1 for Bool, the option index for Choice.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PrefKind {
+    Bool,
+    Choice(&'static [&'static str]),
+    Range {
+        min: i32,
+        max: i32,
+        step: i32,
+        unit: &'static str,
+    },
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct PrefDef {
+    // stable name the value is saved under, eg. "keys.repeat_delay"
+    pub key: &'static str,
+    pub label: &'static str,
+    pub kind: PrefKind,
+    pub default: i32,
+}
+
+impl PrefDef {
+    pub const fn bool(key: &'static str, label: &'static str, default: bool) -> Self {
+        Self {
+            key,
+            label,
+            kind: PrefKind::Bool,
+            default: default as i32,
+        }
+    }
+
+    pub const fn choice(
+        key: &'static str,
+        label: &'static str,
+        options: &'static [&'static str],
+        default: usize,
+    ) -> Self {
+        Self {
+            key,
+            label,
+            kind: PrefKind::Choice(options),
+            default: default as i32,
+        }
+    }
+
+    pub const fn range(
+        key: &'static str,
+        label: &'static str,
+        min: i32,
+        max: i32,
+        step: i32,
+        unit: &'static str,
+        default: i32,
+    ) -> Self {
+        Self {
+            key,
+            label,
+            kind: PrefKind::Range {
+                min,
+                max,
+                step,
+                unit,
+            },
+            default,
+        }
+    }
+
+    fn clamp(&self, value: i32) -> i32 {
+        match self.kind {
+            PrefKind::Bool => (value != 0) as i32,
+            PrefKind::Choice(options) => value.clamp(0, options.len() as i32 - 1),
+            PrefKind::Range { min, max, .. } => value.clamp(min, max),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PrefId(usize);
+
+// Every preference registered by the shell and the apps. Apps read them
+// through InputContext::prefs. Values sit in Cells so the settings app can
+// change them through the shared reference it gets, the shell notices with
+// take_changed() after each update.
+#[derive(Default)]
+pub struct Prefs {
+    defs: Vec<PrefDef>,
+    values: Vec<Cell<i32>>,
+    changed: Cell<bool>,
+}
+
+impl Prefs {
+    // Stored next to the encoded blob, bumped whenever the encoding changes.
+    // Blobs with another version are ignored.
+    pub const FORMAT_VERSION: u16 = 1;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Registering a key twice returns the existing id, so apps can share a
+    // preference.
+    pub fn register(&mut self, def: PrefDef) -> PrefId {
+        if let Some(id) = self.find(def.key) {
+            return id;
+        }
+        self.values.push(Cell::new(def.clamp(def.default)));
+        self.defs.push(def);
+        PrefId(self.defs.len() - 1)
+    }
+
+    pub fn find(&self, key: &str) -> Option<PrefId> {
+        self.defs.iter().position(|d| d.key == key).map(PrefId)
+    }
+
+    pub fn len(&self) -> usize {
+        self.defs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.defs.is_empty()
+    }
+
+    // ids in registration order, for listing them
+    pub fn id_at(&self, idx: usize) -> Option<PrefId> {
+        (idx < self.defs.len()).then_some(PrefId(idx))
+    }
+
+    pub fn def(&self, id: PrefId) -> &PrefDef {
+        &self.defs[id.0]
+    }
+
+    pub fn get(&self, id: PrefId) -> i32 {
+        self.values[id.0].get()
+    }
+
+    pub fn get_bool(&self, id: PrefId) -> bool {
+        self.get(id) != 0
+    }
+
+    pub fn set(&self, id: PrefId, value: i32) {
+        let value = self.defs[id.0].clamp(value);
+        if self.values[id.0].replace(value) != value {
+            self.changed.set(true);
+        }
+    }
+
+    // What left/right do in the settings app. Bools and choices wrap around,
+    // ranges stop at their ends.
+    pub fn step(&self, id: PrefId, forward: bool) {
+        let value = self.get(id);
+        let next = match self.defs[id.0].kind {
+            PrefKind::Bool => 1 - value,
+            PrefKind::Choice(options) => {
+                let n = options.len() as i32;
+                (value + if forward { 1 } else { n - 1 }) % n
+            }
+            PrefKind::Range { step, .. } => value + if forward { step } else { -step },
+        };
+        self.set(id, next);
+    }
+
+    // true once after any value changed
+    pub fn take_changed(&self) -> bool {
+        self.changed.replace(false)
+    }
+
+    // Per pref: [key len][key][value as i32 le]. Keyed by name so adding or
+    // removing preferences keeps the other saved values.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        for (def, value) in self.defs.iter().zip(self.values.iter()) {
+            out.push(def.key.len() as u8);
+            out.extend_from_slice(def.key.as_bytes());
+            out.extend_from_slice(&value.get().to_le_bytes());
+        }
+        out
+    }
+
+    // Applies whatever saved values match registered keys. Doesn't count as
+    // a change, the caller knows it just loaded.
+    pub fn decode(&self, data: &[u8]) {
+        let mut rest = data;
+        while let Some((&key_len, tail)) = rest.split_first() {
+            let key_len = key_len as usize;
+            if tail.len() < key_len + 4 {
+                return;
+            }
+            let (key, tail) = tail.split_at(key_len);
+            let (value, tail) = tail.split_at(4);
+            let value = i32::from_le_bytes([value[0], value[1], value[2], value[3]]);
+            let id = core::str::from_utf8(key).ok().and_then(|k| self.find(k));
+            if let Some(id) = id {
+                self.values[id.0].set(self.defs[id.0].clamp(value));
+            }
+            rest = tail;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODES: &[&str] = &["A", "B", "C"];
+
+    #[test]
+    fn encode_decode_keeps_values_by_key() {
+        let mut prefs = Prefs::new();
+        let flag = prefs.register(PrefDef::bool("flag", "Flag", false));
+        let mode = prefs.register(PrefDef::choice("mode", "Mode", MODES, 0));
+        let delay = prefs.register(PrefDef::range("delay", "Delay", 100, 900, 50, "ms", 500));
+        prefs.set(flag, 1);
+        prefs.step(mode, false);
+        prefs.set(delay, 5000);
+        assert!(prefs.take_changed());
+        assert_eq!(prefs.get(mode), 2);
+        assert_eq!(prefs.get(delay), 900);
+
+        // registered in a different order with one pref missing
+        let mut loaded = Prefs::new();
+        let delay2 = loaded.register(PrefDef::range("delay", "Delay", 100, 700, 50, "ms", 500));
+        let flag2 = loaded.register(PrefDef::bool("flag", "Flag", false));
+        loaded.decode(&prefs.encode());
+        assert!(loaded.get_bool(flag2));
+        assert_eq!(loaded.get(delay2), 700);
+        assert!(!loaded.take_changed());
+
+        // a truncated entry leaves the default alone
+        let mut fresh = Prefs::new();
+        let flag3 = fresh.register(PrefDef::bool("flag", "Flag", false));
+        fresh.decode(&[4, b'f', b'l', b'a', b'g', 1, 0]);
+        assert!(!fresh.get_bool(flag3));
+    }
+}
@@ -1,42 +1,735 @@
+use crate::app::AppInfo;
+use crate::app::FrameRequest;
 use crate::app::IcApp;
 use crate::app::InputContext;
+use crate::app::Viewport;
 use crate::apps::AspectRatioCalculator;
 use crate::apps::Calculator;
-use crate::apps::{ RangeMapperCalculator, FaceCalculator };
+use crate::apps::{ RangeMapperCalculator, FaceCalculator, SettingsApp, BatteryApp };
+use crate::battery::{BatteryLevel, BatteryMonitor, BatteryThresholds, draw_shutdown_screen};
 use crate::input;
 use crate::input::IcKey;
 use crate::input::KeyState;
-use crate::platform::IcPlatform;
-use crate::text::*;
+use crate::input::{KeyEventKind, KeyTiming, ModifierMode, ModifierState};
+use crate::launcher::Launcher;
+use crate::legend::{KeyLayer, KeyLegend, draw_help};
+use crate::macros::{MacroStep, Macros};
+use crate::overlay::Overlays;
+use crate::platform::{IcPlatform, debug_log, rgb8_hex};
+use crate::power::{PowerManager, PowerPolicy, PowerState};
+use crate::keymap::Keymaps;
+use crate::prefs::{PrefDef, PrefId, Prefs};
+use crate::status_bar::{ModifierStatus, STATUS_BAR_HEIGHT, StatusInfo, draw_status_bar};
+use crate::state::{StateReader, StateWriter};
+use crate::storage::{MAX_NAME_LEN, StorageError};
+use crate::theme::{Palette, THEME_NAMES};
+use crate::ui::{NavDir, SCREEN_SIZE};
 use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
 use glam::IVec2;
 use num_traits::FromPrimitive;
-use rgb::Rgb;
-use rgb::*;
+
+// everything below the status bar
+const APP_VIEWPORT: Viewport = Viewport {
+    pos: IVec2::new(0, STATUS_BAR_HEIGHT),
+    size: IVec2::new(SCREEN_SIZE.x, SCREEN_SIZE.y - STATUS_BAR_HEIGHT),
+};
+
+// Super+F1..F4 open whatever app is in the matching slot
+const FAVOURITE_SLOTS: usize = 4;
+
+// name of the storage blob holding every preference
+const PREFS_BLOB: &str = "prefs";
+// and the one holding the user's key bindings
+const KEYMAPS_BLOB: &str = "keymaps";
+// and the one holding the recorded macros
+const MACROS_BLOB: &str = "macros";
+
+// App state is saved once input has been idle this long, so typing doesn't
+// write to flash on every key.
+const AUTOSAVE_IDLE_MS: u64 = 5000;
+
+// how often the shutdown screen checks whether a charger was connected
+const BATTERY_POLL_MS: u32 = 5000;
+
+// in the same order as ModifierMode
+const MODIFIER_MODES: &[&str] = &["Hold", "One-shot", "Locking"];
+
+struct ShellPrefs {
+    repeat_delay: PrefId,
+    repeat_interval: PrefId,
+    long_press: PrefId,
+    shift_mode: PrefId,
+    super_mode: PrefId,
+    theme: PrefId,
+    brightness: PrefId,
+    dim_after: PrefId,
+    blank_after: PrefId,
+    battery_low: PrefId,
+    battery_critical: PrefId,
+}
+
+impl ShellPrefs {
+    fn register(prefs: &mut Prefs) -> Self {
+        let timing = KeyTiming::default();
+        let power = PowerPolicy::default();
+        let battery = BatteryThresholds::default();
+        Self {
+            repeat_delay: prefs.register(PrefDef::range(
+                "keys.repeat_delay",
+                "Repeat delay",
+                200,
+                1000,
+                50,
+                "ms",
+                timing.repeat_delay_ms as i32,
+            )),
+            repeat_interval: prefs.register(PrefDef::range(
+                "keys.repeat_interval",
+                "Repeat rate",
+                30,
+                300,
+                10,
+                "ms",
+                timing.repeat_interval_ms as i32,
+            )),
+            long_press: prefs.register(PrefDef::range(
+                "keys.long_press",
+                "Long press",
+                300,
+                1500,
+                100,
+                "ms",
+                timing.long_press_ms as i32,
+            )),
+            shift_mode: prefs.register(PrefDef::choice(
+                "keys.shift_mode",
+                "Shift",
+                MODIFIER_MODES,
+                1,
+            )),
+            super_mode: prefs.register(PrefDef::choice(
+                "keys.super_mode",
+                "Super",
+                MODIFIER_MODES,
+                1,
+            )),
+            theme: prefs.register(PrefDef::choice("display.theme", "Theme", THEME_NAMES, 0)),
+            brightness: prefs.register(PrefDef::range(
+                "power.brightness",
+                "Brightness",
+                10,
+                100,
+                10,
+                "%",
+                power.brightness as i32,
+            )),
+            dim_after: prefs.register(PrefDef::range(
+                "power.dim_after",
+                "Dim after",
+                0,
+                300,
+                10,
+                "s",
+                (power.dim_after_ms / 1000) as i32,
+            )),
+            blank_after: prefs.register(PrefDef::range(
+                "power.blank_after",
+                "Screen off",
+                0,
+                900,
+                30,
+                "s",
+                (power.blank_after_ms / 1000) as i32,
+            )),
+            battery_low: prefs.register(PrefDef::range(
+                "battery.low",
+                "Low battery",
+                5,
+                50,
+                5,
+                "%",
+                battery.low,
+            )),
+            battery_critical: prefs.register(PrefDef::range(
+                "battery.critical",
+                "Shut down at",
+                1,
+                20,
+                1,
+                "%",
+                battery.critical,
+            )),
+        }
+    }
+}
+
+// storage blob for an app's state, app names may have characters storage
+// names can't
+fn state_blob_name(app_name: &str) -> String {
+    let mut name = String::from("app_");
+    for c in app_name.chars().take(MAX_NAME_LEN - name.len()) {
+        name.push(if c.is_ascii_alphanumeric() { c } else { '_' });
+    }
+    name
+}
+
+fn modifier_mode(choice: i32) -> ModifierMode {
+    match choice {
+        0 => ModifierMode::Momentary,
+        2 => ModifierMode::Locking,
+        _ => ModifierMode::OneShot,
+    }
+}
 
 pub struct IcShell {
-    apps: [Box<dyn IcApp>; 4], // INCREASE THIS SIZE WHEN ADDING NEW APPS
+    apps: Vec<Box<dyn IcApp>>,
+    app_infos: Vec<AppInfo>,
+    favourites: [Option<usize>; FAVOURITE_SLOTS],
     active_app_idx: usize,
+    launcher: Launcher,
+    launcher_open: bool,
+    suspended: bool,
+    // a key changed since the last update
+    input_pending: bool,
     key_states: [KeyState; IcKey::COUNT],
+    key_timing: KeyTiming,
+    last_update_ms: u64,
+    shift: ModifierState,
+    super_mod: ModifierState,
+    // keeps the status bar clock ticking while the app is idle
+    ms_to_next_minute: Option<u32>,
+    palette: Palette,
+    prefs: Prefs,
+    shell_prefs: ShellPrefs,
+    keymaps: Keymaps,
+    // the saved preferences are read on the first update, once there is a
+    // platform to read them from
+    prefs_loaded: bool,
+    // per app, got input since its state was last saved
+    state_dirty: Vec<bool>,
+    last_input_ms: u64,
+    power: PowerManager,
+    // last state and backlight level handed to the platform
+    power_state: PowerState,
+    backlight: Option<u8>,
+    // keys that woke the screen, ignored until they are released
+    swallowed: [bool; IcKey::COUNT],
+    overlays: Overlays,
+    battery: BatteryMonitor,
+    // key legend drawn over everything, opened by holding Super
+    help_open: bool,
+    macros: Macros,
+    // set while playback calls key_down/key_up, so it doesn't stop itself
+    replaying: bool,
 }
 
 impl IcShell {
     pub fn new() -> Self {
-        Self {
-            apps: [Box::new(Calculator::new()), 
-                Box::new(AspectRatioCalculator::new()),
-                Box::new(RangeMapperCalculator::new()),
-                Box::new(FaceCalculator::new())],
+        let mut prefs = Prefs::new();
+        let shell_prefs = ShellPrefs::register(&mut prefs);
+        let mut shell = Self {
+            apps: Vec::new(),
+            app_infos: Vec::new(),
+            favourites: [None; FAVOURITE_SLOTS],
             active_app_idx: 0,
+            launcher: Launcher::new(),
+            launcher_open: false,
+            suspended: false,
+            input_pending: false,
             key_states: [KeyState::default(); IcKey::COUNT],
+            key_timing: KeyTiming::default(),
+            last_update_ms: 0,
+            shift: ModifierState::new(ModifierMode::OneShot),
+            super_mod: ModifierState::new(ModifierMode::OneShot),
+            ms_to_next_minute: None,
+            palette: Palette::from_index(0),
+            prefs,
+            shell_prefs,
+            keymaps: Keymaps::new(),
+            prefs_loaded: false,
+            state_dirty: Vec::new(),
+            last_input_ms: 0,
+            power: PowerManager::new(PowerPolicy::default(), 0),
+            power_state: PowerState::Active,
+            backlight: None,
+            swallowed: [false; IcKey::COUNT],
+            overlays: Overlays::new(),
+            battery: BatteryMonitor::new(BatteryThresholds::default()),
+            help_open: false,
+            macros: Macros::new(),
+            replaying: false,
+        };
+        let calc = shell.register_app(
+            AppInfo {
+                name: "Calc",
+                icon: "+-",
+                color: rgb8_hex(0x8a2020),
+            },
+            Box::new(Calculator::new()),
+        );
+        let aspect = shell.register_app(
+            AppInfo {
+                name: "Aspect",
+                icon: "16:9",
+                color: rgb8_hex(0xad4b27),
+            },
+            Box::new(AspectRatioCalculator::new()),
+        );
+        let range = shell.register_app(
+            AppInfo {
+                name: "Range",
+                icon: "a>b",
+                color: rgb8_hex(0x3a6aaf),
+            },
+            Box::new(RangeMapperCalculator::new()),
+        );
+        let face = shell.register_app(
+            AppInfo {
+                name: "Face",
+                icon: "o o",
+                color: rgb8_hex(0x2a7a4a),
+            },
+            Box::new(FaceCalculator::new()),
+        );
+        shell.register_app(
+            AppInfo {
+                name: "Settings",
+                icon: "cfg",
+                color: rgb8_hex(0x505068),
+            },
+            Box::new(SettingsApp::new()),
+        );
+        shell.register_app(
+            AppInfo {
+                name: "Battery",
+                icon: "bat",
+                color: rgb8_hex(0x6a6a20),
+            },
+            Box::new(BatteryApp::new()),
+        );
+        shell.favourites = [Some(calc), Some(aspect), Some(range), Some(face)];
+        shell.apply_prefs();
+        shell.apps[shell.active_app_idx].on_enter();
+        shell
+    }
+
+    // Returns the index of the new app, for use with set_favourite.
+    pub fn register_app(&mut self, info: AppInfo, mut app: Box<dyn IcApp>) -> usize {
+        app.register_prefs(&mut self.prefs);
+        app.on_prefs_changed(&self.prefs);
+        app.register_keymaps(&mut self.keymaps);
+        app.on_keymaps_changed(&self.keymaps);
+        self.apps.push(app);
+        self.app_infos.push(info);
+        self.state_dirty.push(false);
+        self.apps.len() - 1
+    }
+
+    pub fn set_favourite(&mut self, slot: usize, app_idx: Option<usize>) {
+        if slot < FAVOURITE_SLOTS && app_idx.is_none_or(|i| i < self.apps.len()) {
+            self.favourites[slot] = app_idx;
+        }
+    }
+
+    fn load_prefs(&mut self, platform: &mut dyn IcPlatform) {
+        self.prefs_loaded = true;
+        let Some(storage) = platform.storage() else {
+            return;
+        };
+        let mut data = Vec::new();
+        match storage.read(PREFS_BLOB, &mut data) {
+            Ok(Prefs::FORMAT_VERSION) => self.prefs.decode(&data),
+            Ok(version) => debug_log!(platform, "ignoring prefs version {}", version),
+            Err(StorageError::NotFound) => {}
+            Err(e) => debug_log!(platform, "failed to load prefs: {:?}", e),
+        }
+        self.apply_prefs();
+        self.load_keymaps(platform);
+        self.load_macros(platform);
+        for idx in 0..self.apps.len() {
+            self.load_app_state(idx, platform);
+        }
+    }
+
+    fn load_app_state(&mut self, idx: usize, platform: &mut dyn IcPlatform) {
+        let Some(storage) = platform.storage() else {
+            return;
+        };
+        let mut data = Vec::new();
+        let name = state_blob_name(self.app_infos[idx].name);
+        match storage.read(&name, &mut data) {
+            Ok(version) => {
+                let mut r = StateReader::new(&data);
+                if self.apps[idx].restore_state(version, &mut r).is_none() {
+                    debug_log!(platform, "dropped saved state of {}", self.app_infos[idx].name);
+                }
+            }
+            Err(StorageError::NotFound) => {}
+            Err(e) => debug_log!(platform, "failed to load {}: {:?}", name, e),
+        }
+    }
+
+    fn save_app_state(&mut self, idx: usize, platform: &mut dyn IcPlatform) {
+        self.state_dirty[idx] = false;
+        let Some(storage) = platform.storage() else {
+            return;
+        };
+        let app = &self.apps[idx];
+        let mut w = StateWriter::new();
+        app.save_state(&mut w);
+        let name = state_blob_name(self.app_infos[idx].name);
+        let result = if w.as_bytes().is_empty() {
+            storage.remove(&name)
+        } else {
+            storage.write(&name, app.state_version(), w.as_bytes())
+        };
+        if let Err(e) = result {
+            debug_log!(platform, "failed to save {}: {:?}", name, e);
+        }
+    }
+
+    // Saves every app that got input since its last save, platforms should
+    // call this before cutting power.
+    pub fn save_state(&mut self, platform: &mut dyn IcPlatform) {
+        for idx in 0..self.apps.len() {
+            if self.state_dirty[idx] {
+                self.save_app_state(idx, platform);
+            }
+        }
+    }
+
+    // Hidden apps don't change anymore so they are saved right away, the
+    // one on screen once the user stops typing.
+    fn autosave(&mut self, now: u64, platform: &mut dyn IcPlatform) {
+        let idle = now.saturating_sub(self.last_input_ms) >= AUTOSAVE_IDLE_MS;
+        for idx in 0..self.apps.len() {
+            let on_screen = !self.launcher_open && idx == self.active_app_idx;
+            if self.state_dirty[idx] && (idle || !on_screen) {
+                self.save_app_state(idx, platform);
+            }
+        }
+    }
+
+    fn apply_prefs(&mut self) {
+        let ids = &self.shell_prefs;
+        self.key_timing.repeat_delay_ms = self.prefs.get(ids.repeat_delay) as u64;
+        self.key_timing.repeat_interval_ms = self.prefs.get(ids.repeat_interval) as u64;
+        self.key_timing.long_press_ms = self.prefs.get(ids.long_press) as u64;
+        let shift_mode = modifier_mode(self.prefs.get(ids.shift_mode));
+        let super_mode = modifier_mode(self.prefs.get(ids.super_mode));
+        self.palette = Palette::from_index(self.prefs.get(ids.theme) as usize);
+        self.power.policy.brightness = self.prefs.get(ids.brightness) as u8;
+        self.power.policy.dim_after_ms = self.prefs.get(ids.dim_after) as u64 * 1000;
+        self.power.policy.blank_after_ms = self.prefs.get(ids.blank_after) as u64 * 1000;
+        self.battery.thresholds.low = self.prefs.get(ids.battery_low);
+        self.battery.thresholds.critical = self.prefs.get(ids.battery_critical);
+        if self.shift.mode != shift_mode {
+            self.set_modifier_mode(IcKey::Shift, shift_mode);
+        }
+        if self.super_mod.mode != super_mode {
+            self.set_modifier_mode(IcKey::Super, super_mode);
+        }
+        for app in self.apps.iter_mut() {
+            app.on_prefs_changed(&self.prefs);
+        }
+    }
+
+    fn save_prefs(&mut self, platform: &mut dyn IcPlatform) {
+        let Some(storage) = platform.storage() else {
+            return;
+        };
+        if let Err(e) = storage.write(PREFS_BLOB, Prefs::FORMAT_VERSION, &self.prefs.encode()) {
+            debug_log!(platform, "failed to save prefs: {:?}", e);
+        }
+    }
+
+    fn load_keymaps(&mut self, platform: &mut dyn IcPlatform) {
+        let Some(storage) = platform.storage() else {
+            return;
+        };
+        let mut data = Vec::new();
+        match storage.read(KEYMAPS_BLOB, &mut data) {
+            Ok(Keymaps::FORMAT_VERSION) => self.keymaps.decode(&data),
+            Ok(version) => debug_log!(platform, "ignoring keymaps version {}", version),
+            Err(StorageError::NotFound) => {}
+            Err(e) => debug_log!(platform, "failed to load keymaps: {:?}", e),
+        }
+        self.apply_keymaps();
+    }
+
+    fn load_macros(&mut self, platform: &mut dyn IcPlatform) {
+        let Some(storage) = platform.storage() else {
+            return;
+        };
+        let mut data = Vec::new();
+        match storage.read(MACROS_BLOB, &mut data) {
+            Ok(Macros::FORMAT_VERSION) => {
+                if self.macros.decode(&data).is_none() {
+                    debug_log!(platform, "ignoring broken macros");
+                }
+            }
+            Ok(version) => debug_log!(platform, "ignoring macros version {}", version),
+            Err(StorageError::NotFound) => {}
+            Err(e) => debug_log!(platform, "failed to load macros: {:?}", e),
+        }
+    }
+
+    fn save_macros(&mut self, platform: &mut dyn IcPlatform) {
+        let Some(storage) = platform.storage() else {
+            return;
+        };
+        if let Err(e) = storage.write(MACROS_BLOB, Macros::FORMAT_VERSION, &self.macros.encode()) {
+            debug_log!(platform, "failed to save macros: {:?}", e);
+        }
+    }
+
+    // Shift+Super chords: F1..F4 play a slot or, while recording, save into
+    // it, F5 cancels, F6 starts recording, adds a pause to the recording or
+    // continues playback from one.
+    fn macro_chord(&mut self, key: IcKey, platform: &mut dyn IcPlatform, overlays: &Overlays) -> bool {
+        match key {
+            IcKey::Func1 | IcKey::Func2 | IcKey::Func3 | IcKey::Func4 => {
+                let slot = key as usize - IcKey::Func1 as usize;
+                if self.macros.is_recording() {
+                    self.macros.finish_recording(slot);
+                    self.save_macros(platform);
+                    overlays.toast(&format!("Saved as macro {}", slot + 1));
+                } else if self.macros.play(slot) {
+                    // latches left over from the chord would apply to the first key
+                    self.shift.clear();
+                    self.super_mod.clear();
+                } else {
+                    overlays.toast(&format!("Macro {} is empty", slot + 1));
+                }
+            }
+            IcKey::Func5 => {
+                if self.macros.is_recording() {
+                    self.macros.cancel_recording();
+                    overlays.toast("Recording cancelled");
+                } else if self.macros.is_playing() {
+                    self.stop_macro();
+                    overlays.toast("Macro stopped");
+                }
+            }
+            IcKey::Func6 => {
+                if self.macros.is_paused() {
+                    self.macros.resume();
+                    self.shift.clear();
+                    self.super_mod.clear();
+                } else if self.macros.is_recording() {
+                    if self.macros.record(MacroStep::Pause) {
+                        overlays.toast("Pause added");
+                    }
+                } else if !self.macros.is_playing() {
+                    self.macros.start_recording();
+                    overlays.toast("Recording macro");
+                }
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    fn stop_macro(&mut self) {
+        for key in self.macros.stop() {
+            self.key_states[key as usize].is_down = false;
+        }
+    }
+
+    fn apply_keymaps(&mut self) {
+        for app in self.apps.iter_mut() {
+            app.on_keymaps_changed(&self.keymaps);
+        }
+    }
+
+    fn save_keymaps(&mut self, platform: &mut dyn IcPlatform) {
+        let Some(storage) = platform.storage() else {
+            return;
+        };
+        if let Err(e) = storage.write(KEYMAPS_BLOB, Keymaps::FORMAT_VERSION, &self.keymaps.encode()) {
+            debug_log!(platform, "failed to save keymaps: {:?}", e);
+        }
+    }
+
+    fn open_app(&mut self, idx: usize) {
+        if idx >= self.apps.len() {
+            return;
+        }
+        if !self.launcher_open {
+            if idx == self.active_app_idx {
+                return;
+            }
+            self.apps[self.active_app_idx].on_exit();
+        }
+        self.launcher_open = false;
+        self.active_app_idx = idx;
+        self.apps[idx].on_enter();
+    }
+
+    fn open_launcher(&mut self) {
+        self.apps[self.active_app_idx].on_exit();
+        self.launcher.select(self.active_app_idx);
+        self.launcher_open = true;
+    }
+
+    fn close_launcher(&mut self) {
+        self.launcher_open = false;
+        self.apps[self.active_app_idx].on_enter();
+    }
+
+    fn toggle_launcher(&mut self) {
+        if self.launcher_open {
+            self.close_launcher();
+        } else {
+            self.open_launcher();
+        }
+    }
+
+    // Platforms call these around sleeping the device, only the app on
+    // screen is told, none while the launcher is open. Unsaved app state is
+    // written before sleeping.
+    pub fn suspend(&mut self, platform: &mut dyn IcPlatform) {
+        if !self.suspended {
+            self.suspended = true;
+            if !self.launcher_open {
+                self.apps[self.active_app_idx].on_suspend();
+            }
+            self.save_state(platform);
+        }
+    }
+
+    pub fn resume(&mut self) {
+        if self.suspended {
+            self.suspended = false;
+            if !self.launcher_open {
+                self.apps[self.active_app_idx].on_resume();
+            }
         }
     }
 
+    // Dims and blanks the screen as input goes idle, blanking suspends the
+    // active app.
+    fn update_power(&mut self, now: u64, platform: &mut dyn IcPlatform) {
+        let state = self.power.update(now);
+        if state != self.power_state {
+            if state == PowerState::Blank {
+                self.suspend(platform);
+            } else if self.power_state == PowerState::Blank {
+                self.resume();
+            }
+            self.power_state = state;
+            platform.set_power_state(state);
+        }
+        let mut level = self.power.backlight();
+        if self.battery.level() == BatteryLevel::Critical {
+            level = level.min(self.power.policy.dim_brightness);
+        }
+        if self.backlight != Some(level) {
+            self.backlight = Some(level);
+            platform.set_backlight(level);
+        }
+    }
+
+    // Warns once the battery gets low. Once it is critical everything is
+    // saved and update() only shows the shutdown screen, until a charger is
+    // connected.
+    fn update_battery(&mut self, platform: &mut dyn IcPlatform) {
+        let soc = platform.get_battery_soc();
+        let previous = self.battery.level();
+        match self.battery.update(soc, platform.is_charging()) {
+            Some(BatteryLevel::Low) if previous == BatteryLevel::Normal => {
+                self.overlays.toast(&format!("Battery low: {}%", soc));
+            }
+            Some(BatteryLevel::Critical) => self.save_state(platform),
+            _ => {}
+        }
+    }
+
+    pub fn set_key_timing(&mut self, timing: KeyTiming) {
+        self.key_timing = timing;
+    }
+
+    // key is IcKey::Shift or IcKey::Super, anything else is ignored
+    pub fn set_modifier_mode(&mut self, key: IcKey, mode: ModifierMode) {
+        let modifier = match key {
+            IcKey::Shift => &mut self.shift,
+            IcKey::Super => &mut self.super_mod,
+            _ => return,
+        };
+        modifier.mode = mode;
+        modifier.clear();
+    }
+
+    // The launcher takes arrows from the number pad with or without Super,
+    // since there is no text to type while it is open.
+    fn launcher_key(&mut self, key: IcKey, kind: KeyEventKind) {
+        let count = self.apps.len();
+        if kind == KeyEventKind::Repeat {
+            // only the arrows repeat, holding Enter should not open apps
+            if !matches!(key, IcKey::Num8 | IcKey::Num2 | IcKey::Num4 | IcKey::Num6) {
+                return;
+            }
+        } else if kind != KeyEventKind::Press {
+            return;
+        }
+        match key {
+            IcKey::Num8 => self.launcher.navigate(NavDir::Up, count),
+            IcKey::Num2 => self.launcher.navigate(NavDir::Down, count),
+            IcKey::Num4 => self.launcher.navigate(NavDir::Left, count),
+            IcKey::Num6 => self.launcher.navigate(NavDir::Right, count),
+            IcKey::Num5 | IcKey::Func6 => self.open_app(self.launcher.selected()),
+            IcKey::Func1 => self.close_launcher(),
+            _ => {}
+        }
+    }
+
+    // What each key does right now, for the help overlay and for platforms
+    // that label their keys, like the simulator.
+    pub fn key_legend(&self) -> KeyLegend {
+        let mut legend = KeyLegend::new();
+        if self.launcher_open {
+            for (key, label) in [
+                (IcKey::Num8, "^"),
+                (IcKey::Num2, "v"),
+                (IcKey::Num4, "<"),
+                (IcKey::Num6, ">"),
+                (IcKey::Num5, "Open"),
+                (IcKey::Func6, "Open"),
+                (IcKey::Func1, "Back"),
+            ] {
+                legend.set(key, KeyLayer::Base, label);
+            }
+        } else {
+            self.apps[self.active_app_idx].key_legend(&mut legend);
+        }
+        // the shell's own shortcuts win, as in update()
+        legend.set(IcKey::Shift, KeyLayer::Base, "Shft");
+        legend.set(IcKey::Super, KeyLayer::Base, "Sup");
+        legend.set(IcKey::Super, KeyLayer::Super, "Help");
+        for (slot, app_idx) in self.favourites.iter().enumerate() {
+            let key = IcKey::from_usize(IcKey::Func1 as usize + slot);
+            if let (Some(key), Some(app_idx)) = (key, app_idx) {
+                legend.set(key, KeyLayer::Super, self.app_infos[*app_idx].name);
+            }
+        }
+        legend.set(IcKey::Func5, KeyLayer::Super, "Apps");
+        legend.set(IcKey::Func6, KeyLayer::Super, "Shot");
+        legend
+    }
+
     pub fn key_down(&mut self, key: IcKey) {
         if key == IcKey::_Max {
             ()
         }
+        // typing stops playback, except at a pause where it is expected
+        if !self.replaying && self.macros.is_playing() && !self.macros.is_paused() {
+            self.stop_macro();
+        }
         self.key_states[key as usize].is_down = true;
+        self.input_pending = true;
     }
 
     pub fn key_up(&mut self, key: IcKey) {
@@ -44,78 +737,469 @@ impl IcShell {
             ()
         }
         self.key_states[key as usize].is_down = false;
+        self.input_pending = true;
     }
 
-    fn draw_battery(&mut self, platform: &mut dyn IcPlatform) {
-        let batt_percentage: i32 = platform.get_battery_soc();
-        let batt_icon_pos = IVec2::new(282, 3);
-        let batt_icon_w = 34;
-        let batt_icon_h = 17;
-        let fill_w = (batt_percentage * batt_icon_w) / 100;
-        platform.draw_rectangle(
-            batt_icon_pos,
-            batt_icon_pos + IVec2::new(batt_icon_w, batt_icon_h),
-            RGB8::new(0, 0, 0),
-            0,
-            Some(RGB::new(0x80, 0x80, 0x80)),
-        );
-        platform.draw_rectangle(
-            batt_icon_pos,
-            batt_icon_pos + IVec2::new(fill_w, batt_icon_h),
-            RGB8::new(0, 0, 0),
-            0,
-            Some(RGB::new(0xff, 0xff, 0xff)),
-        );
-        platform.draw_string_f(
-            format_args!("{}", batt_percentage),
-            IVec2::new(290, 2),
-            4,
-            Rgb::new(0, 0, 0),
-        );
+    // The borrowed parts are moved out of the shell during update(), see there.
+    fn context<'a>(
+        &self,
+        key_states: &'a [KeyState; IcKey::COUNT],
+        prefs: &'a Prefs,
+        keymaps: &'a Keymaps,
+        palette: &'a Palette,
+        overlays: &'a Overlays,
+    ) -> InputContext<'a> {
+        InputContext {
+            key_states,
+            shift_latched: self.shift.is_latched(),
+            super_latched: self.super_mod.is_latched(),
+            viewport: APP_VIEWPORT,
+            prefs,
+            keymaps,
+            palette,
+            overlays,
+        }
+    }
+
+    // Platform loops use this to decide when to call update() next.
+    pub fn frame_request(&self) -> FrameRequest {
+        if self.input_pending || (self.macros.is_playing() && !self.macros.is_paused()) {
+            return FrameRequest::Continuous;
+        }
+        // only a key can wake a sleeping device
+        if self.suspended {
+            return FrameRequest::Idle;
+        }
+        let mut request = match self.ms_to_next_minute {
+            Some(ms) => FrameRequest::AfterMs(ms),
+            None => FrameRequest::Idle,
+        };
+        if let Some(ms) = self.power.next_change_in(self.last_update_ms) {
+            request = request.sooner(FrameRequest::AfterMs(ms as u32));
+        }
+        if let Some(ms) = self.overlays.next_change_in(self.last_update_ms) {
+            request = request.sooner(FrameRequest::AfterMs(ms as u32));
+        }
+        // held keys need updates to produce repeats and long presses
+        for s in self.key_states.iter() {
+            if let Some(ms) = s.next_event_in(&self.key_timing, self.last_update_ms) {
+                request = request.sooner(FrameRequest::AfterMs(ms as u32));
+            }
+        }
+        if self.battery.level() == BatteryLevel::Critical {
+            return request.sooner(FrameRequest::AfterMs(BATTERY_POLL_MS));
+        }
+        if self.state_dirty.iter().any(|d| *d) {
+            let idle_ms = self.last_update_ms.saturating_sub(self.last_input_ms);
+            let ms = AUTOSAVE_IDLE_MS.saturating_sub(idle_ms);
+            request = request.sooner(FrameRequest::AfterMs(ms as u32));
+        }
+        if self.launcher_open {
+            request
+        } else {
+            request.sooner(self.apps[self.active_app_idx].frame_request())
+        }
+    }
+
+    fn draw_status_bar(&self, platform: &mut dyn IcPlatform) {
+        let (app_name, mode_text) = if self.launcher_open {
+            ("Apps", "")
+        } else {
+            (
+                self.app_infos[self.active_app_idx].name,
+                self.apps[self.active_app_idx].mode_text(),
+            )
+        };
+        let info = StatusInfo {
+            app_name,
+            mode_text,
+            shift: ModifierStatus {
+                held: self.key_states[IcKey::Shift as usize].is_down,
+                latch: self.shift.latch(),
+            },
+            super_mod: ModifierStatus {
+                held: self.key_states[IcKey::Super as usize].is_down,
+                latch: self.super_mod.latch(),
+            },
+            battery_soc: platform.get_battery_soc(),
+            charging: platform.is_charging(),
+            battery_low: self.battery.level() != BatteryLevel::Normal,
+            macro_tag: if self.macros.is_recording() {
+                "REC"
+            } else if self.macros.is_paused() {
+                "WAIT"
+            } else if self.macros.is_playing() {
+                "PLAY"
+            } else {
+                ""
+            },
+            time_of_day: platform.time_of_day(),
+            palette: &self.palette,
+        };
+        draw_status_bar(platform, &info);
     }
 
     pub fn update(&mut self, platform: &mut dyn IcPlatform) {
-        for s in self.key_states.iter_mut() {
-            s.just_pressed = s.is_down && !s.was_down;
-            s.just_released = !s.is_down && s.was_down;
-            s.was_down = s.is_down;
-        }
-        let ctx = InputContext {
-            key_states: &self.key_states,
-        };
-        for i in 0..IcKey::COUNT {
-            if self.key_states[i].just_pressed {
-                if let Some(key) = IcKey::from_usize(i) {
-                    let mut input_consumed_by_shell: bool = false;
-                    if ctx.is_down(IcKey::Super) {
-                        match key {
-                            IcKey::Func1 => {
-                                self.active_app_idx = 0;
-                                input_consumed_by_shell = true;
-                            }
-                            IcKey::Func2 => {
-                                self.active_app_idx = 1;
-                                input_consumed_by_shell = true;
-                            }
-                            IcKey::Func3 => {
-                                self.active_app_idx = 2;
-                                input_consumed_by_shell = true;
+        if !self.prefs_loaded {
+            self.load_prefs(platform);
+        }
+        self.input_pending = false;
+        if let Some((key, down)) = self.macros.next_change() {
+            self.replaying = true;
+            if down {
+                self.key_down(key);
+            } else {
+                self.key_up(key);
+            }
+            self.replaying = false;
+        }
+        let now = platform.millis();
+        self.last_update_ms = now;
+        let mut events = [[None; 2]; IcKey::COUNT];
+        for (s, e) in self.key_states.iter_mut().zip(events.iter_mut()) {
+            *e = s.update(&self.key_timing, now);
+        }
+        if events.iter().flatten().any(Option::is_some) && self.power.on_activity(now) {
+            for (s, swallowed) in self.key_states.iter().zip(self.swallowed.iter_mut()) {
+                *swallowed |= s.is_down;
+            }
+        }
+        let ignored = self.swallowed;
+        for (i, s) in self.key_states.iter().enumerate() {
+            if ignored[i] {
+                events[i] = [None; 2];
+                self.swallowed[i] = s.is_down;
+            }
+        }
+        self.update_battery(platform);
+        self.update_power(now, platform);
+        if self.power_state == PowerState::Blank {
+            return;
+        }
+        if self.battery.level() == BatteryLevel::Critical {
+            let soc = platform.get_battery_soc();
+            draw_shutdown_screen(platform, soc, &self.palette);
+            return;
+        }
+        for (key, modifier) in [
+            (IcKey::Shift, &mut self.shift),
+            (IcKey::Super, &mut self.super_mod),
+        ] {
+            let state = &self.key_states[key as usize];
+            if ignored[key as usize] {
+                continue;
+            }
+            if state.just_pressed {
+                modifier.on_press();
+            } else if state.just_released {
+                modifier.on_release();
+            }
+        }
+        // copied / moved out so the shell can still switch apps while the
+        // context is alive
+        let key_states = self.key_states;
+        let prefs = core::mem::take(&mut self.prefs);
+        let keymaps = core::mem::take(&mut self.keymaps);
+        let mut overlays = core::mem::take(&mut self.overlays);
+        let palette = self.palette;
+        for (i, key_events) in events.iter().enumerate() {
+            let Some(key) = IcKey::from_usize(i) else {
+                continue;
+            };
+            let is_modifier = key == IcKey::Shift || key == IcKey::Super;
+            for kind in key_events.iter().flatten() {
+                let kind = *kind;
+                // an open dialog takes every key, shell shortcuts included
+                if overlays.has_dialog() {
+                    if let Some((app_idx, id, choice)) = overlays.dialog_key(key, kind) {
+                        let ctx = self.context(&key_states, &prefs, &keymaps, &palette, &overlays);
+                        self.apps[app_idx].on_dialog_result(id, choice, &ctx);
+                        overlays.adopt(app_idx);
+                        self.state_dirty[app_idx] = true;
+                        self.last_input_ms = now;
+                    }
+                    continue;
+                }
+                // any key closes the help, without the Super it was opened with
+                // staying latched
+                if self.help_open {
+                    if kind == KeyEventKind::Press {
+                        self.help_open = false;
+                        self.shift.clear();
+                        self.super_mod.clear();
+                    }
+                    continue;
+                }
+                if key == IcKey::Super && kind == KeyEventKind::LongPress {
+                    self.help_open = true;
+                    continue;
+                }
+                // rebuilt per event since a one-shot only covers the first key
+                let ctx = self.context(&key_states, &prefs, &keymaps, &palette, &overlays);
+                if kind == KeyEventKind::Press && !is_modifier {
+                    self.shift.on_other_key(key_states[IcKey::Shift as usize].is_down);
+                    self.super_mod.on_other_key(key_states[IcKey::Super as usize].is_down);
+                }
+                if ctx.is_shifted()
+                    && ctx.is_super()
+                    && kind == KeyEventKind::Press
+                    && self.macro_chord(key, platform, &overlays)
+                {
+                    continue;
+                }
+                let mut input_consumed_by_shell: bool = false;
+                if ctx.is_super() && kind == KeyEventKind::Press {
+                    match key {
+                        IcKey::Func1 | IcKey::Func2 | IcKey::Func3 | IcKey::Func4 => {
+                            let slot = key as usize - IcKey::Func1 as usize;
+                            if let Some(idx) = self.favourites[slot] {
+                                self.open_app(idx);
                             }
-                            IcKey::Func4 => {
-                                self.active_app_idx = 3;
-                                input_consumed_by_shell = true;
+                            input_consumed_by_shell = true;
+                        }
+                        IcKey::Func5 => {
+                            self.toggle_launcher();
+                            input_consumed_by_shell = true;
+                        }
+                        // the screen still shows the last frame, before the toast
+                        IcKey::Func6 => {
+                            if platform.save_screenshot() {
+                                overlays.toast("Screenshot saved");
+                            } else {
+                                overlays.toast("Screenshot failed");
                             }
-                            _ => {}
+                            input_consumed_by_shell = true;
                         }
+                        _ => {}
                     }
-                    if input_consumed_by_shell {
-                        continue;
+                }
+                if input_consumed_by_shell {
+                    continue;
+                }
+                if self.launcher_open {
+                    self.launcher_key(key, kind);
+                    continue;
+                }
+                // only keys that reach the app are recorded, replaying shell
+                // shortcuts would switch apps or take screenshots
+                if kind == KeyEventKind::Press && !is_modifier && self.macros.is_recording() {
+                    let step = MacroStep::Key {
+                        key,
+                        shifted: ctx.is_shifted(),
+                        sup: ctx.is_super(),
+                    };
+                    if !self.macros.record(step) {
+                        overlays.toast("Macro full");
                     }
-                    self.apps[self.active_app_idx].on_key(key, &ctx);
                 }
+                self.apps[self.active_app_idx].on_key_event(key, kind, &ctx);
+                overlays.adopt(self.active_app_idx);
+                self.state_dirty[self.active_app_idx] = true;
+                self.last_input_ms = now;
             }
         }
-        self.apps[self.active_app_idx].update(platform, &ctx);
-        self.draw_battery(platform);
+        for (i, app) in self.apps.iter_mut().enumerate() {
+            if self.launcher_open || i != self.active_app_idx {
+                app.background_tick(now);
+            }
+        }
+        let ctx = self.context(&key_states, &prefs, &keymaps, &palette, &overlays);
+        if self.launcher_open {
+            self.launcher
+                .draw(platform, &self.app_infos, &self.favourites, &palette);
+        } else {
+            self.apps[self.active_app_idx].update(platform, &ctx);
+            overlays.adopt(self.active_app_idx);
+        }
+        self.prefs = prefs;
+        self.keymaps = keymaps;
+        self.overlays = overlays;
+        if self.prefs.take_changed() {
+            self.apply_prefs();
+            self.save_prefs(platform);
+        }
+        if self.keymaps.take_changed() {
+            self.apply_keymaps();
+            self.save_keymaps(platform);
+        }
+        self.autosave(now, platform);
+        self.draw_status_bar(platform);
+        if self.help_open {
+            let title = if self.launcher_open {
+                "Apps"
+            } else {
+                self.app_infos[self.active_app_idx].name
+            };
+            draw_help(platform, &self.key_legend(), title, &self.palette);
+        }
+        self.overlays.update(now);
+        self.overlays.draw(platform, &self.palette);
+        self.ms_to_next_minute = platform
+            .time_of_day()
+            .map(|secs| (60 - secs % 60) * 1000);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::rc::Rc;
+    use core::cell::RefCell;
+    use core::fmt;
+    use rgb::RGB8;
+
+    // draws nothing, the clock only moves when the test says so
+    struct TestPlatform {
+        now: u64,
+    }
+
+    impl IcPlatform for TestPlatform {
+        fn draw_line(&mut self, _: IVec2, _: IVec2, _: RGB8, _: u32) {}
+        fn draw_rectangle(&mut self, _: IVec2, _: IVec2, _: RGB8, _: u32, _: Option<RGB8>) {}
+        fn draw_rectangle_rounded(&mut self, _: IVec2, _: IVec2, _: RGB8, _: u32, _: Option<RGB8>, _: u32) {}
+        fn draw_triangle(&mut self, _: IVec2, _: IVec2, _: IVec2, _: RGB8, _: u32, _: Option<RGB8>) {}
+        fn draw_circle(&mut self, _: IVec2, _: u32, _: RGB8, _: u32, _: Option<RGB8>) {}
+        fn draw_ellipse(&mut self, _: IVec2, _: IVec2, _: RGB8, _: u32, _: Option<RGB8>) {}
+        fn draw_arc(&mut self, _: IVec2, _: u32, _: f32, _: f32, _: RGB8, _: u32) {}
+        fn draw_polyline(&mut self, _: &[IVec2], _: RGB8, _: u32) {}
+        fn draw_polygon(&mut self, _: &[IVec2], _: RGB8, _: u32, _: Option<RGB8>) {}
+        fn draw_pixel(&mut self, _: IVec2, _: RGB8) {}
+        fn draw_pixels(&mut self, _: IVec2, _: u32, _: &[RGB8]) {}
+        fn draw_string(&mut self, _: &str, _: IVec2, _: u32, _: RGB8) {}
+        fn draw_string_f(&mut self, _: fmt::Arguments, _: IVec2, _: u32, _: RGB8) {}
+        fn clear(&mut self, _: RGB8) {}
+        fn log(&mut self, _: fmt::Arguments) {}
+        fn millis(&self) -> u64 {
+            self.now
+        }
+        fn get_battery_soc(&self) -> i32 {
+            100
+        }
+    }
+
+    // remembers every key it was given, modifiers aside
+    struct KeyLog(Rc<RefCell<Vec<IcKey>>>);
+
+    impl IcApp for KeyLog {
+        fn on_enter(&mut self) {}
+        fn on_key(&mut self, key: IcKey, _ctx: &InputContext) {
+            if !matches!(key, IcKey::Shift | IcKey::Super) {
+                self.0.borrow_mut().push(key);
+            }
+        }
+        fn update(&mut self, _platform: &mut dyn IcPlatform, _ctx: &InputContext) {}
+    }
+
+    struct HookLog(Rc<RefCell<Vec<&'static str>>>);
+
+    impl IcApp for HookLog {
+        fn on_enter(&mut self) {
+            self.0.borrow_mut().push("enter");
+        }
+        fn on_exit(&mut self) {
+            self.0.borrow_mut().push("exit");
+        }
+        fn on_suspend(&mut self) {
+            self.0.borrow_mut().push("suspend");
+        }
+        fn on_resume(&mut self) {
+            self.0.borrow_mut().push("resume");
+        }
+        fn on_key(&mut self, _key: IcKey, _ctx: &InputContext) {}
+        fn update(&mut self, _platform: &mut dyn IcPlatform, _ctx: &InputContext) {}
+    }
+
+    fn step(shell: &mut IcShell, platform: &mut TestPlatform) {
+        platform.now += 20;
+        shell.update(platform);
+    }
+
+    fn tap(shell: &mut IcShell, platform: &mut TestPlatform, keys: &[IcKey]) {
+        for key in keys {
+            shell.key_down(*key);
+            step(shell, platform);
+        }
+        for key in keys.iter().rev() {
+            shell.key_up(*key);
+            step(shell, platform);
+        }
+    }
+
+    #[test]
+    fn macro_records_and_plays_back_through_update() {
+        let mut platform = TestPlatform { now: 1000 };
+        let mut shell = IcShell::new();
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let info = AppInfo {
+            name: "Log",
+            icon: "log",
+            color: rgb8_hex(0),
+        };
+        shell.active_app_idx = shell.register_app(info, Box::new(KeyLog(log.clone())));
+        step(&mut shell, &mut platform);
+
+        let chord = |key| [IcKey::Shift, IcKey::Super, key];
+        tap(&mut shell, &mut platform, &chord(IcKey::Func6));
+        assert!(shell.macros.is_recording());
+        tap(&mut shell, &mut platform, &[IcKey::Num1]);
+        // a shell shortcut in the middle is not part of the macro
+        tap(&mut shell, &mut platform, &[IcKey::Super, IcKey::Func6]);
+        tap(&mut shell, &mut platform, &[IcKey::Num2]);
+        tap(&mut shell, &mut platform, &chord(IcKey::Func1));
+        assert!(!shell.macros.is_recording());
+        let recorded: Vec<IcKey> = shell
+            .macros
+            .steps(0)
+            .iter()
+            .filter_map(|s| match s {
+                MacroStep::Key { key, .. } => Some(*key),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(recorded, [IcKey::Num1, IcKey::Num2]);
+        // the chords themselves never reach the app
+        assert_eq!(*log.borrow(), [IcKey::Num1, IcKey::Num2]);
+
+        log.borrow_mut().clear();
+        tap(&mut shell, &mut platform, &chord(IcKey::Func1));
+        assert!(shell.macros.is_playing());
+        while shell.frame_request() == FrameRequest::Continuous {
+            step(&mut shell, &mut platform);
+        }
+        assert!(!shell.macros.is_playing());
+        assert_eq!(*log.borrow(), [IcKey::Num1, IcKey::Num2]);
+        assert!(shell.key_states.iter().all(|s| !s.is_down));
+    }
+
+    #[test]
+    fn blanking_behind_the_launcher_does_not_suspend_the_app() {
+        let mut platform = TestPlatform { now: 1000 };
+        let mut shell = IcShell::new();
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let info = AppInfo {
+            name: "Hooks",
+            icon: "hooks",
+            color: rgb8_hex(0),
+        };
+        shell.active_app_idx = shell.register_app(info, Box::new(HookLog(log.clone())));
+        step(&mut shell, &mut platform);
+        let blank_and_wake = |shell: &mut IcShell, platform: &mut TestPlatform| {
+            platform.now += shell.power.policy.blank_after_ms;
+            step(shell, platform);
+            assert_eq!(shell.power_state, PowerState::Blank);
+            tap(shell, platform, &[IcKey::Num5]);
+            assert_ne!(shell.power_state, PowerState::Blank);
+        };
+
+        blank_and_wake(&mut shell, &mut platform);
+        assert_eq!(*log.borrow(), ["suspend", "resume"]);
+
+        log.borrow_mut().clear();
+        tap(&mut shell, &mut platform, &[IcKey::Super, IcKey::Func5]);
+        assert!(shell.launcher_open);
+        blank_and_wake(&mut shell, &mut platform);
+        tap(&mut shell, &mut platform, &[IcKey::Super, IcKey::Func5]);
+        assert!(!shell.launcher_open);
+        assert_eq!(*log.borrow(), ["exit", "enter"]);
     }
 }
@@ -0,0 +1,235 @@
+use alloc::vec::Vec;
+
+// Compact little endian encoding for app state blobs. There are no field
+// tags, apps read back exactly what they wrote and bump their state version
+// when that changes. Lengths are LEB128 varints since most are tiny.
+#[derive(Default)]
+pub struct StateWriter {
+    buf: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf
+    }
+
+    pub fn u8(&mut self, v: u8) {
+        self.buf.push(v);
+    }
+
+    pub fn bool(&mut self, v: bool) {
+        self.buf.push(v as u8);
+    }
+
+    pub fn varint(&mut self, mut v: u32) {
+        while v >= 0x80 {
+            self.buf.push(v as u8 | 0x80);
+            v >>= 7;
+        }
+        self.buf.push(v as u8);
+    }
+
+    pub fn i32(&mut self, v: i32) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub fn f32(&mut self, v: f32) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub fn bytes(&mut self, v: &[u8]) {
+        self.varint(v.len() as u32);
+        self.buf.extend_from_slice(v);
+    }
+}
+
+// Every read returns None once the data runs out or doesn't make sense, so
+// restore code can use `?` and give up on a bad blob.
+pub struct StateReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.data.len() < n {
+            return None;
+        }
+        let (head, tail) = self.data.split_at(n);
+        self.data = tail;
+        Some(head)
+    }
+
+    pub fn u8(&mut self) -> Option<u8> {
+        Some(self.take(1)?[0])
+    }
+
+    pub fn bool(&mut self) -> Option<bool> {
+        match self.u8()? {
+            0 => Some(false),
+            1 => Some(true),
+            _ => None,
+        }
+    }
+
+    pub fn varint(&mut self) -> Option<u32> {
+        let mut v: u32 = 0;
+        for shift in (0..35).step_by(7) {
+            let b = self.u8()?;
+            v |= ((b & 0x7f) as u32).checked_shl(shift)?;
+            if b & 0x80 == 0 {
+                return Some(v);
+            }
+        }
+        None
+    }
+
+    pub fn i32(&mut self) -> Option<i32> {
+        let b = self.take(4)?;
+        Some(i32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    pub fn f32(&mut self) -> Option<f32> {
+        let b = self.take(4)?;
+        Some(f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    pub fn bytes(&mut self) -> Option<&'a [u8]> {
+        let len = self.varint()? as usize;
+        self.take(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::{IcApp, InputContext, Viewport};
+    use crate::apps::{AspectRatioCalculator, Calculator, RangeMapperCalculator};
+    use crate::input::{IcKey, KeyState};
+    use crate::keymap::Keymaps;
+    use crate::overlay::Overlays;
+    use crate::prefs::Prefs;
+    use crate::theme::Palette;
+    use alloc::boxed::Box;
+    use glam::IVec2;
+
+    #[test]
+    fn primitives_round_trip() {
+        let mut w = StateWriter::new();
+        w.u8(7);
+        w.bool(true);
+        w.varint(300);
+        w.i32(-5);
+        w.f32(1.5);
+        w.bytes(b"1+2");
+        let mut r = StateReader::new(w.as_bytes());
+        assert_eq!(r.u8(), Some(7));
+        assert_eq!(r.bool(), Some(true));
+        assert_eq!(r.varint(), Some(300));
+        assert_eq!(r.i32(), Some(-5));
+        assert_eq!(r.f32(), Some(1.5));
+        assert_eq!(r.bytes(), Some(&b"1+2"[..]));
+        assert!(r.is_empty());
+        assert_eq!(r.u8(), None);
+        // a length pointing past the end
+        assert_eq!(StateReader::new(&[5, 1, 2]).bytes(), None);
+    }
+
+    fn type_keys(app: &mut dyn IcApp, keys: &[IcKey]) {
+        let key_states = [KeyState::default(); IcKey::COUNT];
+        let prefs = Prefs::new();
+        let ctx = InputContext {
+            key_states: &key_states,
+            shift_latched: false,
+            super_latched: false,
+            viewport: Viewport {
+                pos: IVec2::ZERO,
+                size: IVec2::new(320, 240),
+            },
+            prefs: &prefs,
+            keymaps: &Keymaps::new(),
+            palette: &Palette::from_index(0),
+            overlays: &Overlays::new(),
+        };
+        for key in keys {
+            app.on_key(*key, &ctx);
+        }
+    }
+
+    // Saving, restoring into a fresh app and saving again must give the same
+    // bytes, and the restored app must not be in its initial state.
+    fn assert_round_trip(fresh: impl Fn() -> Box<dyn IcApp>, keys: &[IcKey]) {
+        let mut initial = StateWriter::new();
+        fresh().save_state(&mut initial);
+        let mut app = fresh();
+        type_keys(app.as_mut(), keys);
+        let mut saved = StateWriter::new();
+        app.save_state(&mut saved);
+        assert_ne!(saved.as_bytes(), initial.as_bytes());
+
+        let mut restored = fresh();
+        let mut r = StateReader::new(saved.as_bytes());
+        assert!(
+            restored
+                .restore_state(app.state_version(), &mut r)
+                .is_some()
+        );
+        assert!(r.is_empty());
+        let mut resaved = StateWriter::new();
+        restored.save_state(&mut resaved);
+        assert_eq!(resaved.as_bytes(), saved.as_bytes());
+
+        // other versions and truncated blobs are refused
+        let mut other = fresh();
+        let mut r = StateReader::new(saved.as_bytes());
+        assert!(
+            other
+                .restore_state(app.state_version() + 1, &mut r)
+                .is_none()
+        );
+        let mut r = StateReader::new(&saved.as_bytes()[..saved.as_bytes().len() - 1]);
+        assert!(other.restore_state(app.state_version(), &mut r).is_none());
+    }
+
+    #[test]
+    fn calculator_round_trip() {
+        // 1-2 enter, then 3 left in the editor
+        assert_round_trip(
+            || Box::new(Calculator::new()),
+            &[
+                IcKey::Num1,
+                IcKey::Func4,
+                IcKey::Num2,
+                IcKey::Func6,
+                IcKey::Num3,
+            ],
+        );
+    }
+
+    #[test]
+    fn aspect_round_trip() {
+        assert_round_trip(
+            || Box::new(AspectRatioCalculator::new()),
+            &[IcKey::Num1, IcKey::Num6, IcKey::Num2, IcKey::Num9],
+        );
+    }
+
+    #[test]
+    fn range_mapper_round_trip() {
+        assert_round_trip(
+            || Box::new(RangeMapperCalculator::new()),
+            &[IcKey::Num5, IcKey::Num0, IcKey::Func6, IcKey::Num1],
+        );
+    }
+}
@@ -0,0 +1,157 @@
+use crate::font::Align;
+use crate::input::ModifierLatch;
+use crate::platform::IcPlatform;
+use crate::text::{draw_text, draw_text_f, text_to_pos};
+use crate::theme::Palette;
+use crate::ui::SCREEN_SIZE;
+use alloc::format;
+use glam::IVec2;
+use rgb::RGB8;
+
+pub const STATUS_BAR_HEIGHT: i32 = 22;
+
+#[derive(Clone, Copy)]
+pub struct ModifierStatus {
+    pub held: bool,
+    pub latch: ModifierLatch,
+}
+
+// Everything the bar shows, gathered by the shell each frame.
+pub struct StatusInfo<'a> {
+    pub app_name: &'a str,
+    // short app state like "RAD" or "32-bit", empty for none
+    pub mode_text: &'a str,
+    pub shift: ModifierStatus,
+    pub super_mod: ModifierStatus,
+    pub battery_soc: i32,
+    pub charging: bool,
+    // drawn in the error colour
+    pub battery_low: bool,
+    // macro recording or playback, drawn as a tag left of the modifiers
+    pub macro_tag: &'a str,
+    // seconds since midnight, None when the platform does not know the time
+    pub time_of_day: Option<u32>,
+    pub palette: &'a Palette,
+}
+
+const BATTERY_X: i32 = 282;
+const MODIFIERS_RIGHT: i32 = 266;
+
+pub fn draw_status_bar(platform: &mut dyn IcPlatform, info: &StatusInfo) {
+    let palette = info.palette;
+    platform.draw_rectangle(
+        IVec2::new(0, 0),
+        IVec2::new(SCREEN_SIZE.x, STATUS_BAR_HEIGHT - 1),
+        palette.background,
+        0,
+        Some(palette.background),
+    );
+    platform.draw_line(
+        IVec2::new(0, STATUS_BAR_HEIGHT - 1),
+        IVec2::new(SCREEN_SIZE.x, STATUS_BAR_HEIGHT - 1),
+        palette.dim,
+        1,
+    );
+    draw_text(platform, info.app_name, 4.0, 4.0, 2.0, palette.text);
+    if !info.mode_text.is_empty() {
+        let x = text_to_pos(info.app_name, 4.0, 2.0, info.app_name.len()) + 8.0;
+        draw_text(platform, info.mode_text, x, 7.0, 1.5, palette.accent);
+    }
+    let mut modifiers_left = draw_modifiers(platform, info);
+    if !info.macro_tag.is_empty() {
+        let w = text_to_pos(info.macro_tag, 0.0, 1.5, info.macro_tag.len()) as i32 + 8;
+        modifiers_left -= w + 2;
+        let pos = IVec2::new(modifiers_left, 3);
+        platform.draw_rectangle(pos, pos + IVec2::new(w, 17), palette.error, 0, Some(palette.error));
+        draw_text(platform, info.macro_tag, (modifiers_left + 4) as f32, 6.0, 1.5, palette.background);
+    }
+    if let Some(secs) = info.time_of_day {
+        let hours = secs / 3600 % 24;
+        let minutes = secs / 60 % 60;
+        // fixed width so the clock does not jump around as digits change
+        let clock_w = text_to_pos("00:00", 0.0, 2.0, 5);
+        draw_text_f(
+            platform,
+            format_args!("{:02}:{:02}", hours, minutes),
+            modifiers_left as f32 - clock_w - 6.0,
+            4.0,
+            2.0,
+            palette.text,
+        );
+    }
+    if info.charging {
+        draw_charging_bolt(platform, IVec2::new(BATTERY_X - 10, 3), palette.accent);
+    }
+    draw_battery(platform, info.battery_soc, info.battery_low, palette);
+}
+
+// Small tags left of the battery: outlined while held, yellow for a
+// one-shot, filled when locked. Returns the left edge of the tag area.
+fn draw_modifiers(platform: &mut dyn IcPlatform, info: &StatusInfo) -> i32 {
+    let palette = info.palette;
+    let mut x = MODIFIERS_RIGHT - 24;
+    for (modifier, label) in [(info.super_mod, "Su"), (info.shift, "Sh")] {
+        let (border, fill, text) = match modifier.latch {
+            ModifierLatch::Locked => (palette.text, Some(palette.text), palette.background),
+            ModifierLatch::OneShot => (palette.accent, None, palette.accent),
+            ModifierLatch::Off if modifier.held => (palette.text, None, palette.text),
+            ModifierLatch::Off => {
+                x -= 26;
+                continue;
+            }
+        };
+        let pos = IVec2::new(x, 3);
+        platform.draw_rectangle(pos, pos + IVec2::new(24, 17), border, 1, fill);
+        draw_text(platform, label, (x + 4) as f32, 6.0, 1.5, text);
+        x -= 26;
+    }
+    x + 26
+}
+
+fn draw_charging_bolt(platform: &mut dyn IcPlatform, pos: IVec2, color: RGB8) {
+    platform.draw_triangle(
+        pos + IVec2::new(5, 0),
+        pos + IVec2::new(0, 9),
+        pos + IVec2::new(5, 9),
+        color,
+        0,
+        Some(color),
+    );
+    platform.draw_triangle(
+        pos + IVec2::new(3, 8),
+        pos + IVec2::new(8, 8),
+        pos + IVec2::new(3, 17),
+        color,
+        0,
+        Some(color),
+    );
+}
+
+fn draw_battery(platform: &mut dyn IcPlatform, batt_percentage: i32, low: bool, palette: &Palette) {
+    let batt_icon_pos = IVec2::new(BATTERY_X, 3);
+    let batt_icon_w = 34;
+    let batt_icon_h = 17;
+    let fill_w = (batt_percentage * batt_icon_w) / 100;
+    platform.draw_rectangle(
+        batt_icon_pos,
+        batt_icon_pos + IVec2::new(batt_icon_w, batt_icon_h),
+        palette.dim,
+        0,
+        Some(palette.dim),
+    );
+    let fill = if low { palette.error } else { palette.text };
+    platform.draw_rectangle(
+        batt_icon_pos,
+        batt_icon_pos + IVec2::new(fill_w, batt_icon_h),
+        fill,
+        0,
+        Some(fill),
+    );
+    platform.draw_string_aligned(
+        &format!("{}", batt_percentage),
+        batt_icon_pos + IVec2::new(batt_icon_w / 2, -1),
+        4,
+        Align::Center,
+        palette.background,
+    );
+}
@@ -0,0 +1,108 @@
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+// Limits every backend has to support. They are small so a flash backend can
+// keep each blob in a single sector next to its header.
+pub const MAX_NAME_LEN: usize = 16;
+pub const MAX_BLOB_LEN: usize = 3072;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StorageError {
+    NotFound,
+    // only ascii letters, digits, '_' and '-' are allowed
+    InvalidName,
+    // name longer than MAX_NAME_LEN or data longer than MAX_BLOB_LEN
+    TooLarge,
+    // the backend ran out of space
+    Full,
+    // the backend failed or found a corrupt blob
+    Io,
+}
+
+// Small named blobs that survive power cycles. Each blob is written whole
+// together with the format version of its contents, so readers can tell an
+// old layout from a new one and the backend never has to patch data in place.
+// Backends have to keep the previous copy readable until a write completes,
+// a power cut mid-write must not lose both.
+pub trait Storage {
+    // Replaces buf with the blob and returns its version.
+    fn read(&mut self, name: &str, buf: &mut Vec<u8>) -> Result<u16, StorageError>;
+    fn write(&mut self, name: &str, version: u16, data: &[u8]) -> Result<(), StorageError>;
+    // removing a missing blob is not an error
+    fn remove(&mut self, name: &str) -> Result<(), StorageError>;
+}
+
+// For backends to call at the start of write(). Names are also limited to
+// characters that are safe in file names.
+pub fn check_limits(name: &str, data: &[u8]) -> Result<(), StorageError> {
+    if name.is_empty() || name.len() > MAX_NAME_LEN || data.len() > MAX_BLOB_LEN {
+        return Err(StorageError::TooLarge);
+    }
+    let valid_char = |c: u8| c.is_ascii_alphanumeric() || c == b'_' || c == b'-';
+    if !name.bytes().all(valid_char) {
+        return Err(StorageError::InvalidName);
+    }
+    Ok(())
+}
+
+// Keeps everything in RAM, for tests and platforms without storage.
+#[derive(Default)]
+pub struct MemoryStorage {
+    blobs: BTreeMap<String, (u16, Vec<u8>)>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn read(&mut self, name: &str, buf: &mut Vec<u8>) -> Result<u16, StorageError> {
+        let (version, data) = self.blobs.get(name).ok_or(StorageError::NotFound)?;
+        buf.clear();
+        buf.extend_from_slice(data);
+        Ok(*version)
+    }
+
+    fn write(&mut self, name: &str, version: u16, data: &[u8]) -> Result<(), StorageError> {
+        check_limits(name, data)?;
+        self.blobs
+            .insert(String::from(name), (version, data.to_vec()));
+        Ok(())
+    }
+
+    fn remove(&mut self, name: &str) -> Result<(), StorageError> {
+        self.blobs.remove(name);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memory_storage_keeps_versions() {
+        let mut storage = MemoryStorage::new();
+        let mut buf = Vec::new();
+        assert_eq!(storage.read("prefs", &mut buf), Err(StorageError::NotFound));
+        storage.write("prefs", 3, &[1, 2, 3]).unwrap();
+        storage.write("prefs", 4, &[5]).unwrap();
+        assert_eq!(storage.read("prefs", &mut buf), Ok(4));
+        assert_eq!(buf, [5]);
+        assert_eq!(
+            storage.write("a_name_that_is_too_long", 1, &[]),
+            Err(StorageError::TooLarge)
+        );
+        assert_eq!(storage.write("a/b", 1, &[]), Err(StorageError::InvalidName));
+        assert_eq!(
+            storage.write("big", 1, &[0; MAX_BLOB_LEN + 1]),
+            Err(StorageError::TooLarge)
+        );
+        storage.remove("prefs").unwrap();
+        storage.remove("prefs").unwrap();
+        assert_eq!(storage.read("prefs", &mut buf), Err(StorageError::NotFound));
+    }
+}
@@ -0,0 +1,178 @@
+use crate::platform::rgb8_hex;
+use crate::ui::{DialogStyle, FieldStyle, ListStyle, MenuStyle};
+use rgb::RGB8;
+
+// Colours by what they are used for. The shell hands the active one to apps
+// through InputContext::palette, apps should not need literal colours for
+// anything but pictures.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Palette {
+    pub background: RGB8,
+    pub text: RGB8,
+    // results, cursors and whatever else should stand out
+    pub accent: RGB8,
+    pub error: RGB8,
+    // behind selected rows and focused fields, text is drawn on top of it
+    pub selection: RGB8,
+    // labels, old values and separators
+    pub dim: RGB8,
+    // names on the launcher tiles, which keep their app colour in every theme
+    pub tile_text: RGB8,
+}
+
+pub struct Theme {
+    pub name: &'static str,
+    pub palette: Palette,
+}
+
+pub const THEMES: &[Theme] = &[
+    Theme {
+        name: "Dark",
+        palette: Palette {
+            background: rgb8_hex(0x101018),
+            text: rgb8_hex(0xffffff),
+            accent: rgb8_hex(0xffff00),
+            error: rgb8_hex(0xff5555),
+            selection: rgb8_hex(0x2040c0),
+            dim: rgb8_hex(0x808080),
+            tile_text: rgb8_hex(0xffffff),
+        },
+    },
+    Theme {
+        name: "Light",
+        palette: Palette {
+            background: rgb8_hex(0xf4f0e6),
+            text: rgb8_hex(0x101010),
+            accent: rgb8_hex(0x0050c0),
+            error: rgb8_hex(0xc00000),
+            selection: rgb8_hex(0xa8c8ff),
+            dim: rgb8_hex(0x707070),
+            tile_text: rgb8_hex(0xffffff),
+        },
+    },
+    Theme {
+        name: "Amber",
+        palette: Palette {
+            background: rgb8_hex(0x140c00),
+            text: rgb8_hex(0xffb000),
+            accent: rgb8_hex(0xffe080),
+            error: rgb8_hex(0xff4020),
+            selection: rgb8_hex(0x604000),
+            dim: rgb8_hex(0x906000),
+            tile_text: rgb8_hex(0xffe8c0),
+        },
+    },
+    // for sunlight: pure black and white, saturated accents, no greys
+    Theme {
+        name: "High contrast",
+        palette: Palette {
+            background: rgb8_hex(0x000000),
+            text: rgb8_hex(0xffffff),
+            accent: rgb8_hex(0xffff00),
+            error: rgb8_hex(0xff0000),
+            selection: rgb8_hex(0x0000ff),
+            dim: rgb8_hex(0x00ffff),
+            tile_text: rgb8_hex(0xffffff),
+        },
+    },
+];
+
+// same order as THEMES, for the settings choice
+pub const THEME_NAMES: &[&str] = &["Dark", "Light", "Amber", "High contrast"];
+
+impl Palette {
+    pub fn from_index(idx: usize) -> Palette {
+        THEMES.get(idx).unwrap_or(&THEMES[0]).palette
+    }
+
+    pub fn list_style(&self) -> ListStyle {
+        ListStyle {
+            background: None,
+            text: self.text,
+            selection: self.selection,
+            selection_text: self.text,
+            ..ListStyle::default()
+        }
+    }
+
+    pub fn menu_style(&self) -> MenuStyle {
+        MenuStyle {
+            background: self.background,
+            border: self.text,
+            title: self.accent,
+            list: self.list_style(),
+        }
+    }
+
+    pub fn dialog_style(&self) -> DialogStyle {
+        DialogStyle {
+            background: self.background,
+            border: self.text,
+            title: self.accent,
+            text: self.text,
+            button: self.background,
+            button_border: self.dim,
+            button_selected: self.selection,
+            button_text: self.text,
+        }
+    }
+
+    // Recolours a field style, keeping its sizes and borders.
+    pub fn field_style(&self, style: FieldStyle) -> FieldStyle {
+        FieldStyle {
+            fill: style.fill.map(|_| self.background),
+            fill_focused: style.fill_focused.map(|_| self.selection),
+            text: self.text,
+            text_focused: self.text,
+            border: self.dim,
+            highlight: style.highlight.map(|_| self.accent),
+            cursor: self.accent,
+            ..style
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_match_themes() {
+        assert_eq!(THEMES.len(), THEME_NAMES.len());
+        for (theme, name) in THEMES.iter().zip(THEME_NAMES) {
+            assert_eq!(theme.name, *name);
+        }
+    }
+
+    // stale results, battery gauges and rebound keys are told apart by dim
+    #[test]
+    fn dim_differs_from_text_and_background() {
+        for theme in THEMES {
+            let p = theme.palette;
+            assert_ne!(p.dim, p.text, "{}", theme.name);
+            assert_ne!(p.dim, p.background, "{}", theme.name);
+        }
+    }
+
+    // contrast ratio as in WCAG, with gamma 2 standing in for the sRGB curve
+    fn contrast(a: RGB8, b: RGB8) -> f32 {
+        let luminance = |c: RGB8| {
+            let ch = |v: u8| (v as f32 / 255.0) * (v as f32 / 255.0);
+            0.2126 * ch(c.r) + 0.7152 * ch(c.g) + 0.0722 * ch(c.b)
+        };
+        let (la, lb) = (luminance(a), luminance(b));
+        (la.max(lb) + 0.05) / (la.min(lb) + 0.05)
+    }
+
+    #[test]
+    fn dialog_buttons_are_readable() {
+        for theme in THEMES {
+            let style = theme.palette.dialog_style();
+            for fill in [style.button, style.button_selected] {
+                assert!(contrast(style.button_text, fill) >= 4.5, "{}", theme.name);
+            }
+            assert!(contrast(style.text, style.background) >= 4.5, "{}", theme.name);
+            assert_ne!(style.button_border, style.button, "{}", theme.name);
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use crate::platform::IcPlatform;
+use crate::text::{draw_text, text_to_pos};
+use crate::ui::{NavDir, SCREEN_SIZE};
+use alloc::string::String;
+use glam::IVec2;
+use rgb::RGB8;
+
+#[derive(Clone, Copy)]
+pub struct DialogStyle {
+    pub background: RGB8,
+    pub border: RGB8,
+    pub title: RGB8,
+    pub text: RGB8,
+    pub button: RGB8,
+    // outline of the unselected buttons
+    pub button_border: RGB8,
+    pub button_selected: RGB8,
+    pub button_text: RGB8,
+}
+
+impl Default for DialogStyle {
+    fn default() -> Self {
+        Self {
+            background: RGB8::new(0x20, 0x20, 0x20),
+            border: RGB8::new(0xff, 0xff, 0xff),
+            title: RGB8::new(0xff, 0xff, 0x00),
+            text: RGB8::new(0xff, 0xff, 0xff),
+            button: RGB8::new(0x50, 0x50, 0x50),
+            button_border: RGB8::new(0x50, 0x50, 0x50),
+            button_selected: RGB8::new(0, 0, 0xff),
+            button_text: RGB8::new(0xff, 0xff, 0xff),
+        }
+    }
+}
+
+// Box with a title, a message and a row of buttons. Left and right pick a
+// button, the owner decides which key confirms.
+pub struct Dialog {
+    pub title: String,
+    pub message: String,
+    pub buttons: &'static [&'static str],
+    pub style: DialogStyle,
+    selected: usize,
+}
+
+impl Dialog {
+    pub const YES_NO: &'static [&'static str] = &["Yes", "No"];
+    pub const OK: &'static [&'static str] = &["OK"];
+
+    pub fn new(
+        title: &str,
+        message: &str,
+        buttons: &'static [&'static str],
+        style: DialogStyle,
+    ) -> Self {
+        Self {
+            title: String::from(title),
+            message: String::from(message),
+            buttons,
+            style,
+            selected: 0,
+        }
+    }
+
+    pub fn confirm(title: &str, message: &str, style: DialogStyle) -> Self {
+        Self::new(title, message, Self::YES_NO, style)
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    pub fn navigate(&mut self, dir: NavDir) -> bool {
+        let n = self.buttons.len();
+        if n == 0 {
+            return false;
+        }
+        match dir {
+            NavDir::Left => self.selected = (self.selected + n - 1) % n,
+            NavDir::Right => self.selected = (self.selected + 1) % n,
+            NavDir::Up | NavDir::Down => return false,
+        }
+        true
+    }
+
+    pub fn draw(&self, platform: &mut dyn IcPlatform) {
+        let size = IVec2::new(260, 110);
+        let pos = (SCREEN_SIZE - size) / 2;
+        platform.draw_rectangle(
+            pos,
+            pos + size,
+            self.style.border,
+            2,
+            Some(self.style.background),
+        );
+        draw_text(
+            platform,
+            &self.title,
+            (pos.x + 8) as f32,
+            (pos.y + 8) as f32,
+            2.0,
+            self.style.title,
+        );
+        draw_text(
+            platform,
+            &self.message,
+            (pos.x + 8) as f32,
+            (pos.y + 36) as f32,
+            2.0,
+            self.style.text,
+        );
+        let button_h = 26;
+        let button_gap = 8;
+        let button_y = pos.y + size.y - button_h - 8;
+        let mut button_x = pos.x + size.x - 8;
+        for (i, label) in self.buttons.iter().enumerate().rev() {
+            let label_w = text_to_pos(label, 0.0, 2.0, label.len()) as i32;
+            let button_w = label_w.max(40) + 12;
+            button_x -= button_w;
+            let (fill, border) = if i == self.selected {
+                (self.style.button_selected, self.style.button_selected)
+            } else {
+                (self.style.button, self.style.button_border)
+            };
+            platform.draw_rectangle(
+                IVec2::new(button_x, button_y),
+                IVec2::new(button_x + button_w, button_y + button_h),
+                border,
+                1,
+                Some(fill),
+            );
+            draw_text(
+                platform,
+                label,
+                (button_x + (button_w - label_w) / 2) as f32,
+                (button_y + 5) as f32,
+                2.0,
+                self.style.button_text,
+            );
+            button_x -= button_gap;
+        }
+    }
+}
@@ -0,0 +1,175 @@
+use crate::platform::IcPlatform;
+use crate::text::{draw_text, draw_text_f, text_to_pos};
+use crate::ui::LineBuffer;
+use glam::IVec2;
+use num_traits::clamp_max;
+use rgb::RGB8;
+
+#[derive(Clone, Copy)]
+pub struct FieldStyle {
+    pub fill: Option<RGB8>,
+    pub fill_focused: Option<RGB8>,
+    pub text: RGB8,
+    pub text_focused: RGB8,
+    pub border: RGB8,
+    pub border_width: u32,
+    // outline drawn around the field while it has focus
+    pub highlight: Option<RGB8>,
+    pub cursor: RGB8,
+    // space between the edge of the box and the text
+    pub padding: i32,
+    pub text_scale: f32,
+    // text longer than this gets drawn with small_text_scale so it fits
+    pub shrink_after: usize,
+    pub small_text_scale: f32,
+}
+
+impl Default for FieldStyle {
+    fn default() -> Self {
+        Self {
+            fill: Some(RGB8::new(0xff, 0xff, 0xff)),
+            fill_focused: Some(RGB8::new(0xff, 0xff, 0xff)),
+            text: RGB8::new(0, 0, 0),
+            text_focused: RGB8::new(0, 0, 0),
+            border: RGB8::new(0, 0, 0),
+            border_width: 0,
+            highlight: None,
+            cursor: RGB8::new(0xff, 0x00, 0x44),
+            padding: 4,
+            text_scale: 4.0,
+            shrink_after: usize::MAX,
+            small_text_scale: 2.0,
+        }
+    }
+}
+
+impl FieldStyle {
+    fn scale_for(&self, len: usize) -> f32 {
+        if len > self.shrink_after {
+            self.small_text_scale
+        } else {
+            self.text_scale
+        }
+    }
+}
+
+fn draw_frame(
+    platform: &mut dyn IcPlatform,
+    pos: IVec2,
+    size: IVec2,
+    style: &FieldStyle,
+    has_focus: bool,
+) {
+    let fill = if has_focus {
+        style.fill_focused
+    } else {
+        style.fill
+    };
+    if fill.is_some() || style.border_width > 0 {
+        platform.draw_rectangle(pos, pos + size, style.border, style.border_width, fill);
+    }
+}
+
+fn draw_highlight(platform: &mut dyn IcPlatform, pos: IVec2, size: IVec2, style: &FieldStyle) {
+    if let Some(c) = style.highlight {
+        platform.draw_rectangle(pos, pos + size, c, 3, None);
+    }
+}
+
+// Editable text box, the cursor is only drawn while it has focus.
+pub struct TextField<const N: usize> {
+    pub buffer: LineBuffer<N>,
+    pub pos: IVec2,
+    pub size: IVec2,
+    pub style: FieldStyle,
+}
+
+impl<const N: usize> TextField<N> {
+    pub fn new(pos: IVec2, size: IVec2, style: FieldStyle) -> Self {
+        Self {
+            buffer: LineBuffer::default(),
+            pos,
+            size,
+            style,
+        }
+    }
+
+    pub fn draw(&self, platform: &mut dyn IcPlatform, has_focus: bool) {
+        draw_frame(platform, self.pos, self.size, &self.style, has_focus);
+        let display_text = self.buffer.as_str();
+        let text_scale = self.style.scale_for(self.buffer.len);
+        let text_x = (self.pos.x + self.style.padding) as f32;
+        let text_y = (self.pos.y + self.style.padding) as f32;
+        let text_color = if has_focus {
+            self.style.text_focused
+        } else {
+            self.style.text
+        };
+        draw_text(platform, display_text, text_x, text_y, text_scale, text_color);
+        if has_focus {
+            let cursor_x = text_to_pos(display_text, text_x, text_scale, self.buffer.cursor);
+            let cursor_x = cursor_x.max(text_x + 2.0) as i32;
+            platform.draw_line(
+                IVec2::new(cursor_x - 3, text_y as i32 - 5),
+                IVec2::new(cursor_x - 3, (text_y + 8.0 * text_scale) as i32),
+                self.style.cursor,
+                2,
+            );
+            draw_highlight(platform, self.pos, self.size, &self.style);
+        }
+    }
+}
+
+// Box holding a non-negative integer typed in one digit at a time.
+pub struct NumField {
+    pub value: i32,
+    pub pos: IVec2,
+    pub size: IVec2,
+    pub style: FieldStyle,
+}
+
+impl NumField {
+    pub fn new(pos: IVec2, size: IVec2, style: FieldStyle) -> Self {
+        Self {
+            value: 0,
+            pos,
+            size,
+            style,
+        }
+    }
+
+    pub fn append_digit(&mut self, digit: u32) {
+        self.value = self
+            .value
+            .saturating_mul(10)
+            .saturating_add(clamp_max(digit as i32, 9));
+    }
+
+    pub fn backspace(&mut self) {
+        self.value /= 10;
+    }
+
+    pub fn clear(&mut self) {
+        self.value = 0;
+    }
+
+    pub fn draw(&self, platform: &mut dyn IcPlatform, has_focus: bool) {
+        draw_frame(platform, self.pos, self.size, &self.style, has_focus);
+        let text_color = if has_focus {
+            self.style.text_focused
+        } else {
+            self.style.text
+        };
+        draw_text_f(
+            platform,
+            format_args!("{}", self.value),
+            (self.pos.x + self.style.padding) as f32,
+            (self.pos.y + self.style.padding) as f32,
+            self.style.text_scale,
+            text_color,
+        );
+        if has_focus {
+            draw_highlight(platform, self.pos, self.size, &self.style);
+        }
+    }
+}
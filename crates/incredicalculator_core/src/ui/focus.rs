@@ -0,0 +1,206 @@
+use glam::IVec2;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum NavDir {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+// Keeps track of which widget has focus. Every focusable widget gets an id
+// (its index) and a cell in a grid, arrow navigation picks the nearest enabled
+// cell in that direction and next/prev cycle through the ids in order.
+pub struct FocusManager<const N: usize> {
+    cells: [IVec2; N],
+    enabled: [bool; N],
+    focused: usize,
+    // when there's nothing further left/right, continue on the previous/next row
+    pub wrap_rows: bool,
+}
+
+impl<const N: usize> FocusManager<N> {
+    pub fn new(cells: [IVec2; N]) -> Self {
+        Self {
+            cells,
+            enabled: [true; N],
+            focused: 0,
+            wrap_rows: false,
+        }
+    }
+
+    pub fn focused(&self) -> usize {
+        self.focused
+    }
+
+    pub fn is_focused(&self, id: usize) -> bool {
+        self.focused == id
+    }
+
+    pub fn set_focus(&mut self, id: usize) {
+        if id < N && self.enabled[id] {
+            self.focused = id;
+        }
+    }
+
+    pub fn set_enabled(&mut self, id: usize, enabled: bool) {
+        self.enabled[id] = enabled;
+        if !enabled && self.focused == id {
+            self.next();
+        }
+    }
+
+    pub fn is_enabled(&self, id: usize) -> bool {
+        self.enabled[id]
+    }
+
+    pub fn next(&mut self) {
+        for step in 1..=N {
+            let id = (self.focused + step) % N;
+            if self.enabled[id] {
+                self.focused = id;
+                return;
+            }
+        }
+    }
+
+    pub fn prev(&mut self) {
+        for step in 1..=N {
+            let id = (self.focused + N * 2 - step) % N;
+            if self.enabled[id] {
+                self.focused = id;
+                return;
+            }
+        }
+    }
+
+    // Returns false if there was nowhere to go, so the caller can decide
+    // what leaving the widget group in that direction means.
+    pub fn navigate(&mut self, dir: NavDir) -> bool {
+        let from = self.cells[self.focused];
+        let mut best: Option<(usize, i32)> = None;
+        for id in 0..N {
+            if id == self.focused || !self.enabled[id] {
+                continue;
+            }
+            let d = self.cells[id] - from;
+            let (along, across) = match dir {
+                NavDir::Up => (-d.y, d.x.abs()),
+                NavDir::Down => (d.y, d.x.abs()),
+                NavDir::Left => (-d.x, d.y.abs()),
+                NavDir::Right => (d.x, d.y.abs()),
+            };
+            if along <= 0 {
+                continue;
+            }
+            // stay in the same row/column whenever possible
+            let score = along + across * 64;
+            if best.is_none_or(|(_, s)| score < s) {
+                best = Some((id, score));
+            }
+        }
+        if let Some((id, _)) = best {
+            self.focused = id;
+            return true;
+        }
+        if self.wrap_rows {
+            match dir {
+                NavDir::Left => return self.step_reading_order(false),
+                NavDir::Right => return self.step_reading_order(true),
+                _ => {}
+            }
+        }
+        false
+    }
+
+    fn step_reading_order(&mut self, forward: bool) -> bool {
+        let from = self.cells[self.focused];
+        let key = |c: IVec2| (c.y, c.x);
+        let mut best: Option<usize> = None;
+        for id in 0..N {
+            if id == self.focused || !self.enabled[id] {
+                continue;
+            }
+            let c = self.cells[id];
+            let is_candidate = if forward {
+                key(c) > key(from)
+            } else {
+                key(c) < key(from)
+            };
+            if !is_candidate {
+                continue;
+            }
+            let better = match best {
+                None => true,
+                Some(b) => {
+                    if forward {
+                        key(c) < key(self.cells[b])
+                    } else {
+                        key(c) > key(self.cells[b])
+                    }
+                }
+            };
+            if better {
+                best = Some(id);
+            }
+        }
+        match best {
+            Some(id) => {
+                self.focused = id;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 0 1 2
+    // 3   4
+    fn grid() -> FocusManager<5> {
+        FocusManager::new([
+            IVec2::new(0, 0),
+            IVec2::new(1, 0),
+            IVec2::new(2, 0),
+            IVec2::new(0, 1),
+            IVec2::new(2, 1),
+        ])
+    }
+
+    #[test]
+    fn navigate_picks_nearest_cell_in_row_or_column() {
+        let mut focus = grid();
+        assert!(focus.navigate(NavDir::Right));
+        assert_eq!(focus.focused(), 1);
+        assert!(focus.navigate(NavDir::Down));
+        assert_eq!(focus.focused(), 3);
+        assert!(focus.navigate(NavDir::Right));
+        assert_eq!(focus.focused(), 4);
+        assert!(focus.navigate(NavDir::Up));
+        assert_eq!(focus.focused(), 2);
+        assert!(!focus.navigate(NavDir::Up));
+        assert!(!focus.navigate(NavDir::Right));
+        assert_eq!(focus.focused(), 2);
+    }
+
+    #[test]
+    fn navigate_skips_disabled_and_wraps_rows() {
+        let mut focus = grid();
+        focus.set_enabled(1, false);
+        assert!(focus.navigate(NavDir::Right));
+        assert_eq!(focus.focused(), 2);
+
+        assert!(!focus.navigate(NavDir::Right));
+        focus.wrap_rows = true;
+        assert!(focus.navigate(NavDir::Right));
+        assert_eq!(focus.focused(), 3);
+        assert!(focus.navigate(NavDir::Left));
+        assert_eq!(focus.focused(), 2);
+
+        focus.set_enabled(2, false);
+        assert_eq!(focus.focused(), 3);
+    }
+}
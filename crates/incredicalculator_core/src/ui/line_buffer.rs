@@ -0,0 +1,135 @@
+// Single line of editable ASCII text with a cursor. N is the capacity in bytes.
+#[derive(Clone, Copy)]
+pub struct LineBuffer<const N: usize> {
+    pub data: [u8; N],
+    pub len: usize,
+    pub cursor: usize,
+}
+
+impl<const N: usize> Default for LineBuffer<N> {
+    fn default() -> Self {
+        Self {
+            data: [0; N],
+            len: 0,
+            cursor: 0,
+        }
+    }
+}
+
+impl<const N: usize> LineBuffer<N> {
+    pub const CAPACITY: usize = N;
+
+    pub fn insert_char(&mut self, char_code: u8) {
+        if self.len >= N {
+            return;
+        }
+        self.data.copy_within(self.cursor..self.len, self.cursor + 1);
+        self.data[self.cursor] = char_code;
+        self.cursor += 1;
+        self.len += 1;
+    }
+
+    pub fn insert_str(&mut self, s: &[u8]) {
+        for c in s {
+            self.insert_char(*c);
+        }
+    }
+
+    pub fn move_cursor(&mut self, right: bool) {
+        if right {
+            if self.cursor < self.len {
+                self.cursor += 1;
+            }
+        } else if self.cursor > 0 {
+            self.cursor -= 1;
+        }
+    }
+
+    pub fn move_cursor_home(&mut self) {
+        self.cursor = 0;
+    }
+
+    pub fn move_cursor_end(&mut self) {
+        self.cursor = self.len;
+    }
+
+    pub fn backspace(&mut self) {
+        if self.cursor > 0 {
+            self.data.copy_within(self.cursor..self.len, self.cursor - 1);
+            self.cursor -= 1;
+            self.len -= 1;
+            self.data[self.len] = 0;
+        }
+    }
+
+    pub fn backspace_del(&mut self) {
+        // delete is the same thing as pressing right and then backspace
+        if self.cursor < self.len {
+            self.move_cursor(true);
+            self.backspace();
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.data[..self.len]).unwrap_or("Invalid UTF-8")
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn set_content(&mut self, content: &[u8]) {
+        let copy_len = content.len().min(N);
+        self.data = [0; N];
+        self.data[..copy_len].copy_from_slice(&content[..copy_len]);
+        self.len = copy_len;
+        self.cursor = copy_len;
+    }
+
+    pub fn clear(&mut self) {
+        self.data = [0; N];
+        self.len = 0;
+        self.cursor = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn insert_and_delete_at_cursor() {
+        let mut buf = LineBuffer::<8>::default();
+        buf.insert_str(b"13");
+        buf.move_cursor(false);
+        buf.insert_char(b'2');
+        assert_eq!((buf.as_str(), buf.cursor), ("123", 2));
+
+        buf.backspace();
+        assert_eq!((buf.as_str(), buf.cursor), ("13", 1));
+        buf.backspace_del();
+        assert_eq!((buf.as_str(), buf.cursor), ("1", 1));
+        buf.backspace_del();
+        assert_eq!(buf.as_str(), "1");
+
+        buf.move_cursor_home();
+        buf.backspace();
+        assert_eq!((buf.as_str(), buf.cursor), ("1", 0));
+        buf.move_cursor(false);
+        assert_eq!(buf.cursor, 0);
+        buf.move_cursor_end();
+        buf.move_cursor(true);
+        assert_eq!(buf.cursor, 1);
+    }
+
+    #[test]
+    fn insert_stops_at_capacity() {
+        let mut buf = LineBuffer::<4>::default();
+        buf.insert_str(b"123456");
+        assert_eq!((buf.as_str(), buf.len, buf.cursor), ("1234", 4, 4));
+        buf.set_content(b"abcdef");
+        assert_eq!((buf.as_str(), buf.cursor), ("abcd", 4));
+        buf.clear();
+        assert!(buf.is_empty());
+    }
+}
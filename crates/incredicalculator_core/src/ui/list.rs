@@ -0,0 +1,231 @@
+use crate::platform::IcPlatform;
+use crate::text::draw_text;
+use crate::ui::NavDir;
+use glam::IVec2;
+use rgb::RGB8;
+
+#[derive(Clone, Copy)]
+pub struct ListStyle {
+    pub background: Option<RGB8>,
+    pub text: RGB8,
+    pub selection: RGB8,
+    pub selection_text: RGB8,
+    pub text_scale: f32,
+}
+
+impl Default for ListStyle {
+    fn default() -> Self {
+        Self {
+            background: None,
+            text: RGB8::new(0xff, 0xff, 0xff),
+            selection: RGB8::new(0, 0, 0xff),
+            selection_text: RGB8::new(0xff, 0xff, 0xff),
+            text_scale: 2.0,
+        }
+    }
+}
+
+// Vertical list with a selection that scrolls to keep the selection visible.
+// The list doesn't own its items, the caller passes them in when drawing.
+pub struct ListView {
+    pub pos: IVec2,
+    pub size: IVec2,
+    pub row_height: i32,
+    pub style: ListStyle,
+    len: usize,
+    selected: usize,
+    scroll: usize,
+}
+
+impl ListView {
+    pub fn new(pos: IVec2, size: IVec2, row_height: i32, style: ListStyle) -> Self {
+        Self {
+            pos,
+            size,
+            row_height,
+            style,
+            len: 0,
+            selected: 0,
+            scroll: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn set_len(&mut self, len: usize) {
+        self.len = len;
+        if self.selected >= len {
+            self.selected = len.saturating_sub(1);
+        }
+        self.keep_selection_visible();
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    pub fn select(&mut self, idx: usize) {
+        if idx < self.len {
+            self.selected = idx;
+            self.keep_selection_visible();
+        }
+    }
+
+    pub fn visible_rows(&self) -> usize {
+        if self.row_height <= 0 {
+            return 0;
+        }
+        (self.size.y / self.row_height).max(1) as usize
+    }
+
+    // Up and down move the selection and wrap around at the ends. Returns
+    // true if the key was used.
+    pub fn navigate(&mut self, dir: NavDir) -> bool {
+        if self.len == 0 {
+            return false;
+        }
+        match dir {
+            NavDir::Up => {
+                self.selected = (self.selected + self.len - 1) % self.len;
+            }
+            NavDir::Down => {
+                self.selected = (self.selected + 1) % self.len;
+            }
+            NavDir::Left | NavDir::Right => return false,
+        }
+        self.keep_selection_visible();
+        true
+    }
+
+    fn keep_selection_visible(&mut self) {
+        let rows = self.visible_rows();
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if rows > 0 && self.selected >= self.scroll + rows {
+            self.scroll = self.selected + 1 - rows;
+        }
+        // don't leave empty rows at the bottom after the list shrinks
+        self.scroll = self.scroll.min(self.len.saturating_sub(rows));
+    }
+
+    // Calls draw_row(platform, item_idx, row_top_left, is_selected) for every
+    // visible row after painting the background and selection bar.
+    pub fn draw_with(
+        &self,
+        platform: &mut dyn IcPlatform,
+        has_focus: bool,
+        mut draw_row: impl FnMut(&mut dyn IcPlatform, usize, IVec2, bool),
+    ) {
+        if let Some(bg) = self.style.background {
+            platform.draw_rectangle(self.pos, self.pos + self.size, bg, 0, Some(bg));
+        }
+        let end = (self.scroll + self.visible_rows()).min(self.len);
+        for (row, idx) in (self.scroll..end).enumerate() {
+            let top_left = self.pos + IVec2::new(0, row as i32 * self.row_height);
+            let is_selected = has_focus && idx == self.selected;
+            if is_selected {
+                platform.draw_rectangle(
+                    top_left,
+                    top_left + IVec2::new(self.size.x, self.row_height - 1),
+                    self.style.selection,
+                    0,
+                    Some(self.style.selection),
+                );
+            }
+            draw_row(platform, idx, top_left, is_selected);
+        }
+        if self.scroll > 0 {
+            draw_text(
+                platform,
+                "\x18",
+                (self.pos.x + self.size.x - 12) as f32,
+                self.pos.y as f32,
+                1.0,
+                self.style.text,
+            );
+        }
+        if end < self.len {
+            draw_text(
+                platform,
+                "\x19",
+                (self.pos.x + self.size.x - 12) as f32,
+                (self.pos.y + self.size.y - 9) as f32,
+                1.0,
+                self.style.text,
+            );
+        }
+    }
+
+    pub fn draw(&self, platform: &mut dyn IcPlatform, items: &[&str], has_focus: bool) {
+        let style = self.style;
+        let text_offset = ((self.row_height as f32 - 7.0 * style.text_scale) / 2.0).max(0.0);
+        self.draw_with(platform, has_focus, |platform, idx, top_left, is_selected| {
+            let label = items.get(idx).copied().unwrap_or("");
+            draw_text(
+                platform,
+                label,
+                (top_left.x + 4) as f32,
+                top_left.y as f32 + text_offset,
+                style.text_scale,
+                if is_selected {
+                    style.selection_text
+                } else {
+                    style.text
+                },
+            );
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list(len: usize) -> ListView {
+        // three rows fit
+        let mut list = ListView::new(IVec2::ZERO, IVec2::new(100, 30), 10, ListStyle::default());
+        list.set_len(len);
+        list
+    }
+
+    #[test]
+    fn scroll_follows_selection() {
+        let mut list = list(5);
+        assert_eq!(list.visible_rows(), 3);
+        list.navigate(NavDir::Down);
+        list.navigate(NavDir::Down);
+        assert_eq!(list.scroll, 0);
+        list.navigate(NavDir::Down);
+        assert_eq!((list.selected(), list.scroll), (3, 1));
+        list.select(4);
+        assert_eq!(list.scroll, 2);
+        list.select(0);
+        assert_eq!(list.scroll, 0);
+
+        // up from the top wraps to the end and scrolls down to it
+        list.navigate(NavDir::Up);
+        assert_eq!((list.selected(), list.scroll), (4, 2));
+        list.navigate(NavDir::Down);
+        assert_eq!((list.selected(), list.scroll), (0, 0));
+    }
+
+    #[test]
+    fn shrinking_clamps_selection_and_scroll() {
+        let mut list = list(10);
+        list.select(9);
+        assert_eq!(list.scroll, 7);
+        list.set_len(4);
+        assert_eq!((list.selected(), list.scroll), (3, 1));
+        list.select(7);
+        assert_eq!(list.selected(), 3);
+        list.set_len(0);
+        assert_eq!((list.selected(), list.scroll), (0, 0));
+        assert!(!list.navigate(NavDir::Down));
+    }
+}
@@ -0,0 +1,94 @@
+use crate::platform::IcPlatform;
+use crate::text::draw_text;
+use crate::ui::{ListStyle, ListView, NavDir, SCREEN_SIZE};
+use alloc::vec::Vec;
+use glam::IVec2;
+use rgb::RGB8;
+
+#[derive(Clone, Copy)]
+pub struct MenuStyle {
+    pub background: RGB8,
+    pub border: RGB8,
+    pub title: RGB8,
+    pub list: ListStyle,
+}
+
+impl Default for MenuStyle {
+    fn default() -> Self {
+        Self {
+            background: RGB8::new(0x20, 0x20, 0x20),
+            border: RGB8::new(0xff, 0xff, 0xff),
+            title: RGB8::new(0xff, 0xff, 0x00),
+            list: ListStyle::default(),
+        }
+    }
+}
+
+// Popup list of choices with a title, drawn centered over whatever is
+// underneath it.
+pub struct Menu {
+    pub title: &'static str,
+    pub options: Vec<&'static str>,
+    pub style: MenuStyle,
+    list: ListView,
+    pos: IVec2,
+    size: IVec2,
+}
+
+impl Menu {
+    const ROW_H: i32 = 22;
+    const TITLE_H: i32 = 24;
+    const MAX_VISIBLE_ROWS: usize = 7;
+
+    pub fn new(title: &'static str, options: &[&'static str], style: MenuStyle) -> Self {
+        let rows = options.len().clamp(1, Self::MAX_VISIBLE_ROWS) as i32;
+        let size = IVec2::new(200, Self::TITLE_H + rows * Self::ROW_H + 4);
+        let pos = (SCREEN_SIZE - size) / 2;
+        let mut list = ListView::new(
+            pos + IVec2::new(2, Self::TITLE_H),
+            IVec2::new(size.x - 4, rows * Self::ROW_H),
+            Self::ROW_H,
+            style.list,
+        );
+        list.set_len(options.len());
+        Self {
+            title,
+            options: options.to_vec(),
+            style,
+            list,
+            pos,
+            size,
+        }
+    }
+
+    pub fn selected(&self) -> usize {
+        self.list.selected()
+    }
+
+    pub fn select(&mut self, idx: usize) {
+        self.list.select(idx);
+    }
+
+    pub fn navigate(&mut self, dir: NavDir) -> bool {
+        self.list.navigate(dir)
+    }
+
+    pub fn draw(&self, platform: &mut dyn IcPlatform) {
+        platform.draw_rectangle(
+            self.pos,
+            self.pos + self.size,
+            self.style.border,
+            2,
+            Some(self.style.background),
+        );
+        draw_text(
+            platform,
+            self.title,
+            (self.pos.x + 6) as f32,
+            (self.pos.y + 5) as f32,
+            2.0,
+            self.style.title,
+        );
+        self.list.draw(platform, &self.options, true);
+    }
+}
@@ -0,0 +1,18 @@
+// Widgets shared between apps. They only know how to draw themselves with an
+// IcPlatform and update their own state, apps decide which keys drive them.
+mod dialog;
+pub use dialog::{Dialog, DialogStyle};
+mod field;
+pub use field::{FieldStyle, NumField, TextField};
+mod focus;
+pub use focus::{FocusManager, NavDir};
+mod line_buffer;
+pub use line_buffer::LineBuffer;
+mod list;
+pub use list::{ListStyle, ListView};
+mod menu;
+pub use menu::{Menu, MenuStyle};
+
+use glam::IVec2;
+
+pub const SCREEN_SIZE: IVec2 = IVec2::new(320, 240);
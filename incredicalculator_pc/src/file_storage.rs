@@ -0,0 +1,64 @@
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::PathBuf;
+
+use incredicalculator_core::storage::{Storage, StorageError, check_limits};
+
+// One file per blob holding the version as two little endian bytes followed
+// by the data. Writes go to a temporary file that is renamed over the old
+// one, so an interrupted write leaves the previous copy in place.
+pub struct FileStorage {
+    dir: PathBuf,
+}
+
+impl FileStorage {
+    pub fn new(dir: impl Into<PathBuf>) -> FileStorage {
+        FileStorage { dir: dir.into() }
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{}.blob", name))
+    }
+}
+
+impl Storage for FileStorage {
+    fn read(&mut self, name: &str, buf: &mut Vec<u8>) -> Result<u16, StorageError> {
+        let bytes = match fs::read(self.path(name)) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Err(StorageError::NotFound),
+            Err(_) => return Err(StorageError::Io),
+        };
+        if bytes.len() < 2 {
+            return Err(StorageError::Io);
+        }
+        buf.clear();
+        buf.extend_from_slice(&bytes[2..]);
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn write(&mut self, name: &str, version: u16, data: &[u8]) -> Result<(), StorageError> {
+        check_limits(name, data)?;
+        let path = self.path(name);
+        let tmp_path = path.with_extension("tmp");
+        let result = fs::create_dir_all(&self.dir)
+            .and_then(|_| fs::File::create(&tmp_path))
+            .and_then(|mut file| {
+                file.write_all(&version.to_le_bytes())?;
+                file.write_all(data)?;
+                file.sync_all()
+            })
+            .and_then(|_| fs::rename(&tmp_path, &path));
+        result.map_err(|e| match e.kind() {
+            ErrorKind::StorageFull => StorageError::Full,
+            _ => StorageError::Io,
+        })
+    }
+
+    fn remove(&mut self, name: &str) -> Result<(), StorageError> {
+        match fs::remove_file(self.path(name)) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+            Err(_) => Err(StorageError::Io),
+        }
+    }
+}
@@ -1,24 +1,29 @@
 use ::core::fmt;
-use std::{collections::HashMap, time::Instant};
+use std::{collections::HashMap, io::Write, time::{Duration, Instant, SystemTime, UNIX_EPOCH}};
 
-use embedded_graphics::{Drawable, pixelcolor::{BinaryColor, Rgb565}, prelude::{Primitive, RgbColor}, primitives::{PrimitiveStyle, PrimitiveStyleBuilder}};
-use embedded_graphics_framebuf::FrameBuf;
+use embedded_graphics::{pixelcolor::Rgb565, prelude::RgbColor};
 use raylib::{ffi::{SetTextureFilter, RL_TEXTURE_FILTER_LINEAR}, prelude::*};
 
+use incredicalculator_core::app::FrameRequest;
+use incredicalculator_core::framebuffer::FrameBuffer;
 use incredicalculator_core::input::IcKey;
+use incredicalculator_core::legend::KeyLayer;
 use incredicalculator_core::platform::{IcPlatform};
+use incredicalculator_core::png::write_png;
+use incredicalculator_core::power::PowerState;
 use incredicalculator_core::shell::IcShell;
+use incredicalculator_core::storage::Storage;
 use glam::IVec2;
 
+mod file_storage;
+use file_storage::FileStorage;
+
 struct VirtualKey {
     key: IcKey,
     x: u32,
     y: u32,
     pressed: bool,
     hovered: bool,
-    label: &'static str,
-    shlabel: &'static str,
-    sulabel: &'static str,
     sticky: bool
 }
 
@@ -26,33 +31,45 @@ const RENDER_W: u32 = 320;
 const RENDER_H: u32 = 240;
 
 pub struct IcRaylibPlatform {
-    pub canvas_data: [Rgb565; (RENDER_W * RENDER_H) as usize],
-    start_time: Instant
+    pub frame: FrameBuffer<Vec<Rgb565>>,
+    start_time: Instant,
+    storage: FileStorage,
+    // shown by dimming the simulated screen
+    backlight: u8,
+    power_state: PowerState,
+    // faked battery, changed with F9-F11
+    battery_soc: i32,
+    charging: bool,
 }
 
 impl IcRaylibPlatform {
     pub fn new() -> IcRaylibPlatform {
         IcRaylibPlatform {
-            canvas_data: [Rgb565::BLACK; (RENDER_W * RENDER_H) as usize],
-            start_time: Instant::now()
+            frame: FrameBuffer::new(
+                vec![Rgb565::BLACK; (RENDER_W * RENDER_H) as usize],
+                RENDER_W,
+                RENDER_H,
+            ),
+            start_time: Instant::now(),
+            // IC_STORAGE_DIR picks another place, eg. to keep separate setups
+            storage: FileStorage::new(
+                std::env::var("IC_STORAGE_DIR").unwrap_or_else(|_| String::from("ic_storage")),
+            ),
+            backlight: 100,
+            power_state: PowerState::Active,
+            battery_soc: 77,
+            charging: false,
         }
     }
 }
 
 impl IcPlatform for IcRaylibPlatform {
     fn clear(&mut self, color: rgb::RGB8) {
-        self.canvas_data.fill(rgbu8_to_rgb565(color));
+        self.frame.clear(color);
     }
 
     fn draw_line(&mut self, start: IVec2, end: IVec2, color: rgb::RGB8, width: u32) {
-        let mut fbuf = FrameBuf::new(&mut self.canvas_data, RENDER_W as usize, RENDER_H as usize);
-        embedded_graphics::primitives::Line::new(
-            embedded_graphics::prelude::Point::new(start.x, start.y),
-            embedded_graphics::prelude::Point::new(end.x, end.y),
-        )
-        .into_styled(PrimitiveStyle::with_stroke(rgbu8_to_rgb565(color), width))
-        .draw(&mut fbuf)
-        .unwrap();
+        self.frame.draw_line(start, end, color, width);
     }
 
     fn log(&mut self, arg: fmt::Arguments) {
@@ -67,22 +84,7 @@ impl IcPlatform for IcRaylibPlatform {
         stroke_width: u32,
         fill_color: Option<rgb::RGB8>,
     ) {
-        let mut fbuf = FrameBuf::new(&mut self.canvas_data, RENDER_W as usize, RENDER_H as usize);
-        let mut style_builder = PrimitiveStyleBuilder::new()
-            .stroke_color(rgbu8_to_rgb565(stroke_color))
-            .stroke_width(stroke_width)
-            .stroke_alignment(embedded_graphics::primitives::StrokeAlignment::Center);
-        if let Some(c) = fill_color {
-            style_builder = style_builder.fill_color(rgbu8_to_rgb565(c));
-        }
-        let style = style_builder.build();
-        embedded_graphics::primitives::Rectangle::with_corners(
-            embedded_graphics::prelude::Point::new(start.x, start.y),
-            embedded_graphics::prelude::Point::new(end.x, end.y),
-        )
-        .into_styled(style)
-        .draw(&mut fbuf)
-        .unwrap();
+        self.frame.draw_rectangle(start, end, stroke_color, stroke_width, fill_color);
     }
 
     fn draw_rectangle_rounded(
@@ -94,81 +96,43 @@ impl IcPlatform for IcRaylibPlatform {
         fill_color: Option<rgb::RGB8>,
         corner_radius: u32,
     ) {
-        let mut fbuf = FrameBuf::new(&mut self.canvas_data, RENDER_W as usize, RENDER_H as usize);
-        let mut style_builder = PrimitiveStyleBuilder::new()
-            .stroke_color(rgbu8_to_rgb565(stroke_color))
-            .stroke_width(stroke_width)
-            .stroke_alignment(embedded_graphics::primitives::StrokeAlignment::Center);
-        if let Some(c) = fill_color {
-            style_builder = style_builder.fill_color(rgbu8_to_rgb565(c));
-        }
-        let style = style_builder.build();
-        embedded_graphics::primitives::RoundedRectangle::with_equal_corners(
-            embedded_graphics::primitives::Rectangle::with_corners(
-                embedded_graphics::prelude::Point::new(start.x, start.y),
-                embedded_graphics::prelude::Point::new(end.x, end.y),
-            ),
-            embedded_graphics::prelude::Size::new(corner_radius, corner_radius),
-        )
-        .into_styled(style)
-        .draw(&mut fbuf)
-        .unwrap();
+        self.frame.draw_rectangle_rounded(start, end, stroke_color, stroke_width, fill_color, corner_radius);
     }
 
     fn draw_triangle(&mut self, vertex1: IVec2, vertex2: IVec2, vertex3: IVec2, stroke_color: rgb::RGB8, stroke_width: u32, fill_color: Option<rgb::RGB8>) {
-        let mut fbuf = FrameBuf::new(&mut self.canvas_data, RENDER_W as usize, RENDER_H as usize);
-        let mut style_builder = PrimitiveStyleBuilder::new()
-            .stroke_color(rgbu8_to_rgb565(stroke_color))
-            .stroke_width(stroke_width)
-            .stroke_alignment(embedded_graphics::primitives::StrokeAlignment::Center);
-        if let Some(c) = fill_color {
-            style_builder = style_builder.fill_color(rgbu8_to_rgb565(c));
-        }
-        let style = style_builder.build();
-        embedded_graphics::primitives::Triangle::new(
-            embedded_graphics::prelude::Point::new(vertex1.x, vertex1.y),
-            embedded_graphics::prelude::Point::new(vertex2.x, vertex2.y),
-            embedded_graphics::prelude::Point::new(vertex3.x, vertex3.y),
-        )
-        .into_styled(style).draw(&mut fbuf).unwrap();
+        self.frame.draw_triangle(vertex1, vertex2, vertex3, stroke_color, stroke_width, fill_color);
     }
-    
+
+    fn draw_circle(&mut self, center: IVec2, radius: u32, stroke_color: rgb::RGB8, stroke_width: u32, fill_color: Option<rgb::RGB8>) {
+        self.frame.draw_circle(center, radius, stroke_color, stroke_width, fill_color);
+    }
+
+    fn draw_ellipse(&mut self, center: IVec2, radii: IVec2, stroke_color: rgb::RGB8, stroke_width: u32, fill_color: Option<rgb::RGB8>) {
+        self.frame.draw_ellipse(center, radii, stroke_color, stroke_width, fill_color);
+    }
+
+    fn draw_arc(&mut self, center: IVec2, radius: u32, start_deg: f32, sweep_deg: f32, color: rgb::RGB8, width: u32) {
+        self.frame.draw_arc(center, radius, start_deg, sweep_deg, color, width);
+    }
+
+    fn draw_polyline(&mut self, points: &[IVec2], color: rgb::RGB8, width: u32) {
+        self.frame.draw_polyline(points, color, width);
+    }
+
+    fn draw_polygon(&mut self, points: &[IVec2], stroke_color: rgb::RGB8, stroke_width: u32, fill_color: Option<rgb::RGB8>) {
+        self.frame.draw_polygon(points, stroke_color, stroke_width, fill_color);
+    }
+
+    fn draw_pixel(&mut self, pos: IVec2, color: rgb::RGB8) {
+        self.frame.draw_pixel(pos, color);
+    }
+
+    fn draw_pixels(&mut self, pos: IVec2, width: u32, pixels: &[rgb::RGB8]) {
+        self.frame.draw_pixels(pos, width, pixels);
+    }
+
     fn draw_string(&mut self, text: &str, pos: IVec2, size: u32, color: rgb::RGB8) {
-        let mut fbuf = FrameBuf::new(&mut self.canvas_data, RENDER_W as usize, RENDER_H as usize);
-        
-        // using a BUILT-IN FONT!
-        let char_style = embedded_graphics::mono_font::MonoTextStyle::new(
-            &embedded_graphics::mono_font::ascii::FONT_10X20,
-            rgbu8_to_rgb565(color)
-        );
-        let text_style = embedded_graphics::text::TextStyleBuilder::new()
-        .alignment(embedded_graphics::text::Alignment::Left)
-        .baseline(embedded_graphics::text::Baseline::Top)
-        .build();
-        embedded_graphics::text::Text::with_text_style(
-            text,
-            embedded_graphics::prelude::Point::new(pos.x, pos.y),
-            char_style,
-            text_style,
-        )
-        .draw(&mut fbuf)
-        .unwrap();
-
-        // using a TTF FONT!
-        // let style = embedded_ttf::FontTextStyleBuilder::new(
-        // rusttype::Font::try_from_bytes(include_bytes!("../assets/Roboto-Regular.ttf")).unwrap())
-        // .font_size(30)
-        // .text_color(Rgb565::WHITE)
-        // //.anti_aliasing_color(Rgb565::BLACK)
-        // .build();
-        // embedded_graphics::text::Text::with_alignment(
-        //     text,
-        //     embedded_graphics::prelude::Point::new(pos.x, pos.y),
-        //     style,
-        //     embedded_graphics::text::Alignment::Left,
-        // )
-        // .draw(&mut fbuf)
-        // .unwrap();
+        self.frame.draw_string(text, pos, size, color);
     }
 
     fn draw_string_f(&mut self, arg: fmt::Arguments, pos: IVec2, size: u32, color: rgb::RGB8) {
@@ -181,7 +145,67 @@ impl IcPlatform for IcRaylibPlatform {
     }
 
     fn get_battery_soc(&self) -> i32 {
-        77
+        self.battery_soc
+    }
+
+    fn is_charging(&self) -> bool {
+        self.charging
+    }
+
+    // roughly a lithium cell between empty and full
+    fn battery_voltage(&self) -> Option<f32> {
+        Some(3.3 + 0.9 * self.battery_soc as f32 / 100.0)
+    }
+
+    fn battery_charge_rate(&self) -> Option<f32> {
+        Some(if self.charging { 25.0 } else { -6.0 })
+    }
+
+    fn time_of_day(&self) -> Option<u32> {
+        // UTC, there is no timezone handling on the device either
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?;
+        Some((now.as_secs() % 86400) as u32)
+    }
+
+    fn storage(&mut self) -> Option<&mut dyn Storage> {
+        Some(&mut self.storage)
+    }
+
+    // IC_SCREENSHOT_DIR picks the folder, named by time so they sort
+    fn save_screenshot(&mut self) -> bool {
+        let dir = std::env::var("IC_SCREENSHOT_DIR").unwrap_or_else(|_| String::from("ic_screenshots"));
+        let millis = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis()).unwrap_or(0);
+        let path = std::path::Path::new(&dir).join(format!("screenshot-{millis}.png"));
+        let result = std::fs::create_dir_all(&dir)
+            .and_then(|_| std::fs::File::create(&path))
+            .and_then(|file| {
+                let mut writer = std::io::BufWriter::new(file);
+                let mut result = Ok(());
+                write_png(self.frame.pixels(), RENDER_W, RENDER_H, &mut |bytes| {
+                    if result.is_ok() {
+                        result = writer.write_all(bytes);
+                    }
+                });
+                result.and_then(|_| writer.flush())
+            });
+        match result {
+            Ok(()) => {
+                println!("Saved {}", path.display());
+                true
+            }
+            Err(e) => {
+                eprintln!("Screenshot to {} failed: {}", path.display(), e);
+                false
+            }
+        }
+    }
+
+    fn set_backlight(&mut self, level: u8) {
+        self.backlight = level.min(100);
+    }
+
+    fn set_power_state(&mut self, state: PowerState) {
+        self.power_state = state;
     }
 }
 
@@ -189,10 +213,6 @@ fn rgb565_to_rl_color(rgb565_col: Rgb565) -> Color {
     Color { r: rgb565_col.r() << 3, g: rgb565_col.g() << 2, b: rgb565_col.b() << 3, a: 255 }
 }
 
-fn rgbu8_to_rgb565(rgbu8_col: rgb::Rgb<u8>) -> Rgb565 {
-    Rgb565::new(rgbu8_col.r >> 3, rgbu8_col.g >> 2, rgbu8_col.b >> 3)
-}
-
 fn main() {
     let mut icalc: IcShell = IcShell::new();
     let mut ic_rl_platform = Box::new(IcRaylibPlatform::new());
@@ -220,24 +240,24 @@ fn main() {
     };
     let mut virtual_keys = [
         
-        VirtualKey { key: IcKey::Func1,  x: 7 + 69 * 3, y: 9 + 69 * 0, pressed: false, hovered: false, label: "Bk", shlabel: "&",   sulabel: "F1", sticky: false },
-        VirtualKey { key: IcKey::Func2,  x: 7 + 69 * 3, y: 9 + 69 * 1, pressed: false, hovered: false, label: "/", shlabel: "|",   sulabel: "F2", sticky: false },
-        VirtualKey { key: IcKey::Num7,   x: 7 + 69 * 0, y: 9 + 69 * 2, pressed: false, hovered: false, label: "7", shlabel: "(",  sulabel: "Hm", sticky: false },
-        VirtualKey { key: IcKey::Num8,   x: 7 + 69 * 1, y: 9 + 69 * 2, pressed: false, hovered: false, label: "8", shlabel: ")",  sulabel: "^", sticky: false },
-        VirtualKey { key: IcKey::Num9,   x: 7 + 69 * 2, y: 9 + 69 * 2, pressed: false, hovered: false, label: "9", shlabel: "0x", sulabel: "Clr", sticky: false },
-        VirtualKey { key: IcKey::Func3,  x: 7 + 69 * 3, y: 9 + 69 * 2, pressed: false, hovered: false, label: "*", shlabel: "%",   sulabel: "F3", sticky: false },
-        VirtualKey { key: IcKey::Num4,   x: 7 + 69 * 0, y: 9 + 69 * 3, pressed: false, hovered: false, label: "4", shlabel: "E",   sulabel: "<", sticky: false },
-        VirtualKey { key: IcKey::Num5,   x: 7 + 69 * 1, y: 9 + 69 * 3, pressed: false, hovered: false, label: "5", shlabel: "F",   sulabel: "Sel", sticky: false },
-        VirtualKey { key: IcKey::Num6,   x: 7 + 69 * 2, y: 9 + 69 * 3, pressed: false, hovered: false, label: "6", shlabel: "~",   sulabel: ">", sticky: false },
-        VirtualKey { key: IcKey::Func4,  x: 7 + 69 * 3, y: 9 + 69 * 3, pressed: false, hovered: false, label: "-", shlabel: "<<",  sulabel: "F4", sticky: false },
-        VirtualKey { key: IcKey::Num1,   x: 7 + 69 * 0, y: 9 + 69 * 4, pressed: false, hovered: false, label: "1", shlabel: "B",   sulabel: "End", sticky: false },
-        VirtualKey { key: IcKey::Num2,   x: 7 + 69 * 1, y: 9 + 69 * 4, pressed: false, hovered: false, label: "2", shlabel: "C",   sulabel: "v", sticky: false },
-        VirtualKey { key: IcKey::Num3,   x: 7 + 69 * 2, y: 9 + 69 * 4, pressed: false, hovered: false, label: "3", shlabel: "D",   sulabel: "Und", sticky: false },
-        VirtualKey { key: IcKey::Func5,  x: 7 + 69 * 3, y: 9 + 69 * 4, pressed: false, hovered: false, label: "+", shlabel: ">>",   sulabel: "F5", sticky: false },
-        VirtualKey { key: IcKey::Num0,   x: 7 + 69 * 0, y: 9 + 69 * 5, pressed: false, hovered: false, label: "0", shlabel: "A",   sulabel: ".", sticky: false },
-        VirtualKey { key: IcKey::Shift,  x: 7 + 69 * 1, y: 9 + 69 * 5, pressed: false, hovered: false, label: "Shft", shlabel: "",    sulabel: "", sticky: true },
-        VirtualKey { key: IcKey::Super,  x: 7 + 69 * 2, y: 9 + 69 * 5, pressed: false, hovered: false, label: "§", shlabel: "",    sulabel: "", sticky: true },
-        VirtualKey { key: IcKey::Func6,  x: 7 + 69 * 3, y: 9 + 69 * 5, pressed: false, hovered: false, label: "=", shlabel: "^",   sulabel: "F6", sticky: false },
+        VirtualKey { key: IcKey::Func1,  x: 7 + 69 * 3, y: 9 + 69 * 0, pressed: false, hovered: false, sticky: false },
+        VirtualKey { key: IcKey::Func2,  x: 7 + 69 * 3, y: 9 + 69 * 1, pressed: false, hovered: false, sticky: false },
+        VirtualKey { key: IcKey::Num7,   x: 7 + 69 * 0, y: 9 + 69 * 2, pressed: false, hovered: false, sticky: false },
+        VirtualKey { key: IcKey::Num8,   x: 7 + 69 * 1, y: 9 + 69 * 2, pressed: false, hovered: false, sticky: false },
+        VirtualKey { key: IcKey::Num9,   x: 7 + 69 * 2, y: 9 + 69 * 2, pressed: false, hovered: false, sticky: false },
+        VirtualKey { key: IcKey::Func3,  x: 7 + 69 * 3, y: 9 + 69 * 2, pressed: false, hovered: false, sticky: false },
+        VirtualKey { key: IcKey::Num4,   x: 7 + 69 * 0, y: 9 + 69 * 3, pressed: false, hovered: false, sticky: false },
+        VirtualKey { key: IcKey::Num5,   x: 7 + 69 * 1, y: 9 + 69 * 3, pressed: false, hovered: false, sticky: false },
+        VirtualKey { key: IcKey::Num6,   x: 7 + 69 * 2, y: 9 + 69 * 3, pressed: false, hovered: false, sticky: false },
+        VirtualKey { key: IcKey::Func4,  x: 7 + 69 * 3, y: 9 + 69 * 3, pressed: false, hovered: false, sticky: false },
+        VirtualKey { key: IcKey::Num1,   x: 7 + 69 * 0, y: 9 + 69 * 4, pressed: false, hovered: false, sticky: false },
+        VirtualKey { key: IcKey::Num2,   x: 7 + 69 * 1, y: 9 + 69 * 4, pressed: false, hovered: false, sticky: false },
+        VirtualKey { key: IcKey::Num3,   x: 7 + 69 * 2, y: 9 + 69 * 4, pressed: false, hovered: false, sticky: false },
+        VirtualKey { key: IcKey::Func5,  x: 7 + 69 * 3, y: 9 + 69 * 4, pressed: false, hovered: false, sticky: false },
+        VirtualKey { key: IcKey::Num0,   x: 7 + 69 * 0, y: 9 + 69 * 5, pressed: false, hovered: false, sticky: false },
+        VirtualKey { key: IcKey::Shift,  x: 7 + 69 * 1, y: 9 + 69 * 5, pressed: false, hovered: false, sticky: false },
+        VirtualKey { key: IcKey::Super,  x: 7 + 69 * 2, y: 9 + 69 * 5, pressed: false, hovered: false, sticky: false },
+        VirtualKey { key: IcKey::Func6,  x: 7 + 69 * 3, y: 9 + 69 * 5, pressed: false, hovered: false, sticky: false },
     ];
     
     println!("Hello, world!");
@@ -254,6 +274,7 @@ fn main() {
     unsafe {
         SetTextureFilter(target_tex.texture, RL_TEXTURE_FILTER_LINEAR as i32);
     }    
+    let mut last_update: Option<Instant> = None;
     while !rl_handle.window_should_close() {
         while let Some(rl_key) = rl_handle.get_key_pressed() {
             if let Some(ic_key) = key_map.get(&rl_key) {
@@ -267,6 +288,21 @@ fn main() {
                 }
             }
         }
+        // fake battery controls
+        let battery_before = (ic_rl_platform.battery_soc, ic_rl_platform.charging);
+        if rl_handle.is_key_pressed(KeyboardKey::KEY_F9) {
+            ic_rl_platform.charging = !ic_rl_platform.charging;
+        }
+        if rl_handle.is_key_pressed(KeyboardKey::KEY_F10) {
+            ic_rl_platform.battery_soc = (ic_rl_platform.battery_soc - 5).max(0);
+        }
+        if rl_handle.is_key_pressed(KeyboardKey::KEY_F11) {
+            ic_rl_platform.battery_soc = (ic_rl_platform.battery_soc + 5).min(100);
+        }
+        if battery_before != (ic_rl_platform.battery_soc, ic_rl_platform.charging) {
+            // the shell only looks at the battery when it updates
+            last_update = None;
+        }
         let virtual_key_size: i32 = 64;
         let mouse_pos = rl_handle.get_mouse_position();
         let mouse_down = rl_handle.is_mouse_button_down(MouseButton::MOUSE_BUTTON_LEFT);
@@ -299,20 +335,32 @@ fn main() {
             }
         }
 
-        icalc.update(ic_rl_platform.as_mut());
-
-        let fps: u32 = rl_handle.get_fps();
+        // the window keeps redrawing at its own rate, the shell only runs when
+        // there was input or the active app asked for a frame
+        let frame_due = match (icalc.frame_request(), last_update) {
+            (_, None) | (FrameRequest::Continuous, _) => true,
+            (FrameRequest::AfterMs(ms), Some(t)) => t.elapsed() >= Duration::from_millis(ms as u64),
+            (FrameRequest::Idle, Some(_)) => false,
+        };
+        if frame_due {
+            icalc.update(ic_rl_platform.as_mut());
+            last_update = Some(Instant::now());
 
-        let mut raw_pixels: Vec<u8> = Vec::with_capacity((RENDER_W * RENDER_H * 4) as usize);
-        for pixel in ic_rl_platform.canvas_data.iter() {
-            let c = rgb565_to_rl_color(*pixel);
-            raw_pixels.push(c.r);
-            raw_pixels.push(c.g);
-            raw_pixels.push(c.b);
-            raw_pixels.push(c.a);
+            let mut raw_pixels: Vec<u8> = Vec::with_capacity((RENDER_W * RENDER_H * 4) as usize);
+            for pixel in ic_rl_platform.frame.pixels().iter() {
+                let c = rgb565_to_rl_color(*pixel);
+                raw_pixels.push(c.r);
+                raw_pixels.push(c.g);
+                raw_pixels.push(c.b);
+                raw_pixels.push(c.a);
+            }
+            target_tex.update_texture(&raw_pixels).unwrap();
         }
-        target_tex.update_texture(&raw_pixels).unwrap();
 
+        let fps: u32 = rl_handle.get_fps();
+
+        // labels follow whatever the active app maps the keys to
+        let legend = icalc.key_legend();
         let mut rl_draw_handle = rl_handle.begin_drawing(&rl_thread);
         rl_draw_handle.clear_background(Color::DARKOLIVEGREEN);
         rl_draw_handle.draw_rectangle(0, 0, 286, 430, Color::GRAY);
@@ -325,17 +373,37 @@ fn main() {
                 Color::LIGHTGRAY
             };
             rl_draw_handle.draw_rectangle(vk.x as i32, vk.y as i32, virtual_key_size, virtual_key_size, c);
-            rl_draw_handle.draw_text(&vk.label, vk.x as i32 + 16, vk.y as i32 + 16, 20, Color::BLACK);
-            rl_draw_handle.draw_text(&vk.shlabel, vk.x as i32 + 46, vk.y as i32 + 46, 20, Color::BLUE);
-            rl_draw_handle.draw_text(&vk.sulabel, vk.x as i32 + 6, vk.y as i32 + 46, 20, Color::RED);
+            let label = legend.get(vk.key, KeyLayer::Base);
+            let size = if rl_draw_handle.measure_text(label, 20) > virtual_key_size - 20 { 14 } else { 20 };
+            rl_draw_handle.draw_text(label, vk.x as i32 + 10, vk.y as i32 + 14, size, Color::BLACK);
+            // the corner labels share the bottom edge, shift right aligned
+            let shlabel = legend.get(vk.key, KeyLayer::Shift);
+            let shlabel_w = rl_draw_handle.measure_text(shlabel, 12);
+            rl_draw_handle.draw_text(shlabel, vk.x as i32 + virtual_key_size - 4 - shlabel_w, vk.y as i32 + 46, 12, Color::BLUE);
+            let sulabel = legend.get(vk.key, KeyLayer::Super);
+            rl_draw_handle.draw_text(sulabel, vk.x as i32 + 4, vk.y as i32 + 46, 12, Color::RED);
         }
         let source_rec = Rectangle::new(0.0, 0.0, target_tex.texture.width as f32, target_tex.texture.height as f32);
         let dest_rec = Rectangle::new(23.0, 10.0, 160.0, 120.0);
         let origin = Vector2::new(0.0, 0.0);
-        rl_draw_handle.draw_texture_pro(&target_tex, source_rec, dest_rec, origin, 0.0, Color::WHITE);
+        let level = (255 * ic_rl_platform.backlight as u32 / 100) as u8;
+        let tint = Color::new(level, level, level, 255);
+        rl_draw_handle.draw_texture_pro(&target_tex, source_rec, dest_rec, origin, 0.0, tint);
         let dest_rec_zoom = Rectangle::new(300.0, 10.0, RENDER_W as f32, RENDER_H as f32);
-        rl_draw_handle.draw_texture_pro(&target_tex, source_rec, dest_rec_zoom, origin, 0.0, Color::WHITE);
+        rl_draw_handle.draw_texture_pro(&target_tex, source_rec, dest_rec_zoom, origin, 0.0, tint);
+        rl_draw_handle.draw_text(
+            format!("Backlight {}% ({:?})", ic_rl_platform.backlight, ic_rl_platform.power_state).as_str(),
+            300, 260, 20, Color::WHITE);
+        rl_draw_handle.draw_text(
+            format!(
+                "Battery {}%{} (F9 charger, F10/F11 -/+5%)",
+                ic_rl_platform.battery_soc,
+                if ic_rl_platform.charging { " charging" } else { "" },
+            ).as_str(),
+            300, 284, 20, Color::WHITE);
          rl_draw_handle.draw_text(format!("What! {fps} FPS").as_str(),
                 12, 435, 24, Color::WHITE);
     }
+    // closing the window is the simulator's power off
+    icalc.save_state(ic_rl_platform.as_mut());
 }
\ No newline at end of file
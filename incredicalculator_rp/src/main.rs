@@ -3,12 +3,14 @@
 
 extern crate alloc;
 
-use core::sync::atomic::AtomicI32;
+use core::sync::atomic::{AtomicI32, AtomicU32, Ordering};
 use core::{cell::RefCell, fmt};
 
 use defmt::*;
 use embassy_embedded_hal::shared_bus::blocking::spi::SpiDeviceWithConfig;
 use embassy_executor::{Executor, Spawner};
+use embassy_futures::select::{Either, select};
+use embassy_futures::yield_now;
 use embassy_rp::gpio::{Input, Level, Output, Pull};
 use embassy_rp::multicore::{Stack, spawn_core1};
 use embassy_rp::spi;
@@ -20,15 +22,14 @@ use embassy_sync::channel::{Channel, DynamicSender};
 use embassy_time::{Delay, Instant};
 use embassy_time::Timer;
 use embedded_alloc::LlffHeap as Heap;
-use embedded_graphics::mono_font::MonoTextStyle;
-use embedded_graphics::mono_font::ascii::FONT_10X20;
 use embedded_graphics::pixelcolor::{Rgb565};
-use embedded_graphics::primitives::{PrimitiveStyle, PrimitiveStyleBuilder};
-use embedded_graphics::text::Text;
 use embedded_graphics::{prelude::*};
-use embedded_graphics_framebuf::FrameBuf;
+use incredicalculator_core::app::FrameRequest;
+use incredicalculator_core::font;
+use incredicalculator_core::framebuffer::FrameBuffer;
 use incredicalculator_core::input::{self, IcKey};
 use incredicalculator_core::platform::IcPlatform;
+use incredicalculator_core::power::PowerState;
 use incredicalculator_core::shell::IcShell;
 use glam::IVec2;
 use max170xx::Max17048;
@@ -61,6 +62,15 @@ static INPUT_BUFFER: Channel<CriticalSectionRawMutex, InputBufferEvent, 32> = Ch
 
 type BoardI2c = embassy_rp::i2c::I2c<'static, embassy_rp::peripherals::I2C0, embassy_rp::i2c::Blocking>;
 static BATTERY_SOC: AtomicI32 = AtomicI32::new(-1);
+// millivolts and hundredths of a percent per hour, i32::MIN until the gauge
+// has been read
+static BATTERY_MV: AtomicI32 = AtomicI32::new(i32::MIN);
+static BATTERY_RATE: AtomicI32 = AtomicI32::new(i32::MIN);
+// how often core1 scans the key matrix, slower while the screen is off
+static SCAN_INTERVAL_MS: AtomicU32 = AtomicU32::new(16);
+// Shift+Super held on their own this long reboot into the bootloader. The
+// macro chords press an F key while both are down, which restarts the wait.
+const BOOTLOADER_HOLD_MS: u64 = 3000;
 
 enum KeyMovement {
     Up,
@@ -73,46 +83,39 @@ struct InputBufferEvent {
 }
 
 pub struct IcRpPlatform {
-    pub canvas_data: &'static mut [Rgb565; PIXEL_COUNT]
+    pub frame: FrameBuffer<&'static mut [Rgb565; PIXEL_COUNT]>,
+    backlight: Pwm<'static>,
+    backlight2: Pwm<'static>,
 }
 
 impl IcRpPlatform {
-    pub fn new() -> IcRpPlatform {
+    pub fn new(backlight: Pwm<'static>, backlight2: Pwm<'static>) -> IcRpPlatform {
         IcRpPlatform {
-            canvas_data: unsafe { &mut *core::ptr::addr_of_mut!(CANVAS_DATA) }
+            frame: FrameBuffer::new(
+                unsafe { &mut *core::ptr::addr_of_mut!(CANVAS_DATA) },
+                RENDER_W,
+                RENDER_H,
+            ),
+            backlight,
+            backlight2,
         }
     }
 }
 
+fn backlight_config(level: u8) -> PwmConfig {
+    let mut config = PwmConfig::default();
+    config.top = 0xFFFF;
+    config.compare_b = (0xFFFF * level.min(100) as u32 / 100) as u16;
+    config
+}
+
 impl IcPlatform for IcRpPlatform {
     fn draw_line(&mut self, start: IVec2, end: IVec2, color: RGB8, width: u32) {
-        let mut fbuf = FrameBuf::new(&mut *self.canvas_data, RENDER_W as usize, RENDER_H as usize);
-        embedded_graphics::primitives::Line::new(
-            embedded_graphics::prelude::Point::new(start.x, start.y),
-            embedded_graphics::prelude::Point::new(end.x, end.y),
-        )   
-        .into_styled(PrimitiveStyle::with_stroke(rgbu8_to_rgb565(color), width))
-        .draw(&mut fbuf)
-        .unwrap();
+        self.frame.draw_line(start, end, color, width);
     }
 
     fn draw_rectangle(&mut self, start: IVec2, end: IVec2, stroke_color: RGB8, stroke_width: u32, fill_color: Option<RGB8>) {
-        let mut fbuf = FrameBuf::new(&mut *self.canvas_data, RENDER_W as usize, RENDER_H as usize);
-        let mut style_builder = PrimitiveStyleBuilder::new()
-            .stroke_color(rgbu8_to_rgb565(stroke_color))
-            .stroke_width(stroke_width)
-            .stroke_alignment(embedded_graphics::primitives::StrokeAlignment::Center);
-        if let Some(c) = fill_color {
-            style_builder = style_builder.fill_color(rgbu8_to_rgb565(c));
-        }
-        let style = style_builder.build();
-        embedded_graphics::primitives::Rectangle::with_corners(
-            embedded_graphics::prelude::Point::new(start.x, start.y),
-            embedded_graphics::prelude::Point::new(end.x, end.y),
-        )
-        .into_styled(style)
-        .draw(&mut fbuf)
-        .unwrap();
+        self.frame.draw_rectangle(start, end, stroke_color, stroke_width, fill_color);
     }
 
     fn draw_rectangle_rounded(
@@ -124,65 +127,43 @@ impl IcPlatform for IcRpPlatform {
         fill_color: Option<RGB8>,
         corner_radius: u32,
     ) {
-        let mut fbuf = FrameBuf::new(&mut *self.canvas_data, RENDER_W as usize, RENDER_H as usize);
-        let mut style_builder = PrimitiveStyleBuilder::new()
-            .stroke_color(rgbu8_to_rgb565(stroke_color))
-            .stroke_width(stroke_width)
-            .stroke_alignment(embedded_graphics::primitives::StrokeAlignment::Center);
-        if let Some(c) = fill_color {
-            style_builder = style_builder.fill_color(rgbu8_to_rgb565(c));
-        }
-        let style = style_builder.build();
-        embedded_graphics::primitives::RoundedRectangle::with_equal_corners(
-            embedded_graphics::primitives::Rectangle::with_corners(
-                embedded_graphics::prelude::Point::new(start.x, start.y),
-                embedded_graphics::prelude::Point::new(end.x, end.y),
-            ),
-            embedded_graphics::prelude::Size::new(corner_radius, corner_radius),
-        )
-        .into_styled(style)
-        .draw(&mut fbuf)
-        .unwrap();
+        self.frame.draw_rectangle_rounded(start, end, stroke_color, stroke_width, fill_color, corner_radius);
     }
 
     fn draw_triangle(&mut self, vertex1: IVec2, vertex2: IVec2, vertex3: IVec2, stroke_color: RGB8, stroke_width: u32, fill_color: Option<RGB8>) {
-        let mut fbuf = FrameBuf::new(&mut *self.canvas_data, RENDER_W as usize, RENDER_H as usize);
-        let mut style_builder = PrimitiveStyleBuilder::new()
-            .stroke_color(rgbu8_to_rgb565(stroke_color))
-            .stroke_width(stroke_width)
-            .stroke_alignment(embedded_graphics::primitives::StrokeAlignment::Center);
-        if let Some(c) = fill_color {
-            style_builder = style_builder.fill_color(rgbu8_to_rgb565(c));
-        }
-        let style = style_builder.build();
-        embedded_graphics::primitives::Triangle::new(
-            embedded_graphics::prelude::Point::new(vertex1.x, vertex1.y),
-            embedded_graphics::prelude::Point::new(vertex2.x, vertex2.y),
-            embedded_graphics::prelude::Point::new(vertex3.x, vertex3.y),
-        )
-        .into_styled(style).draw(&mut fbuf).unwrap();
-    }
-
-    fn draw_string(&mut self, text: &str, pos: IVec2, _size: u32, color: RGB8) {
-        let mut fbuf = FrameBuf::new(&mut *self.canvas_data, RENDER_W as usize, RENDER_H as usize);
-        
-        // using a BUILT-IN FONT!
-        let char_style = embedded_graphics::mono_font::MonoTextStyle::new(
-            &embedded_graphics::mono_font::ascii::FONT_10X20,
-            rgbu8_to_rgb565(color)
-        );
-        let text_style = embedded_graphics::text::TextStyleBuilder::new()
-        .alignment(embedded_graphics::text::Alignment::Left)
-        .baseline(embedded_graphics::text::Baseline::Top)
-        .build();
-        embedded_graphics::text::Text::with_text_style(
-            text,
-            embedded_graphics::prelude::Point::new(pos.x, pos.y),
-            char_style,
-            text_style,
-        )
-        .draw(&mut fbuf)
-        .unwrap();
+        self.frame.draw_triangle(vertex1, vertex2, vertex3, stroke_color, stroke_width, fill_color);
+    }
+
+    fn draw_circle(&mut self, center: IVec2, radius: u32, stroke_color: RGB8, stroke_width: u32, fill_color: Option<RGB8>) {
+        self.frame.draw_circle(center, radius, stroke_color, stroke_width, fill_color);
+    }
+
+    fn draw_ellipse(&mut self, center: IVec2, radii: IVec2, stroke_color: RGB8, stroke_width: u32, fill_color: Option<RGB8>) {
+        self.frame.draw_ellipse(center, radii, stroke_color, stroke_width, fill_color);
+    }
+
+    fn draw_arc(&mut self, center: IVec2, radius: u32, start_deg: f32, sweep_deg: f32, color: RGB8, width: u32) {
+        self.frame.draw_arc(center, radius, start_deg, sweep_deg, color, width);
+    }
+
+    fn draw_polyline(&mut self, points: &[IVec2], color: RGB8, width: u32) {
+        self.frame.draw_polyline(points, color, width);
+    }
+
+    fn draw_polygon(&mut self, points: &[IVec2], stroke_color: RGB8, stroke_width: u32, fill_color: Option<RGB8>) {
+        self.frame.draw_polygon(points, stroke_color, stroke_width, fill_color);
+    }
+
+    fn draw_pixel(&mut self, pos: IVec2, color: RGB8) {
+        self.frame.draw_pixel(pos, color);
+    }
+
+    fn draw_pixels(&mut self, pos: IVec2, width: u32, pixels: &[RGB8]) {
+        self.frame.draw_pixels(pos, width, pixels);
+    }
+
+    fn draw_string(&mut self, text: &str, pos: IVec2, size: u32, color: RGB8) {
+        self.frame.draw_string(text, pos, size, color);
     }
 
     fn draw_string_f(&mut self, arg: fmt::Arguments, pos: IVec2, size: u32, color: RGB8) {
@@ -191,7 +172,18 @@ impl IcPlatform for IcRpPlatform {
     }
 
     fn clear(&mut self, color: RGB8) {
-        self.canvas_data.fill(rgbu8_to_rgb565(color));
+        self.frame.clear(color);
+    }
+
+    fn set_backlight(&mut self, level: u8) {
+        let config = backlight_config(level);
+        self.backlight.set_config(&config);
+        self.backlight2.set_config(&config);
+    }
+
+    fn set_power_state(&mut self, state: PowerState) {
+        let interval = if state == PowerState::Blank { 50 } else { 16 };
+        SCAN_INTERVAL_MS.store(interval, Ordering::Relaxed);
     }
 
     fn log(&mut self, _arg: fmt::Arguments) {}
@@ -203,6 +195,21 @@ impl IcPlatform for IcRpPlatform {
     fn get_battery_soc(&self) -> i32 {
         BATTERY_SOC.load(core::sync::atomic::Ordering::Relaxed)
     }
+
+    // the gauge has no charger input, a rising charge is taken as charging
+    fn is_charging(&self) -> bool {
+        BATTERY_RATE.load(Ordering::Relaxed) > 0
+    }
+
+    fn battery_voltage(&self) -> Option<f32> {
+        let mv = BATTERY_MV.load(Ordering::Relaxed);
+        (mv != i32::MIN).then(|| mv as f32 / 1000.0)
+    }
+
+    fn battery_charge_rate(&self) -> Option<f32> {
+        let rate = BATTERY_RATE.load(Ordering::Relaxed);
+        (rate != i32::MIN).then(|| rate as f32 / 100.0)
+    }
 }
 
 const MATRIX_ROWS: usize = 5;
@@ -316,10 +323,13 @@ impl<'d> KeyMatrix<'d> {
     pub fn is_pressed(&self, key: IcKey) -> bool {
         self.prev_pressed[key as usize]
     }
-}
 
-fn rgbu8_to_rgb565(rgbu8_col: rgb::Rgb<u8>) -> Rgb565 {
-    Rgb565::new(rgbu8_col.r >> 3, rgbu8_col.g >> 2, rgbu8_col.b >> 3)
+    // keys are all down and nothing else is
+    pub fn only_pressed(&self, keys: &[IcKey]) -> bool {
+        (0..IcKey::COUNT)
+            .filter_map(Self::key_from_index)
+            .all(|key| self.is_pressed(key) == keys.contains(&key))
+    }
 }
 
 fn reboot_into_bootloader() {
@@ -444,15 +454,9 @@ async fn main(spawner: Spawner) {
     let _tft_unused_d6 = Output::new(p.PIN_39, Level::Low);
     let _tft_unused_d7 = Output::new(p.PIN_40, Level::Low);
 
-    // PWM backlight
-    let mut pwm_config = PwmConfig::default();
-    pwm_config.top = 0xFFFF;
-    pwm_config.compare_b = 0xFFFF/2;
-    let _backlight = Pwm::new_output_b(p.PWM_SLICE7, module_bl, pwm_config);
-    let mut pwm_config2 = PwmConfig::default();
-    pwm_config2.top = 0xFFFF;
-    pwm_config2.compare_b = 0xFFFF/2;
-    let _backlight2 = Pwm::new_output_b(p.PWM_SLICE8, bare_display_bl, pwm_config2);
+    // PWM backlight, the shell sets the level from its power policy
+    let backlight = Pwm::new_output_b(p.PWM_SLICE7, module_bl, backlight_config(50));
+    let backlight2 = Pwm::new_output_b(p.PWM_SLICE8, bare_display_bl, backlight_config(50));
 
     // create SPI
     let mut display_config = spi::Config::default();
@@ -513,23 +517,33 @@ async fn main(spawner: Spawner) {
         }
     );
 
-    let style = MonoTextStyle::new(&FONT_10X20, Rgb565::GREEN);
-    Text::new(
+    font::for_size(4).draw(
+        &mut display,
         "Hello embedded_graphics \n + incredicalculator",
         Point::new(20, 200),
-        style,
-    )
-    .draw(&mut display)
-    .unwrap();
+        Rgb565::GREEN,
+    );
     let mut icalc: IcShell = IcShell::new();
-    let mut ic_rp_platform = IcRpPlatform::new();
+    let mut ic_rp_platform = IcRpPlatform::new(backlight, backlight2);
     display.clear(Rgb565::CYAN).unwrap();
     let mut frame_counter: usize = 0;
     loop {
-        let first_key_event = INPUT_BUFFER.receive().await;
-        match first_key_event.movement {
-            KeyMovement::Up => icalc.key_up(first_key_event.key),
-            KeyMovement::Down => icalc.key_down(first_key_event.key),
+        // sleep until a key arrives or the active app wants its next frame
+        let first_key_event = match icalc.frame_request() {
+            FrameRequest::Continuous => {
+                yield_now().await;
+                INPUT_BUFFER.try_receive().ok()
+            }
+            FrameRequest::AfterMs(ms) => {
+                match select(INPUT_BUFFER.receive(), Timer::after_millis(ms as u64)).await {
+                    Either::First(event) => Some(event),
+                    Either::Second(_) => None,
+                }
+            }
+            FrameRequest::Idle => Some(INPUT_BUFFER.receive().await),
+        };
+        if let Some(event) = first_key_event {
+            apply_key_event(&mut icalc, event);
         }
         led.set_high();
         info!("Pre-update");
@@ -537,10 +551,7 @@ async fn main(spawner: Spawner) {
         led.set_low();
         info!("Post-update");
         while let Ok(event) = INPUT_BUFFER.try_receive() {
-            match event.movement {
-                KeyMovement::Up => icalc.key_up(event.key),
-                KeyMovement::Down => icalc.key_down(event.key),
-            }
+            apply_key_event(&mut icalc, event);
             led.set_high();
             info!("Pre-update");
             icalc.update(&mut ic_rp_platform);
@@ -569,13 +580,20 @@ async fn main(spawner: Spawner) {
         //     );
         // }
         frame_counter = frame_counter.wrapping_add(1);
-        display.fill_contiguous(
-            &embedded_graphics::primitives::Rectangle::new(
-                embedded_graphics::prelude::Point::new(0, 0),
-                embedded_graphics::prelude::Size::new(RENDER_W, RENDER_H)
-            ),
-            ic_rp_platform.canvas_data.iter().copied()
-        ).unwrap();
+        // only send the parts of the screen that changed since the last flush
+        for rect in ic_rp_platform.frame.take_dirty_rects() {
+            display.fill_contiguous(
+                &rect,
+                ic_rp_platform.frame.pixels_in(&rect)
+            ).unwrap();
+        }
+    }
+}
+
+fn apply_key_event(icalc: &mut IcShell, event: InputBufferEvent) {
+    match event.movement {
+        KeyMovement::Up => icalc.key_up(event.key),
+        KeyMovement::Down => icalc.key_down(event.key),
     }
 }
 
@@ -590,6 +608,12 @@ async fn battery_task(mut fuel_gauge: Max17048<BoardI2c>) {
         } else {
             warn!("Error getting battery soc from fuel gauge");
         }
+        if let Ok(v) = fuel_gauge.voltage() {
+            BATTERY_MV.store((v * 1000.0) as i32, Ordering::Relaxed);
+        }
+        if let Ok(rate) = fuel_gauge.charge_rate() {
+            BATTERY_RATE.store((rate * 100.0) as i32, Ordering::Relaxed);
+        }
         Timer::after_secs(10).await;
     }
 }
@@ -599,14 +623,20 @@ async fn inputs_core1_task(matrix_rows: [Output<'static>; 5], matrix_cols: [Inpu
     info!("Hello from the \"inputs core\"");
     let mut key_matrix = KeyMatrix::new(matrix_rows, matrix_cols);
     let input_buf_sender = INPUT_BUFFER.dyn_sender();
+    let mut bootloader_hold_since: Option<Instant> = None;
     loop {
         // todo: wait here for interrupt of one of the column lines (input gpios) changing states
         // so that this core can sleep while youre not pressing anything. 
         // then reducing the after_millis can be a good idea
         key_matrix.scan_and_send(input_buf_sender);
-        if key_matrix.is_pressed(IcKey::Super) && key_matrix.is_pressed(IcKey::Shift) {
-            reboot_into_bootloader();
+        if key_matrix.only_pressed(&[IcKey::Shift, IcKey::Super]) {
+            let since = *bootloader_hold_since.get_or_insert_with(Instant::now);
+            if since.elapsed().as_millis() >= BOOTLOADER_HOLD_MS {
+                reboot_into_bootloader();
+            }
+        } else {
+            bootloader_hold_since = None;
         }
-        Timer::after_millis(16).await;
+        Timer::after_millis(SCAN_INTERVAL_MS.load(Ordering::Relaxed) as u64).await;
     }
 }